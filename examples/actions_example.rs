@@ -160,10 +160,7 @@ fn main() {
     
     // Add coins and try again
     context.coins = 15;
-    state = MachineStateImpl {
-        value: state.value().clone(),
-        context: context.clone(),
-    };
+    state = MachineStateImpl::new(state.value().clone(), context.clone());
     
     println!("\n--- Testing Heal Transition (With Coins) ---");
     let heal_result = machine.transition(&state, GameEvent::Heal);
@@ -185,10 +182,7 @@ fn main() {
     
     // Level up and try again
     context.player_level = 8;
-    state = MachineStateImpl {
-        value: state.value().clone(),
-        context: context.clone(),
-    };
+    state = MachineStateImpl::new(state.value().clone(), context.clone());
     
     println!("\n--- Testing Special Move (High Level) ---");
     let special_result = machine.transition(&state, GameEvent::SpecialMove);
@@ -93,10 +93,7 @@ fn main() {
     
     // Add coins and try again
     context.coins = 10;
-    state = MachineStateImpl {
-        value: state.value().clone(),
-        context: context.clone(),
-    };
+    state = MachineStateImpl::new(state.value().clone(), context.clone());
     
     println!("\n--- Testing Heal Transition (With Coins) ---");
     let heal_result = machine.transition(&state, GameEvent::Heal);
@@ -109,10 +106,7 @@ fn main() {
     
     // Level up and try again
     context.player_level = 8;
-    state = MachineStateImpl {
-        value: state.value().clone(),
-        context: context.clone(),
-    };
+    state = MachineStateImpl::new(state.value().clone(), context.clone());
     
     println!("\n--- Testing Special Move (High Level) ---");
     let special_result = machine.transition(&state, GameEvent::SpecialMove);
@@ -142,10 +136,7 @@ fn main() {
     // Test going offline (should block attacks)
     println!("\n--- Testing Offline State ---");
     context.is_online = false;
-    state = MachineStateImpl {
-        value: state.value().clone(),
-        context: context.clone(),
-    };
+    state = MachineStateImpl::new(state.value().clone(), context.clone());
     
     let offline_attack = machine.transition(&state, GameEvent::Attack);
     println!("Attack while offline: {:?}", offline_attack.value());
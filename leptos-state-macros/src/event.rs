@@ -0,0 +1,86 @@
+//! `#[derive(MachineEvent)]`
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields};
+
+/// Implement `Event::event_type` with the variant name, or the struct name
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let event_type = ident.to_string();
+                let pattern = match variant.fields {
+                    Fields::Named(_) => quote!(Self::#ident { .. }),
+                    Fields::Unnamed(_) => quote!(Self::#ident(..)),
+                    Fields::Unit => quote!(Self::#ident),
+                };
+                quote!(#pattern => #event_type)
+            });
+            quote!(match self { #(#arms,)* })
+        }
+        Data::Struct(_) => {
+            let event_type = name.to_string();
+            quote!(#event_type)
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "MachineEvent cannot be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::leptos_state::machine::Event for #name #ty_generics #where_clause {
+            fn event_type(&self) -> &str {
+                #body
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn enum_variants_report_their_names() {
+        let expanded = expand(parse_quote! {
+            enum FormEvent {
+                Reset,
+                Edit(String),
+                Submit { draft: bool },
+            }
+        })
+        .unwrap()
+        .to_string();
+
+        assert!(expanded.contains("Self :: Reset => \"Reset\""));
+        assert!(expanded.contains("Self :: Edit (..) => \"Edit\""));
+        assert!(expanded.contains("Self :: Submit { .. } => \"Submit\""));
+    }
+
+    #[test]
+    fn structs_report_their_name() {
+        let expanded = expand(parse_quote!(
+            struct Tick<T>(T);
+        ))
+        .unwrap()
+        .to_string();
+
+        assert!(expanded.contains("for Tick < T >"));
+        assert!(expanded.contains("\"Tick\""));
+    }
+
+    #[test]
+    fn unions_are_rejected() {
+        let error = expand(parse_quote!(union Bits { a: u8, b: i8 })).unwrap_err();
+        assert!(error.to_string().contains("unions"));
+    }
+}
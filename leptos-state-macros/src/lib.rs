@@ -0,0 +1,78 @@
+//! # Leptos State Macros
+//!
+//! Derive and attribute macros for [`leptos-state`](https://docs.rs/leptos-state)
+//! state machines. Use them through the `leptos_state` re-exports.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemEnum};
+
+mod event;
+mod state_machine;
+
+/// Implement `leptos_state::machine::Event` for an enum or struct
+///
+/// `event_type` reports the variant name for enums, whatever the payload, and the
+/// type name for structs.
+///
+/// ```rust,ignore
+/// #[derive(Debug, Clone, PartialEq, MachineEvent)]
+/// enum FormEvent {
+///     Edit(String),
+///     Submit,
+/// }
+///
+/// assert_eq!(FormEvent::Edit("name".into()).event_type(), "Edit");
+/// ```
+#[proc_macro_derive(MachineEvent)]
+pub fn derive_machine_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    event::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Turn an enum of states into a state machine
+///
+/// Every unit variant becomes a state of a `Machine` built once on first use.
+/// Transitions name their target variant, so a typo is a compile error rather
+/// than a state the machine can never reach.
+///
+/// Attribute arguments:
+/// - `event = Type` (required): the machine's event type
+/// - `context = Type`: the machine's context, `()` by default
+/// - `machine = Name`: the generated `StateMachine` type, `{Enum}Machine` by default
+///
+/// Variant attributes:
+/// - `#[initial]`: the initial state, the first variant by default
+/// - `#[final_state]`: the machine is done once it reaches this state
+/// - `#[entry(f)]`, `#[exit(f)]`: `Fn(&mut Context, &Event)` actions
+/// - `#[on(event => Target)]`: a transition, optionally followed by
+///   `guard = Fn(&Context, &Event) -> bool` and `action = Fn(&mut Context, &Event)`
+///
+/// The enum derives whichever of `Debug`, `Clone`, `Copy`, `PartialEq`, `Eq` and
+/// `Hash` it does not derive already, and gains `ALL`, `id`, `from_id`, `of` (the
+/// active state of a `MachineStateImpl`) and `machine`. Put your own `#[derive]`
+/// below `#[state_machine]`: derives above it are expanded before the macro sees them.
+///
+/// ```rust,ignore
+/// #[state_machine(context = LightContext, event = LightEvent)]
+/// pub enum Light {
+///     #[initial]
+///     #[on(LightEvent::Timer => Yellow)]
+///     Green,
+///     #[on(LightEvent::Timer => Red)]
+///     Yellow,
+///     #[on(LightEvent::Timer => Green, action = count_cycle)]
+///     Red,
+/// }
+///
+/// let handle = use_machine::<LightMachine>();
+/// let light = Light::of(&handle.state.get());
+/// ```
+#[proc_macro_attribute]
+pub fn state_machine(args: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemEnum);
+    state_machine::expand(args.into(), item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,469 @@
+//! `#[state_machine]`
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::{parse_quote, Attribute, Expr, Fields, Ident, ItemEnum, Token, Type};
+
+/// Arguments of the `#[state_machine(...)]` attribute
+#[derive(Default)]
+struct MachineArgs {
+    context: Option<Type>,
+    event: Option<Type>,
+    machine: Option<Ident>,
+}
+
+impl MachineArgs {
+    fn parse(args: TokenStream) -> syn::Result<Self> {
+        let mut parsed = Self::default();
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("context") {
+                parsed.context = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("event") {
+                parsed.event = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("machine") {
+                parsed.machine = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `context`, `event` or `machine`"));
+            }
+            Ok(())
+        });
+        parser.parse2(args)?;
+        Ok(parsed)
+    }
+}
+
+/// State declared by an enum variant
+struct StateDef {
+    ident: Ident,
+    initial: bool,
+    is_final: bool,
+    entry: Vec<Expr>,
+    exit: Vec<Expr>,
+    transitions: Vec<TransitionDef>,
+}
+
+/// `#[on(event => Target, guard = .., action = ..)]`
+struct TransitionDef {
+    event: Expr,
+    target: Ident,
+    guard: Option<Expr>,
+    action: Option<Expr>,
+}
+
+impl Parse for TransitionDef {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let event = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let target = input.parse()?;
+
+        let mut transition = Self {
+            event,
+            target,
+            guard: None,
+            action: None,
+        };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let value = input.parse()?;
+            match key.to_string().as_str() {
+                "guard" => transition.guard = Some(value),
+                "action" => transition.action = Some(value),
+                _ => return Err(syn::Error::new(key.span(), "expected `guard` or `action`")),
+            }
+        }
+        Ok(transition)
+    }
+}
+
+/// Attributes of a variant read by the macro; everything else is kept on the variant
+const HELPER_ATTRIBUTES: [&str; 5] = ["initial", "final_state", "entry", "exit", "on"];
+
+fn is_helper(attr: &Attribute) -> bool {
+    HELPER_ATTRIBUTES
+        .iter()
+        .any(|helper| attr.path().is_ident(helper))
+}
+
+/// Traits the state enum needs, derived unless the enum already derives them
+const STATE_DERIVES: [&str; 6] = ["Debug", "Clone", "Copy", "PartialEq", "Eq", "Hash"];
+
+/// `STATE_DERIVES` missing from the `#[derive]` attributes in `attrs`
+fn missing_derives(attrs: &[Attribute]) -> syn::Result<Vec<Ident>> {
+    let mut derived = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("derive")) {
+        let paths = attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)?;
+        derived.extend(
+            paths
+                .into_iter()
+                .filter_map(|path| path.segments.last().map(|segment| segment.ident.clone())),
+        );
+    }
+
+    Ok(STATE_DERIVES
+        .iter()
+        .filter(|name| !derived.iter().any(|ident| ident == *name))
+        .map(|name| format_ident!("{}", name))
+        .collect())
+}
+
+/// Generate the state enum, its machine and a `StateMachine` implementation
+pub fn expand(args: TokenStream, mut item: ItemEnum) -> syn::Result<TokenStream> {
+    let args = MachineArgs::parse(args)?;
+    let Some(event) = args.event else {
+        return Err(syn::Error::new_spanned(
+            &item.ident,
+            "missing the event type, as in `#[state_machine(event = MyEvent)]`",
+        ));
+    };
+    let context = args.context.unwrap_or_else(|| parse_quote!(()));
+
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item.generics,
+            "state enums cannot be generic",
+        ));
+    }
+    if item.variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item.ident,
+            "a state machine needs at least one state",
+        ));
+    }
+
+    let mut states = Vec::new();
+    for variant in &mut item.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                &variant.fields,
+                "states cannot carry data, keep it in the context",
+            ));
+        }
+
+        let mut state = StateDef {
+            ident: variant.ident.clone(),
+            initial: false,
+            is_final: false,
+            entry: Vec::new(),
+            exit: Vec::new(),
+            transitions: Vec::new(),
+        };
+        for attr in variant.attrs.iter().filter(|attr| is_helper(attr)) {
+            let path = attr.path();
+            if path.is_ident("initial") {
+                attr.meta.require_path_only()?;
+                state.initial = true;
+            } else if path.is_ident("final_state") {
+                attr.meta.require_path_only()?;
+                state.is_final = true;
+            } else if path.is_ident("entry") {
+                state.entry.push(attr.parse_args()?);
+            } else if path.is_ident("exit") {
+                state.exit.push(attr.parse_args()?);
+            } else {
+                state.transitions.push(attr.parse_args()?);
+            }
+        }
+        variant.attrs.retain(|attr| !is_helper(attr));
+        states.push(state);
+    }
+
+    // Unknown targets fail here rather than when the machine is built at runtime
+    for transition in states.iter().flat_map(|state| &state.transitions) {
+        if !states.iter().any(|state| state.ident == transition.target) {
+            return Err(syn::Error::new_spanned(
+                &transition.target,
+                format!("unknown state `{}`", transition.target),
+            ));
+        }
+    }
+
+    let initial = match states.iter().filter(|state| state.initial).count() {
+        0 => &states[0],
+        1 => states.iter().find(|state| state.initial).unwrap(),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &item.ident,
+                "only one state can be marked `#[initial]`",
+            ))
+        }
+    };
+    let initial = initial.ident.to_string();
+
+    let name = &item.ident;
+    let vis = &item.vis;
+    let machine_name = args
+        .machine
+        .unwrap_or_else(|| format_ident!("{}Machine", name));
+    let count = states.len();
+    let variants: Vec<&Ident> = states.iter().map(|state| &state.ident).collect();
+    let ids: Vec<String> = variants.iter().map(|variant| variant.to_string()).collect();
+    let definitions = states.iter().map(state_definition);
+    let missing = missing_derives(&item.attrs)?;
+    let derives = (!missing.is_empty()).then(|| quote!(#[derive(#(#missing),*)]));
+    let machine_doc = format!("`StateMachine` running the states of [`{}`]", name);
+
+    Ok(quote! {
+        #derives
+        #item
+
+        impl #name {
+            /// Every state, in declaration order
+            pub const ALL: [Self; #count] = [#(Self::#variants),*];
+
+            /// Id of the state in the generated machine
+            pub fn id(self) -> &'static str {
+                match self {
+                    #(Self::#variants => #ids,)*
+                }
+            }
+
+            /// State with the given id
+            pub fn from_id(id: &str) -> ::std::option::Option<Self> {
+                match id {
+                    #(#ids => ::std::option::Option::Some(Self::#variants),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            /// Active state of a machine state
+            pub fn of(
+                state: &::leptos_state::machine::MachineStateImpl<#context>,
+            ) -> ::std::option::Option<Self> {
+                match &state.value {
+                    ::leptos_state::machine::states::StateValue::Simple(id) => Self::from_id(id),
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            /// Machine built from the state definitions
+            pub fn machine() -> &'static ::leptos_state::machine::Machine<#context, #event, #context> {
+                static MACHINE: ::std::sync::OnceLock<
+                    ::leptos_state::machine::Machine<#context, #event, #context>,
+                > = ::std::sync::OnceLock::new();
+
+                MACHINE.get_or_init(|| {
+                    ::leptos_state::machine::MachineBuilder::<#context, #event>::new()
+                        #(#definitions)*
+                        .initial(#initial)
+                        .build()
+                })
+            }
+        }
+
+        impl ::std::fmt::Display for #name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.id())
+            }
+        }
+
+        #[doc = #machine_doc]
+        #vis struct #machine_name;
+
+        impl ::leptos_state::machine::StateMachine for #machine_name {
+            type Context = #context;
+            type Event = #event;
+            type State = ::leptos_state::machine::MachineStateImpl<#context>;
+
+            fn initial() -> Self::State {
+                #name::machine().initial_state()
+            }
+
+            fn transition(state: &Self::State, event: Self::Event) -> Self::State {
+                #name::machine().transition(state, event)
+            }
+
+            fn is_done(state: &Self::State) -> bool {
+                #name::machine().is_done(state)
+            }
+
+            fn can(state: &Self::State, event: &Self::Event) -> bool {
+                #name::machine().can(state, event)
+            }
+
+            fn enabled_events(state: &Self::State) -> ::std::vec::Vec<Self::Event> {
+                #name::machine().enabled_events(state)
+            }
+
+            fn runtime() -> ::std::option::Option<
+                ::std::boxed::Box<dyn ::leptos_state::machine::MachineRuntime<Self::State, Self::Event>>,
+            > {
+                ::std::option::Option::Some(::std::boxed::Box::new(
+                    ::leptos_state::machine::TimedMachine::new(
+                        #name::machine().clone(),
+                        ::leptos_state::machine::default_scheduler(),
+                    ),
+                ))
+            }
+        }
+    })
+}
+
+/// Builder calls declaring one state
+fn state_definition(state: &StateDef) -> TokenStream {
+    let id = state.ident.to_string();
+    let final_state = state.is_final.then(|| quote!(.final_state()));
+    let entry = &state.entry;
+    let exit = &state.exit;
+    let transitions = state.transitions.iter().map(|transition| {
+        let event = &transition.event;
+        let target = transition.target.to_string();
+        let guard = transition
+            .guard
+            .as_ref()
+            .map(|guard| quote!(.guard_fn(#guard)));
+        let action = transition
+            .action
+            .as_ref()
+            .map(|action| quote!(.action(::leptos_state::machine::FunctionAction::new(#action))));
+        quote!(.on(#event, #target) #guard #action)
+    });
+
+    quote! {
+        .state(#id)
+        #final_state
+        #(.on_entry_fn(#entry))*
+        #(.on_exit_fn(#exit))*
+        #(#transitions)*
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_str(args: TokenStream, item: ItemEnum) -> Result<String, String> {
+        expand(args, item)
+            .map(|tokens| tokens.to_string())
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn generates_the_machine_and_strips_helper_attributes() {
+        let expanded = expand_str(
+            quote!(context = LightContext, event = LightEvent),
+            parse_quote! {
+                pub enum Light {
+                    /// Cars may go
+                    #[on(LightEvent::Timer => Yellow, guard = has_cars)]
+                    Green,
+                    #[on(LightEvent::Timer => Red, action = count_cycle)]
+                    Yellow,
+                    #[initial]
+                    #[entry(stop_cars)]
+                    #[on(LightEvent::Timer => Green)]
+                    #[on(LightEvent::PowerOff => Off)]
+                    Red,
+                    #[final_state]
+                    Off,
+                }
+            },
+        )
+        .unwrap();
+
+        assert!(expanded.contains("pub struct LightMachine"));
+        assert!(expanded.contains("pub const ALL : [Self ; 4usize]"));
+        assert!(expanded.contains(
+            ". state (\"Green\") . on (LightEvent :: Timer , \"Yellow\") . guard_fn (has_cars)"
+        ));
+        assert!(expanded.contains(". state (\"Red\") . on_entry_fn (stop_cars)"));
+        assert!(expanded.contains(". state (\"Off\") . final_state ()"));
+        assert!(expanded.contains(". initial (\"Red\")"));
+        assert!(expanded.contains("# [doc = r\" Cars may go\"]"));
+        assert!(!expanded.contains("# [on"));
+        assert!(!expanded.contains("# [initial]"));
+    }
+
+    #[test]
+    fn machine_name_and_context_have_defaults() {
+        let expanded = expand_str(
+            quote!(event = DoorEvent, machine = Door),
+            parse_quote!(
+                enum DoorState {
+                    Open,
+                    Closed,
+                }
+            ),
+        )
+        .unwrap();
+
+        assert!(expanded.contains("struct Door ;"));
+        assert!(expanded.contains("type Context = ()"));
+        assert!(expanded.contains(". initial (\"Open\")"));
+    }
+
+    #[test]
+    fn unknown_targets_are_rejected() {
+        let error = expand_str(
+            quote!(event = LightEvent),
+            parse_quote! {
+                enum Light {
+                    #[on(LightEvent::Timer => Yelow)]
+                    Green,
+                    Yellow,
+                }
+            },
+        )
+        .unwrap_err();
+
+        assert_eq!(error, "unknown state `Yelow`");
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let missing_event = expand_str(
+            quote!(),
+            parse_quote!(
+                enum Light {
+                    Green,
+                }
+            ),
+        );
+        assert!(missing_event
+            .unwrap_err()
+            .contains("missing the event type"));
+
+        let data = expand_str(
+            quote!(event = E),
+            parse_quote!(
+                enum Light {
+                    Green(u8),
+                }
+            ),
+        );
+        assert!(data.unwrap_err().contains("cannot carry data"));
+
+        let two_initial = expand_str(
+            quote!(event = E),
+            parse_quote!(
+                enum Light {
+                    #[initial]
+                    Green,
+                    #[initial]
+                    Red,
+                }
+            ),
+        );
+        assert!(two_initial.unwrap_err().contains("only one state"));
+
+        let unknown_option = expand_str(
+            quote!(event = E),
+            parse_quote!(
+                enum Light {
+                    #[on(E::Go => Green, delay = 5)]
+                    Green,
+                }
+            ),
+        );
+        assert!(unknown_option
+            .unwrap_err()
+            .contains("expected `guard` or `action`"));
+    }
+}
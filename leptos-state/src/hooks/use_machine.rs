@@ -1,20 +1,44 @@
 use crate::machine::states::StateValue;
 use crate::machine::*;
 use leptos::prelude::*;
+use std::sync::{Arc, Mutex};
 
 /// Hook to interact with state machines in Leptos components
+///
+/// Machines with a [`runtime`](StateMachine::runtime) run their delayed transitions
+/// and invoked services until the component is cleaned up.
 pub fn use_machine<M: StateMachine>() -> MachineHandle<M> {
-    let (state, set_state) = signal(M::initial());
-
-    let send = Callback::new(move |event: M::Event| {
-        set_state.update(|s| *s = M::transition(s, event));
-    });
+    let (state, send) = match M::runtime() {
+        Some(runtime) => {
+            let runtime: Arc<dyn MachineRuntime<M::State, M::Event>> = Arc::from(runtime);
+            let (state, set_state) = signal(runtime.state());
+            runtime.subscribe(Box::new(move |next: &M::State| set_state.set(next.clone())));
+
+            let stopped = runtime.clone();
+            on_cleanup(move || stopped.stop());
+            let send = Callback::new(move |event: M::Event| runtime.send(event));
+            (state, send)
+        }
+        None => {
+            let (state, set_state) = signal(M::initial());
+            let send = Callback::new(move |event: M::Event| {
+                set_state.update(|s| *s = M::transition(s, event));
+            });
+            (state, send)
+        }
+    };
 
     MachineHandle {
         state,
         send,
         context: Memo::new(move |_| state.get().context().clone()),
         value: Memo::new(move |_| state.get().value().clone()),
+        done: Memo::new(move |_| M::is_done(&state.get())),
+        // Events need not be comparable, so the list is recomputed on every change
+        enabled_events: Memo::new_with_compare(
+            move |_| M::enabled_events(&state.get()),
+            |_, _| true,
+        ),
     }
 }
 
@@ -48,6 +72,10 @@ pub struct MachineHandle<M: StateMachine> {
     pub send: Callback<M::Event>,
     pub context: Memo<M::Context>,
     pub value: Memo<StateValue>,
+    /// Whether the machine has reached a final state
+    pub done: Memo<bool>,
+    /// Events the current state can take a transition for
+    pub enabled_events: Memo<Vec<M::Event>>,
 }
 
 impl<M: StateMachine> MachineHandle<M> {
@@ -66,11 +94,32 @@ impl<M: StateMachine> MachineHandle<M> {
         self.context.get()
     }
 
+    /// Check if the machine has reached a final state
+    pub fn is_done(&self) -> bool {
+        self.done.get()
+    }
+
     /// Check if a transition is possible from current state
-    pub fn can(&self, _event: M::Event) -> bool {
-        // This would need access to the machine definition
-        // For now, return true as a placeholder
-        true
+    pub fn can(&self, event: M::Event) -> bool {
+        M::can(&self.state.get(), &event)
+    }
+
+    /// Create a reactive memo for whether `event` can be sent
+    ///
+    /// Useful to disable buttons whose event the machine would ignore.
+    pub fn create_can(&self, event: M::Event) -> Memo<bool> {
+        let state = self.state;
+        Memo::new(move |_| M::can(&state.get(), &event))
+    }
+
+    /// Create a reactive memo for whether any active state is tagged with `tag`
+    ///
+    /// Lets components show a spinner for every `"loading"` state without
+    /// listing the states by name.
+    pub fn has_tag(&self, tag: &str) -> Memo<bool> {
+        let state = self.state;
+        let tag = tag.to_string();
+        Memo::new(move |_| state.get().has_tag(&tag))
     }
 
     /// Send an event to the machine
@@ -192,6 +241,8 @@ impl<M: StateMachine> MachineHistory<M> {
 }
 
 /// Hook for parallel machine management
+///
+/// Limited to two machines; use [`use_actor_system`] for any number of them.
 pub fn use_parallel_machines<M1, M2>(
     machine1: MachineHandle<M1>,
     machine2: MachineHandle<M2>,
@@ -230,6 +281,83 @@ impl<M1: StateMachine, M2: StateMachine> ParallelMachineHandle<M1, M2> {
     }
 }
 
+/// Hook owning an actor system, for any number of independent machines
+///
+/// Reads through the handle are reactive: they re-run whenever an event has been
+/// handled by the system.
+pub fn use_actor_system() -> ActorSystemHandle {
+    ActorSystemHandle {
+        system: Arc::new(Mutex::new(ActorSystem::new())),
+        version: RwSignal::new(0),
+    }
+}
+
+/// Handle for spawning machines into an actor system and messaging them
+#[derive(Clone)]
+pub struct ActorSystemHandle {
+    system: Arc<Mutex<ActorSystem>>,
+    version: RwSignal<u64>,
+}
+
+impl ActorSystemHandle {
+    /// Start a top-level actor running `machine`
+    pub fn spawn<C, E>(&self, id: impl Into<String>, machine: Machine<C, E, C>) -> ActorRef<C, E>
+    where
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    {
+        self.spawn_with_context(id, machine, C::default())
+    }
+
+    /// Start a top-level actor running `machine` with `context`
+    pub fn spawn_with_context<C, E>(
+        &self,
+        id: impl Into<String>,
+        machine: Machine<C, E, C>,
+        context: C,
+    ) -> ActorRef<C, E>
+    where
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    {
+        let actor = self
+            .system
+            .lock()
+            .unwrap()
+            .spawn_with_context(id, machine, context);
+        self.version.update(|version| *version += 1);
+        actor
+    }
+
+    /// Send an event to an actor and handle everything it causes
+    ///
+    /// Fails like [`ActorSystem::send`] when actors keep messaging each other.
+    pub fn send<C, E: std::any::Any + PartialEq + Send + Sync>(
+        &self,
+        actor: &ActorRef<C, E>,
+        event: E,
+    ) -> MachineResult<()> {
+        let delivered = self.system.lock().unwrap().send(actor, event);
+        self.version.update(|version| *version += 1);
+        delivered
+    }
+
+    /// Get the current state of an actor
+    pub fn state<C: Clone + Send + Sync + 'static, E>(
+        &self,
+        actor: &ActorRef<C, E>,
+    ) -> Option<MachineStateImpl<C>> {
+        self.version.track();
+        self.system.lock().unwrap().state(actor)
+    }
+
+    /// Stop an actor and all of its children
+    pub fn stop(&self, id: &str) {
+        self.system.lock().unwrap().stop(id);
+        self.version.update(|version| *version += 1);
+    }
+}
+
 /// Hook for machine composition (parent-child relationships)
 pub fn use_composed_machine<Parent, Child>(
     parent: MachineHandle<Parent>,
@@ -279,6 +407,14 @@ mod tests {
         fn transition(_state: &Self::State, _event: Self::Event) -> Self::State {
             MachineStateImpl::default()
         }
+
+        fn can(_state: &Self::State, _event: &Self::Event) -> bool {
+            false
+        }
+
+        fn enabled_events(_state: &Self::State) -> Vec<Self::Event> {
+            Vec::new()
+        }
     }
 
     #[test]
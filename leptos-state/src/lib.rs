@@ -70,6 +70,9 @@ pub use store::{
 };
 // Machine types
 pub use machine::{Machine, MachineBuilder, MachineState, StateMachine};
+// Macros
+#[cfg(feature = "macros")]
+pub use leptos_state_macros::{state_machine, MachineEvent};
 // Hook types
 pub use hooks::{
     use_machine, use_machine_history, use_machine_with_instance, use_store, use_store_with_actions,
@@ -0,0 +1,415 @@
+//! Execution context handed to actions, for raising and sending follow-up events
+
+use super::*;
+use crate::machine::action_control::CircuitState;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Event whose type is only known to its receiver
+///
+/// Clones share the same event. Events compare equal when they have the same type
+/// and equal values.
+#[derive(Clone)]
+pub struct AnyEvent {
+    event: Arc<dyn Any + Send + Sync>,
+    type_name: &'static str,
+    /// Compares two events of the type `event` was created with
+    eq: fn(&dyn Any, &dyn Any) -> bool,
+}
+
+impl AnyEvent {
+    pub fn new<T: Any + PartialEq + Send + Sync>(event: T) -> Self {
+        Self {
+            event: Arc::new(event),
+            type_name: std::any::type_name::<T>(),
+            eq: |a, b| match (a.downcast_ref::<T>(), b.downcast_ref::<T>()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+
+    /// Get the event if it is a `T`
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.event.downcast_ref()
+    }
+
+    /// Name of the event's type
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl std::fmt::Debug for AnyEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AnyEvent").field(&self.type_name).finish()
+    }
+}
+
+impl PartialEq for AnyEvent {
+    fn eq(&self, other: &Self) -> bool {
+        (self.eq)(self.event.as_ref(), other.event.as_ref())
+    }
+}
+
+/// Event an action sent to another machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentEvent {
+    /// Id of the receiving machine
+    pub target: String,
+    pub event: AnyEvent,
+}
+
+/// Action state kept by a runtime instead of the shared machine definition
+///
+/// Each `TimedMachine` and actor owns its own, so machines built from the same
+/// definition never trip each other's circuit breakers. Clones share the state.
+#[derive(Clone, Default)]
+pub struct ActionRuntime {
+    /// Circuit of every circuit breaker, by the id its clones share
+    circuits: Arc<Mutex<HashMap<u64, CircuitState>>>,
+}
+
+impl ActionRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` on the circuit of the breaker with the id `breaker`
+    pub(crate) fn with_circuit<T>(
+        &self,
+        breaker: u64,
+        f: impl FnOnce(&mut CircuitState) -> T,
+    ) -> T {
+        f(self.circuits.lock().unwrap().entry(breaker).or_default())
+    }
+}
+
+impl std::fmt::Debug for ActionRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActionRuntime").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for ActionRuntime {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.circuits, &other.circuits)
+    }
+}
+
+/// How a runtime delivers an event to a machine's actions
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Delivery {
+    /// Id of the actor the machine runs as, scoping the ids of the actors it spawns
+    pub(crate) actor: Option<String>,
+    /// Which delivery of the event this is, counting from 1
+    pub(crate) attempt: u32,
+    /// Whether the runtime delivers the event again when an action asks for a retry
+    pub(crate) retries: bool,
+    /// Action state kept between deliveries; without one every step starts afresh
+    pub(crate) runtime: Option<ActionRuntime>,
+}
+
+impl Default for Delivery {
+    fn default() -> Self {
+        Self {
+            actor: None,
+            attempt: 1,
+            retries: false,
+            runtime: None,
+        }
+    }
+}
+
+/// What an action can do besides changing the context
+///
+/// Raised events are processed by the same machine once the current event has
+/// been fully handled, before `Machine::step` returns. Sent events and spawned
+/// actors are collected in `TransitionOutcome::sent` and
+/// `TransitionOutcome::spawned` for the runtime to deliver and start.
+pub struct ActionContext<E> {
+    raised: Vec<E>,
+    sent: Vec<SentEvent>,
+    spawned: Vec<SpawnedActor>,
+    /// Id of the actor running the machine, if any
+    actor: Option<String>,
+    attempt: u32,
+    retries: bool,
+    runtime: Option<ActionRuntime>,
+}
+
+impl<E> ActionContext<E> {
+    pub fn new() -> Self {
+        Self::for_delivery(&Delivery::default())
+    }
+
+    /// Create a context for actions handling an event delivered as `delivery`
+    pub(crate) fn for_delivery(delivery: &Delivery) -> Self {
+        Self {
+            raised: Vec::new(),
+            sent: Vec::new(),
+            spawned: Vec::new(),
+            actor: delivery.actor.clone(),
+            attempt: delivery.attempt,
+            retries: delivery.retries,
+            runtime: delivery.runtime.clone(),
+        }
+    }
+
+    /// Which delivery of the current event this is, counting from 1
+    ///
+    /// `TimedMachine` counts up each time it sends an event again after an action
+    /// asked for a retry. A bare `Machine::step` is always the first attempt; use
+    /// `Machine::try_step_attempt` to deliver a later one.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Whether the runtime sends the event again when an action asks for a retry
+    ///
+    /// Only `TimedMachine` and `Machine::try_step_attempt` do; everywhere else a
+    /// failure is final.
+    pub fn retries(&self) -> bool {
+        self.retries
+    }
+
+    /// Action state kept by the runtime delivering the event, if it keeps any
+    ///
+    /// A bare `Machine::step` has none; actions then keep their state themselves.
+    pub fn runtime(&self) -> Option<&ActionRuntime> {
+        self.runtime.as_ref()
+    }
+
+    /// Queue `event` for this machine
+    pub fn raise(&mut self, event: E) {
+        self.raised.push(event);
+    }
+
+    /// Send `event` to the machine with the id `machine_id`
+    pub fn send_to<T: Any + PartialEq + Send + Sync>(
+        &mut self,
+        machine_id: impl Into<String>,
+        event: T,
+    ) {
+        self.sent.push(SentEvent {
+            target: machine_id.into(),
+            event: AnyEvent::new(event),
+        });
+    }
+
+    /// Send `event` to a spawned actor
+    pub fn send<C2, E2: Any + PartialEq + Send + Sync>(
+        &mut self,
+        actor: &ActorRef<C2, E2>,
+        event: E2,
+    ) {
+        self.send_to(actor.id(), event);
+    }
+
+    /// Send `event` to the actor that spawned this machine
+    pub fn send_parent<T: Any + PartialEq + Send + Sync>(&mut self, event: T) {
+        self.send_to(PARENT_ACTOR, event);
+    }
+
+    /// Spawn a child actor running `machine`
+    ///
+    /// Keep the returned reference in the context to send events to the child. Once
+    /// the child reaches a final state this machine receives `E::from(ActorDone)`.
+    /// Inside an actor system the child's id is scoped under this machine's actor as
+    /// `parent/id`, so children of different actors never clash.
+    pub fn spawn<C2, E2>(
+        &mut self,
+        id: impl Into<String>,
+        machine: Machine<C2, E2, C2>,
+    ) -> ActorRef<C2, E2>
+    where
+        C2: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E2: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+        E: From<ActorDone> + PartialEq + Send + Sync + 'static,
+    {
+        self.spawn_with_context(id, machine, C2::default())
+    }
+
+    /// Spawn a child actor running `machine` with `context`, like `spawn`
+    pub fn spawn_with_context<C2, E2>(
+        &mut self,
+        id: impl Into<String>,
+        machine: Machine<C2, E2, C2>,
+        context: C2,
+    ) -> ActorRef<C2, E2>
+    where
+        C2: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E2: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+        E: From<ActorDone> + PartialEq + Send + Sync + 'static,
+    {
+        let id = match &self.actor {
+            Some(parent) => format!("{}/{}", parent, id.into()),
+            None => id.into(),
+        };
+        let spawned = SpawnedActor::with_context::<C2, E2, E>(id, machine, context);
+        let actor = ActorRef::new(spawned.id.clone());
+        self.spawned.push(spawned);
+        actor
+    }
+
+    /// Events raised so far
+    pub fn raised(&self) -> &[E] {
+        &self.raised
+    }
+
+    /// Events sent so far
+    pub fn sent(&self) -> &[SentEvent] {
+        &self.sent
+    }
+
+    /// Actors spawned so far
+    pub fn spawned(&self) -> &[SpawnedActor] {
+        &self.spawned
+    }
+
+    pub(crate) fn into_parts(self) -> (Vec<E>, Vec<SentEvent>, Vec<SpawnedActor>) {
+        (self.raised, self.sent, self.spawned)
+    }
+}
+
+impl<E> Default for ActionContext<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Function-based action with access to the action context
+pub struct ContextAction<C, E, F> {
+    pub func: F,
+    _phantom: std::marker::PhantomData<fn(&mut C, &E)>,
+}
+
+impl<C, E, F> ContextAction<C, E, F>
+where
+    F: Fn(&mut C, &E, &mut ActionContext<E>) + 'static,
+{
+    pub fn new(func: F) -> Self {
+        Self {
+            func,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<C: 'static, E: 'static, F> Action<C, E> for ContextAction<C, E, F>
+where
+    F: Fn(&mut C, &E, &mut ActionContext<E>) + Clone + Send + Sync + 'static,
+{
+    /// Run without a machine; raised and sent events are dropped
+    fn execute(&self, context: &mut C, event: &E) {
+        (self.func)(context, event, &mut ActionContext::new());
+    }
+
+    fn execute_in(
+        &self,
+        context: &mut C,
+        event: &E,
+        effects: &mut ActionContext<E>,
+    ) -> Result<(), ActionError> {
+        (self.func)(context, event, effects);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "context"
+    }
+
+    fn clone_action(&self) -> Box<dyn Action<C, E>> {
+        Box::new(Self {
+            func: self.func.clone(),
+            _phantom: std::marker::PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Increment,
+    }
+
+    #[test]
+    fn raised_events_are_processed_before_the_step_returns() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("editing")
+            .on(TestEvent::Start, "validating")
+            .action_with_context(|_: &mut TestContext, _: &TestEvent, actions| {
+                actions.raise(TestEvent::Increment)
+            })
+            .state("validating")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 10)
+            // Only taken once the entry action ran, as the raised event waits for it
+            .on(TestEvent::Increment, "valid")
+            .guard_fn(|ctx: &TestContext, _: &TestEvent| ctx.count == 10)
+            .on(TestEvent::Increment, "invalid")
+            .state("valid")
+            .state("invalid")
+            .initial("editing")
+            .build();
+
+        let outcome = machine.step(&machine.initial_state(), TestEvent::Start);
+        assert!(outcome.state.matches("valid"));
+        assert_eq!(outcome.entered, vec!["validating", "valid"]);
+        assert_eq!(outcome.raised.len(), 1);
+        assert_eq!(
+            outcome.raised[0].downcast_ref::<TestEvent>(),
+            Some(&TestEvent::Increment)
+        );
+    }
+
+    #[test]
+    fn sent_events_are_left_for_the_runtime() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on_entry_with_context(|_: &mut TestContext, _: &TestEvent, actions| {
+                actions.send_to("logger", "idle entered")
+            })
+            .on(TestEvent::Start, "idle")
+            .initial("idle")
+            .build();
+
+        let outcome = machine.step(&machine.initial_state(), TestEvent::Start);
+        assert_eq!(outcome.sent.len(), 1);
+        assert_eq!(outcome.sent[0].target, "logger");
+        assert_eq!(
+            outcome.sent[0].event.downcast_ref::<&str>(),
+            Some(&"idle entered")
+        );
+        assert!(outcome.raised.is_empty());
+    }
+
+    #[test]
+    fn raised_event_loops_are_rejected() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on_event(TestEvent::Increment)
+            .action_with_context(|ctx: &mut TestContext, _: &TestEvent, actions| {
+                ctx.count += 1;
+                actions.raise(TestEvent::Increment)
+            })
+            .initial("idle")
+            .build();
+
+        let idle = machine.initial_state();
+        assert!(matches!(
+            machine.try_transition(&idle, TestEvent::Increment),
+            Err(MachineError::RaisedEventLoop(_))
+        ));
+        assert_eq!(machine.transition(&idle, TestEvent::Increment), idle);
+    }
+}
@@ -1,8 +1,18 @@
 //! Control flow action implementations
 
 use super::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// Retry action that attempts to execute an action multiple times
+///
+/// A failed attempt does not block: it rejects the event and asks the runtime to
+/// deliver it again after the backoff delay. `TimedMachine` schedules that delivery
+/// on its scheduler and counts the attempts. Runtimes that never deliver an event
+/// twice (a bare `Machine::step`, actors) get a single attempt, whose failure takes
+/// the transition's error target right away; see `ActionContext::retries`. The
+/// action itself keeps no count, so machines sharing it never see each other's
+/// attempts.
 pub struct RetryAction<C, E> {
     /// The action to retry
     pub action: Box<dyn Action<C, E>>,
@@ -55,13 +65,49 @@ impl<C, E> RetryAction<C, E> {
         self.description = description;
         self
     }
+
+    /// Get the delay before the given retry, counting from 1
+    pub fn delay_before(&self, retry: u32) -> std::time::Duration {
+        match self.backoff {
+            RetryBackoff::Fixed => self.delay,
+            RetryBackoff::Linear => self.delay * retry,
+            RetryBackoff::Exponential => self.delay * 2u32.saturating_pow(retry.saturating_sub(1)),
+        }
+    }
 }
 
 impl<C: Send + Sync + 'static, E: Send + Sync + 'static> Action<C, E> for RetryAction<C, E> {
     fn execute(&self, context: &mut C, event: &E) {
-        // For now, just execute the action once
-        // In a real implementation, this would retry on failure
-        self.action.execute(context, event);
+        if let Err(error) = self.try_execute(context, event) {
+            tracing::warn!("{}", error);
+        }
+    }
+
+    /// Run the action once, failing for good as without a retrying runtime
+    fn try_execute(&self, context: &mut C, event: &E) -> Result<(), ActionError> {
+        self.execute_in(context, event, &mut ActionContext::new())
+    }
+
+    /// Run the action as the attempt `ActionContext::attempt` of the event
+    ///
+    /// A failure before the last attempt carries the backoff delay in
+    /// `ActionError::retry_after`; the last one, or any failure when the runtime
+    /// does not retry, is returned as is.
+    fn execute_in(
+        &self,
+        context: &mut C,
+        event: &E,
+        effects: &mut ActionContext<E>,
+    ) -> Result<(), ActionError> {
+        let attempt = effects.attempt();
+        let retries = effects.retries();
+        self.action.try_execute(context, event).map_err(|error| {
+            if !retries || attempt as usize >= self.max_attempts {
+                return error;
+            }
+            tracing::debug!("Attempt {} failed, retrying: {}", attempt, error);
+            error.retry_after(self.delay_before(attempt))
+        })
     }
 
     fn name(&self) -> &str {
@@ -295,7 +341,16 @@ impl<C: Send + Sync + 'static, E: Send + Sync + 'static> Action<C, E> for Timeou
     }
 }
 
+/// Source of the ids telling circuit breakers apart
+static NEXT_BREAKER: AtomicU64 = AtomicU64::new(0);
+
 /// Circuit breaker action that prevents executing failing actions
+///
+/// Each breaker created with `new` has a circuit of its own, shared by its clones;
+/// breakers never share one through their name. A runtime running the machine
+/// (see `ActionRuntime`) keeps a separate copy of the circuit, so every
+/// `TimedMachine` and actor trips its own. A bare `Machine::step` uses the circuit
+/// kept by the breaker itself.
 pub struct CircuitBreakerAction<C, E> {
     /// The action to protect with circuit breaker
     pub action: Box<dyn Action<C, E>>,
@@ -309,6 +364,28 @@ pub struct CircuitBreakerAction<C, E> {
     pub fallback_action: Option<Box<dyn Action<C, E>>>,
     /// Description of the circuit breaker action
     pub description: String,
+    /// Id runtimes keep this breaker's circuit by
+    id: u64,
+    /// Circuit used when no runtime keeps one
+    circuit: Arc<Mutex<CircuitState>>,
+}
+
+/// Whether a circuit breaker lets calls through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitStatus {
+    /// Calls go through
+    Closed,
+    /// Calls are rejected until the recovery timeout has passed
+    Open,
+    /// The recovery timeout has passed, the next call decides whether to close
+    HalfOpen,
+}
+
+/// Failures and opening time of a circuit breaker
+#[derive(Debug, Default)]
+pub(crate) struct CircuitState {
+    consecutive_failures: usize,
+    opened_at: Option<std::time::Instant>,
 }
 
 impl<C, E> CircuitBreakerAction<C, E> {
@@ -321,6 +398,19 @@ impl<C, E> CircuitBreakerAction<C, E> {
             recovery_timeout: std::time::Duration::from_secs(60),
             fallback_action: None,
             description: "Circuit Breaker Action".to_string(),
+            id: NEXT_BREAKER.fetch_add(1, Ordering::Relaxed),
+            circuit: Arc::new(Mutex::new(CircuitState::default())),
+        }
+    }
+
+    /// Get whether the circuit kept by `runtime` currently lets calls through
+    ///
+    /// Without a runtime this is the circuit kept by the breaker itself.
+    pub fn status(&self, runtime: Option<&ActionRuntime>) -> CircuitStatus {
+        match self.with_circuit(runtime, |circuit| circuit.opened_at) {
+            None => CircuitStatus::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.recovery_timeout => CircuitStatus::Open,
+            Some(_) => CircuitStatus::HalfOpen,
         }
     }
 
@@ -347,6 +437,18 @@ impl<C, E> CircuitBreakerAction<C, E> {
         self.description = description;
         self
     }
+
+    /// Run `f` on the circuit kept by `runtime`, or by the breaker without one
+    fn with_circuit<T>(
+        &self,
+        runtime: Option<&ActionRuntime>,
+        f: impl FnOnce(&mut CircuitState) -> T,
+    ) -> T {
+        match runtime {
+            Some(runtime) => runtime.with_circuit(self.id, f),
+            None => f(&mut self.circuit.lock().unwrap()),
+        }
+    }
 }
 
 impl<C: Send + Sync + 'static, E: Send + Sync + 'static> Action<C, E>
@@ -357,9 +459,48 @@ impl<C: Send + Sync + 'static, E: Send + Sync + 'static> Action<C, E>
     }
 
     fn execute(&self, context: &mut C, event: &E) {
-        // For now, just execute the action
-        // In a real implementation, this would implement circuit breaker logic
-        self.action.execute(context, event);
+        if let Err(error) = self.try_execute(context, event) {
+            tracing::warn!("{}", error);
+        }
+    }
+
+    /// Run the action with the circuit kept by the breaker
+    fn try_execute(&self, context: &mut C, event: &E) -> Result<(), ActionError> {
+        self.execute_in(context, event, &mut ActionContext::new())
+    }
+
+    /// Run the action unless the circuit is open
+    ///
+    /// `failure_threshold` consecutive failures open the circuit. While it is open the
+    /// fallback action runs instead, or the call fails if there is none. Once the
+    /// recovery timeout has passed, one call is let through to decide whether to close it.
+    fn execute_in(
+        &self,
+        context: &mut C,
+        event: &E,
+        effects: &mut ActionContext<E>,
+    ) -> Result<(), ActionError> {
+        let runtime = effects.runtime();
+        if self.status(runtime) == CircuitStatus::Open {
+            return match &self.fallback_action {
+                Some(fallback) => fallback.try_execute(context, event),
+                None => Err(ActionError::new(format!("circuit {} is open", self.name))),
+            };
+        }
+
+        let result = self.action.try_execute(context, event);
+        self.with_circuit(runtime, |circuit| match &result {
+            Ok(()) => *circuit = CircuitState::default(),
+            Err(_) => {
+                circuit.consecutive_failures += 1;
+                if circuit.consecutive_failures >= self.failure_threshold
+                    || circuit.opened_at.is_some()
+                {
+                    circuit.opened_at = Some(std::time::Instant::now());
+                }
+            }
+        });
+        result
     }
 
     fn description(&self) -> String {
@@ -377,6 +518,206 @@ impl<C: Send + Sync + 'static, E: Send + Sync + 'static> Action<C, E>
             recovery_timeout: self.recovery_timeout,
             fallback_action: self.fallback_action.as_ref().map(|a| a.clone_action()),
             description: self.description.clone(),
+            id: self.id,
+            circuit: self.circuit.clone(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::action_context::Delivery;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Fails until it has been called `failures` times, counting every call
+    fn flaky(failures: usize) -> (Box<dyn Action<Vec<usize>, ()>>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let action = FallibleAction::new(move |log: &mut Vec<usize>, _: &()| {
+            let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            log.push(call);
+            if call <= failures {
+                Err(ActionError::new(format!("call {} failed", call)))
+            } else {
+                Ok(())
+            }
+        });
+        (Box::new(action), calls)
+    }
+
+    /// Context for the `attempt`th delivery of an event in a retrying `runtime`
+    fn delivered(attempt: u32, runtime: &ActionRuntime) -> ActionContext<()> {
+        ActionContext::for_delivery(&Delivery {
+            attempt,
+            retries: true,
+            runtime: Some(runtime.clone()),
+            ..Delivery::default()
+        })
+    }
+
+    #[test]
+    fn retry_action_asks_for_a_retry_until_the_last_attempt() {
+        let (action, calls) = flaky(2);
+        let retry = RetryAction::new(action, 3)
+            .with_delay(Duration::from_millis(100))
+            .with_backoff(RetryBackoff::Linear);
+        let runtime = ActionRuntime::new();
+
+        let mut log = Vec::new();
+        let error = retry
+            .execute_in(&mut log, &(), &mut delivered(1, &runtime))
+            .unwrap_err();
+        assert_eq!(error.retry_after, Some(Duration::from_millis(100)));
+        let error = retry
+            .clone_action()
+            .execute_in(&mut log, &(), &mut delivered(2, &runtime))
+            .unwrap_err();
+        assert_eq!(error.retry_after, Some(Duration::from_millis(200)));
+        assert_eq!(
+            retry.execute_in(&mut log, &(), &mut delivered(3, &runtime)),
+            Ok(())
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let (action, _) = flaky(5);
+        let retry = RetryAction::new(action, 2).with_delay(Duration::ZERO);
+        let error = retry
+            .execute_in(&mut log, &(), &mut delivered(1, &runtime))
+            .unwrap_err();
+        assert!(error.retry_after.is_some());
+        let error = retry
+            .execute_in(&mut log, &(), &mut delivered(2, &runtime))
+            .unwrap_err();
+        assert_eq!(error.message, "call 2 failed");
+        assert_eq!(error.retry_after, None);
+
+        // The action keeps no count: another first attempt asks for a retry again
+        let error = retry
+            .execute_in(&mut log, &(), &mut delivered(1, &runtime))
+            .unwrap_err();
+        assert!(error.retry_after.is_some());
+    }
+
+    #[test]
+    fn retry_action_fails_at_once_without_a_retrying_runtime() {
+        let (action, calls) = flaky(1);
+        let retry = RetryAction::new(action, 3);
+
+        let mut log = Vec::new();
+        let error = retry.try_execute(&mut log, &()).unwrap_err();
+        assert_eq!(error.message, "call 1 failed");
+        assert_eq!(error.retry_after, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retry_backoff_grows_the_delay() {
+        let (action, _) = flaky(0);
+        let retry = RetryAction::new(action, 3)
+            .with_delay(Duration::from_millis(100))
+            .with_backoff(RetryBackoff::Exponential);
+
+        assert_eq!(retry.delay_before(1), Duration::from_millis(100));
+        assert_eq!(retry.delay_before(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn circuit_breaker_opens_after_the_failure_threshold() {
+        let (action, calls) = flaky(usize::MAX);
+        let breaker = CircuitBreakerAction::new(action, "uploads".to_string())
+            .with_failure_threshold(2)
+            .with_fallback(Box::new(FallibleAction::new(
+                |log: &mut Vec<usize>, _: &()| {
+                    log.push(0);
+                    Ok(())
+                },
+            )));
+
+        let runtime = ActionRuntime::new();
+
+        let mut log = Vec::new();
+        assert!(breaker
+            .execute_in(&mut log, &(), &mut delivered(1, &runtime))
+            .is_err());
+        assert_eq!(breaker.status(Some(&runtime)), CircuitStatus::Closed);
+        assert!(breaker
+            .execute_in(&mut log, &(), &mut delivered(1, &runtime))
+            .is_err());
+        assert_eq!(breaker.status(Some(&runtime)), CircuitStatus::Open);
+
+        // Clones in the same runtime share the circuit; the fallback runs while it is open
+        let clone = breaker.clone_action();
+        assert_eq!(
+            clone.execute_in(&mut log, &(), &mut delivered(1, &runtime)),
+            Ok(())
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(log, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn circuit_breakers_of_different_runtimes_are_independent() {
+        let (action, calls) = flaky(usize::MAX);
+        let breaker =
+            CircuitBreakerAction::new(action, "uploads".to_string()).with_failure_threshold(1);
+        let tripped = ActionRuntime::new();
+        let other = ActionRuntime::new();
+
+        let mut log = Vec::new();
+        assert!(breaker
+            .execute_in(&mut log, &(), &mut delivered(1, &tripped))
+            .is_err());
+        assert_eq!(breaker.status(Some(&tripped)), CircuitStatus::Open);
+        assert_eq!(breaker.status(Some(&other)), CircuitStatus::Closed);
+
+        // The other runtime still calls the action
+        assert!(breaker
+            .execute_in(&mut log, &(), &mut delivered(1, &other))
+            .is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn circuit_breakers_without_a_runtime_keep_their_own_circuit() {
+        let (action, calls) = flaky(usize::MAX);
+        let breaker =
+            CircuitBreakerAction::new(action, "uploads".to_string()).with_failure_threshold(1);
+        let (action, _) = flaky(usize::MAX);
+        let namesake = CircuitBreakerAction::new(action, "uploads".to_string());
+
+        let mut log = Vec::new();
+        assert!(breaker.try_execute(&mut log, &()).is_err());
+        assert_eq!(breaker.status(None), CircuitStatus::Open);
+        assert_eq!(namesake.status(None), CircuitStatus::Closed);
+
+        // Clones share the tripped circuit, so the action is not called again
+        let clone = breaker.clone_action();
+        assert!(clone.try_execute(&mut log, &()).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn circuit_breaker_closes_once_a_call_succeeds_after_recovery() {
+        let (action, _) = flaky(1);
+        let breaker = CircuitBreakerAction::new(action, "uploads".to_string())
+            .with_failure_threshold(1)
+            .with_recovery_timeout(Duration::ZERO);
+
+        let runtime = ActionRuntime::new();
+
+        let mut log = Vec::new();
+        assert!(breaker
+            .execute_in(&mut log, &(), &mut delivered(1, &runtime))
+            .is_err());
+        assert_eq!(breaker.status(Some(&runtime)), CircuitStatus::HalfOpen);
+
+        assert_eq!(
+            breaker.execute_in(&mut log, &(), &mut delivered(1, &runtime)),
+            Ok(())
+        );
+        assert_eq!(breaker.status(Some(&runtime)), CircuitStatus::Closed);
+    }
+}
@@ -60,6 +60,61 @@ where
     }
 }
 
+/// Function-based action that can fail
+pub struct FallibleAction<C, E, F> {
+    /// The function to execute
+    pub func: F,
+    /// Description of the action
+    pub description: String,
+    /// Phantom data for unused type parameters
+    pub _phantom: std::marker::PhantomData<(C, E)>,
+}
+
+impl<C, E, F> FallibleAction<C, E, F>
+where
+    F: Fn(&mut C, &E) -> Result<(), ActionError> + 'static,
+{
+    /// Create a new fallible action
+    pub fn new(func: F) -> Self {
+        Self {
+            func,
+            description: "Fallible Action".to_string(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<C: Send + Sync + 'static, E: Send + Sync + 'static, F> Action<C, E> for FallibleAction<C, E, F>
+where
+    F: Fn(&mut C, &E) -> Result<(), ActionError> + Clone + Send + Sync + 'static,
+{
+    fn execute(&self, context: &mut C, event: &E) {
+        if let Err(error) = self.try_execute(context, event) {
+            tracing::warn!("{}", error);
+        }
+    }
+
+    fn try_execute(&self, context: &mut C, event: &E) -> Result<(), ActionError> {
+        (self.func)(context, event)
+    }
+
+    fn name(&self) -> &str {
+        "fallible"
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn clone_action(&self) -> Box<dyn Action<C, E>> {
+        Box::new(Self {
+            func: self.func.clone(),
+            description: self.description.clone(),
+            _phantom: std::marker::PhantomData,
+        })
+    }
+}
+
 /// Assign action that updates context fields
 pub struct AssignAction<C, E, T, F> {
     /// The assignment function
@@ -0,0 +1,790 @@
+//! Actor layer: machine instances that spawn children and message each other
+//!
+//! An [`ActorSystem`] owns running machine instances (actors) and delivers the
+//! events their actions send. Actions spawn children with
+//! [`ActionContext::spawn`] and keep the returned [`ActorRef`] in their context;
+//! children reply with [`ActionContext::send_parent`], and a child reaching a
+//! final state notifies its parent with an [`ActorDone`] event and is stopped.
+//! Children ids are scoped under their parent's id as `parent/child`.
+
+use super::*;
+use crate::machine::action_context::Delivery;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Target id that resolves to the sending actor's parent
+pub const PARENT_ACTOR: &str = "#parent";
+
+/// Maximum number of events delivered while handling a single send to an actor system
+pub const MAX_ACTOR_MESSAGES: usize = 1000;
+
+/// Typed reference to an actor running a `Machine<C, E, C>`
+///
+/// References only hold the actor's id, so they can be stored in a machine
+/// context and compared.
+pub struct ActorRef<C, E> {
+    id: String,
+    _phantom: std::marker::PhantomData<fn() -> (C, E)>,
+}
+
+impl<C, E> ActorRef<C, E> {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Id of the actor in its system
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl<C, E> Clone for ActorRef<C, E> {
+    fn clone(&self) -> Self {
+        Self::new(self.id.clone())
+    }
+}
+
+impl<C, E> std::fmt::Debug for ActorRef<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ActorRef").field(&self.id).finish()
+    }
+}
+
+impl<C, E> PartialEq for ActorRef<C, E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<C, E> Eq for ActorRef<C, E> {}
+
+impl<C, E> std::hash::Hash for ActorRef<C, E> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+/// Event sent to a parent when one of its children reaches a final state
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActorDone {
+    /// Id of the child actor
+    pub id: String,
+    /// The child's final state value
+    pub state: String,
+}
+
+/// What an actor did while handling an event
+#[derive(Debug, Default)]
+pub struct ActorStep {
+    pub sent: Vec<SentEvent>,
+    pub spawned: Vec<SpawnedActor>,
+    /// Whether the actor reached a final state with this event
+    pub done: bool,
+}
+
+/// Running instance hosted by an [`ActorSystem`]
+pub trait Actor: Send {
+    /// Called once the system hosts the actor, with what starting it did
+    fn start(&mut self) -> ActorStep {
+        ActorStep::default()
+    }
+
+    /// Handle an event; events of a type the actor does not understand are ignored
+    fn receive(&mut self, event: &AnyEvent) -> ActorStep;
+
+    /// Current state, for typed access through [`ActorSystem::state`]
+    fn state(&self) -> &dyn Any;
+
+    /// Description of the current state, used in [`ActorDone`]
+    fn describe(&self) -> String;
+}
+
+/// Actor running a machine definition
+pub struct MachineActor<
+    C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+    E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+> {
+    id: String,
+    machine: Machine<C, E, C>,
+    state: MachineStateImpl<C>,
+    /// Action state of this actor, such as its circuit breakers
+    runtime: ActionRuntime,
+    /// What settling the initial state did, until the system starts the actor
+    started: ActorStep,
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    > MachineActor<C, E>
+{
+    /// Start the machine in its initial state, as the actor with the id `id`
+    pub fn new(id: impl Into<String>, machine: Machine<C, E, C>) -> Self {
+        Self::with_context(id, machine, C::default())
+    }
+
+    /// Start the machine in its initial state with `context`, as the actor with the id `id`
+    pub fn with_context(id: impl Into<String>, machine: Machine<C, E, C>, context: C) -> Self {
+        let id = id.into();
+        let runtime = ActionRuntime::new();
+        let delivery = Delivery {
+            actor: Some(id.clone()),
+            runtime: Some(runtime.clone()),
+            ..Delivery::default()
+        };
+        let outcome = machine.start_in(delivery, context);
+        let started = ActorStep {
+            sent: outcome.sent,
+            spawned: outcome.spawned,
+            done: machine.is_done(&outcome.state),
+        };
+        Self {
+            id,
+            machine,
+            state: outcome.state,
+            runtime,
+            started,
+        }
+    }
+
+    /// How events reach this actor's actions
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            actor: Some(self.id.clone()),
+            runtime: Some(self.runtime.clone()),
+            ..Delivery::default()
+        }
+    }
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    > Actor for MachineActor<C, E>
+{
+    fn start(&mut self) -> ActorStep {
+        std::mem::take(&mut self.started)
+    }
+
+    fn receive(&mut self, event: &AnyEvent) -> ActorStep {
+        let Some(event) = event.downcast_ref::<E>() else {
+            tracing::warn!(
+                "Ignoring {} event, the actor handles {}",
+                event.type_name(),
+                std::any::type_name::<E>()
+            );
+            return ActorStep::default();
+        };
+
+        let was_done = self.machine.is_done(&self.state);
+        let outcome = self
+            .machine
+            .try_step_in(self.delivery(), &self.state, event.clone())
+            .unwrap_or_else(|err| {
+                tracing::error!("Transition rejected: {}", err);
+                TransitionOutcome::new(&self.state)
+            });
+        self.state = outcome.state;
+
+        ActorStep {
+            sent: outcome.sent,
+            spawned: outcome.spawned,
+            done: !was_done && self.machine.is_done(&self.state),
+        }
+    }
+
+    fn state(&self) -> &dyn Any {
+        &self.state
+    }
+
+    fn describe(&self) -> String {
+        self.state.value().to_string()
+    }
+}
+
+/// Child actor an action asked to spawn, left for the actor system to start
+#[derive(Clone)]
+pub struct SpawnedActor {
+    pub id: String,
+    start: Arc<dyn Fn() -> Box<dyn Actor> + Send + Sync>,
+    /// Turns the child's completion into an event for the parent
+    notify: fn(ActorDone) -> AnyEvent,
+}
+
+impl SpawnedActor {
+    pub fn new<C, E, P>(id: impl Into<String>, machine: Machine<C, E, C>) -> Self
+    where
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+        P: From<ActorDone> + PartialEq + Send + Sync + 'static,
+    {
+        Self::with_context::<C, E, P>(id, machine, C::default())
+    }
+
+    /// Child actor starting `machine` with `context` instead of the default context
+    pub fn with_context<C, E, P>(
+        id: impl Into<String>,
+        machine: Machine<C, E, C>,
+        context: C,
+    ) -> Self
+    where
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+        P: From<ActorDone> + PartialEq + Send + Sync + 'static,
+    {
+        let id = id.into();
+        let actor = id.clone();
+        Self {
+            id,
+            start: Arc::new(move || {
+                Box::new(MachineActor::with_context(
+                    actor.clone(),
+                    machine.clone(),
+                    context.clone(),
+                ))
+            }),
+            notify: |done| AnyEvent::new(P::from(done)),
+        }
+    }
+}
+
+impl std::fmt::Debug for SpawnedActor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SpawnedActor").field(&self.id).finish()
+    }
+}
+
+impl PartialEq for SpawnedActor {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && Arc::ptr_eq(&self.start, &other.start)
+    }
+}
+
+/// Actor hosted by a system, with its place in the hierarchy
+struct ActorEntry {
+    actor: Box<dyn Actor>,
+    parent: Option<String>,
+    notify: Option<fn(ActorDone) -> AnyEvent>,
+}
+
+/// Event waiting to be delivered
+struct Envelope {
+    from: Option<String>,
+    to: String,
+    event: AnyEvent,
+    /// Stop the sender once this is delivered, for the completion of a done child
+    stop_sender: bool,
+}
+
+/// Runtime owning a tree of actors and the events sent between them
+///
+/// Events are delivered one at a time in the order they were sent, and
+/// [`ActorSystem::send`] returns once every event it caused has been handled, or
+/// fails once `MAX_ACTOR_MESSAGES` of them were delivered.
+#[derive(Default)]
+pub struct ActorSystem {
+    actors: HashMap<String, ActorEntry>,
+    queue: VecDeque<Envelope>,
+    /// Events for the parents living outside the system, by id
+    mailboxes: HashMap<String, Vec<AnyEvent>>,
+}
+
+impl ActorSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a top-level actor running `machine`
+    ///
+    /// An actor already using `id` is replaced, children included.
+    pub fn spawn<C, E>(
+        &mut self,
+        id: impl Into<String>,
+        machine: Machine<C, E, C>,
+    ) -> ActorRef<C, E>
+    where
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    {
+        self.spawn_with_context(id, machine, C::default())
+    }
+
+    /// Start a top-level actor running `machine` with `context`
+    ///
+    /// Messages that never settle while starting the actor are dropped and logged.
+    pub fn spawn_with_context<C, E>(
+        &mut self,
+        id: impl Into<String>,
+        machine: Machine<C, E, C>,
+        context: C,
+    ) -> ActorRef<C, E>
+    where
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    {
+        let id = id.into();
+        self.stop(&id);
+        let actor = Box::new(MachineActor::with_context(id.clone(), machine, context));
+        self.start(
+            id.clone(),
+            ActorEntry {
+                actor,
+                parent: None,
+                notify: None,
+            },
+        );
+        if let Err(err) = self.run() {
+            tracing::error!("Starting actor {} failed: {}", id, err);
+        }
+        ActorRef::new(id)
+    }
+
+    /// Send `event` to `actor` and handle everything it causes
+    ///
+    /// Fails with `MachineError::ActorMessageLoop`, dropping the events still
+    /// queued, if actors keep messaging each other.
+    pub fn send<C, E: Any + PartialEq + Send + Sync>(
+        &mut self,
+        actor: &ActorRef<C, E>,
+        event: E,
+    ) -> MachineResult<()> {
+        self.queue.push_back(Envelope {
+            from: None,
+            to: actor.id().to_string(),
+            event: AnyEvent::new(event),
+            stop_sender: false,
+        });
+        self.run()
+    }
+
+    /// Start the children spawned and deliver the events sent by `id`, a parent
+    /// living outside the system such as a [`MachineService`]
+    ///
+    /// Returns the events the system's actors sent back to `id`, including the
+    /// completion of its children. Fails like [`ActorSystem::send`].
+    pub fn relay(
+        &mut self,
+        id: &str,
+        spawned: Vec<SpawnedActor>,
+        sent: Vec<SentEvent>,
+    ) -> MachineResult<Vec<AnyEvent>> {
+        self.mailboxes.entry(id.to_string()).or_default();
+        self.adopt(id, spawned, sent);
+        let delivered = self.run();
+        let replies = self
+            .mailboxes
+            .get_mut(id)
+            .map(std::mem::take)
+            .unwrap_or_default();
+        delivered.map(|()| replies)
+    }
+
+    /// Get a snapshot of the state of `actor`
+    pub fn state<C: Clone + Send + Sync + 'static, E>(
+        &self,
+        actor: &ActorRef<C, E>,
+    ) -> Option<MachineStateImpl<C>> {
+        let entry = self.actors.get(actor.id())?;
+        entry.actor.state().downcast_ref().cloned()
+    }
+
+    /// Ids of the children of the actor with the id `id`, sorted
+    pub fn children(&self, id: &str) -> Vec<&str> {
+        let mut children: Vec<&str> = self
+            .actors
+            .iter()
+            .filter(|(_, entry)| entry.parent.as_deref() == Some(id))
+            .map(|(child, _)| child.as_str())
+            .collect();
+        children.sort();
+        children
+    }
+
+    /// Whether an actor with the id `id` is running
+    pub fn contains(&self, id: &str) -> bool {
+        self.actors.contains_key(id)
+    }
+
+    /// Number of running actors
+    pub fn len(&self) -> usize {
+        self.actors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actors.is_empty()
+    }
+
+    /// Stop the actor with the id `id` and all of its descendants
+    pub fn stop(&mut self, id: &str) {
+        if self.actors.remove(id).is_none() {
+            return;
+        }
+
+        let children: Vec<String> = self.children(id).into_iter().map(String::from).collect();
+        for child in children {
+            self.stop(&child);
+        }
+    }
+
+    /// Deliver queued events until none is left
+    ///
+    /// Fails with `MachineError::ActorMessageLoop`, dropping the events still
+    /// queued, if events are still queued after `MAX_ACTOR_MESSAGES` deliveries.
+    fn run(&mut self) -> MachineResult<()> {
+        let mut delivered = 0;
+        while let Some(envelope) = self.queue.pop_front() {
+            if delivered == MAX_ACTOR_MESSAGES {
+                self.queue.clear();
+                return Err(MachineError::ActorMessageLoop(envelope.to));
+            }
+            delivered += 1;
+
+            let to = if envelope.to == PARENT_ACTOR {
+                let parent = envelope
+                    .from
+                    .as_ref()
+                    .and_then(|from| self.actors.get(from))
+                    .and_then(|entry| entry.parent.clone());
+                let Some(parent) = parent else {
+                    tracing::warn!("Dropping event sent to the parent of a top-level actor");
+                    continue;
+                };
+                parent
+            } else {
+                envelope.to
+            };
+
+            if let Some(entry) = self.actors.get_mut(&to) {
+                let step = entry.actor.receive(&envelope.event);
+                self.handle(&to, step);
+            } else if let Some(mailbox) = self.mailboxes.get_mut(&to) {
+                mailbox.push(envelope.event);
+            } else {
+                tracing::warn!("Dropping event sent to unknown actor {}", to);
+                continue;
+            }
+
+            if envelope.stop_sender {
+                if let Some(from) = &envelope.from {
+                    self.stop(from);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Host `entry` under `id` and handle what starting it did
+    fn start(&mut self, id: String, mut entry: ActorEntry) {
+        let step = entry.actor.start();
+        self.actors.insert(id.clone(), entry);
+        self.handle(&id, step);
+    }
+
+    /// Start the children the actor `id` spawned and queue the events it sent,
+    /// followed by its completion if it just finished
+    fn handle(&mut self, id: &str, step: ActorStep) {
+        let Some(entry) = self.actors.get(id) else {
+            return;
+        };
+        let completion = match (step.done, &entry.parent, entry.notify) {
+            (true, Some(parent), Some(notify)) => Some(Envelope {
+                from: Some(id.to_string()),
+                to: parent.clone(),
+                event: notify(ActorDone {
+                    id: id.to_string(),
+                    state: entry.actor.describe(),
+                }),
+                stop_sender: true,
+            }),
+            _ => None,
+        };
+
+        self.adopt(id, step.spawned, step.sent);
+        self.queue.extend(completion);
+    }
+
+    /// Start the children `id` spawned and queue the events it sent
+    fn adopt(&mut self, id: &str, spawned: Vec<SpawnedActor>, sent: Vec<SentEvent>) {
+        for spawned in spawned {
+            if self.actors.contains_key(&spawned.id) {
+                tracing::warn!("Replacing actor {} with a newly spawned one", spawned.id);
+                self.stop(&spawned.id);
+            }
+            self.start(
+                spawned.id.clone(),
+                ActorEntry {
+                    actor: (spawned.start)(),
+                    parent: Some(id.to_string()),
+                    notify: Some(spawned.notify),
+                },
+            );
+        }
+
+        for sent in sent {
+            self.queue.push_back(Envelope {
+                from: Some(id.to_string()),
+                to: sent.target,
+                event: sent.event,
+                stop_sender: false,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct ToastContext;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum ToastEvent {
+        Click,
+        Dismiss,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct CenterContext {
+        toasts: Vec<ActorRef<ToastContext, ToastEvent>>,
+        clicks: usize,
+        dismissed: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum CenterEvent {
+        Notify(String),
+        DismissAll,
+        Clicked,
+        ToastDone(ActorDone),
+    }
+
+    impl From<ActorDone> for CenterEvent {
+        fn from(done: ActorDone) -> Self {
+            CenterEvent::ToastDone(done)
+        }
+    }
+
+    fn toast_machine() -> Machine<ToastContext, ToastEvent, ToastContext> {
+        MachineBuilder::<ToastContext, ToastEvent>::new()
+            .state("visible")
+            .on_event(ToastEvent::Click)
+            .action_with_context(|_, _, effects: &mut ActionContext<ToastEvent>| {
+                effects.send_parent(CenterEvent::Clicked)
+            })
+            .on(ToastEvent::Dismiss, "dismissed")
+            .state("dismissed")
+            .final_state()
+            .initial("visible")
+            .build()
+    }
+
+    fn center_machine() -> Machine<CenterContext, CenterEvent, CenterContext> {
+        MachineBuilder::<CenterContext, CenterEvent>::new()
+            .state("active")
+            .on_event(CenterEvent::Notify(String::new()))
+            .match_variant()
+            .action_with_context(|ctx: &mut CenterContext, event, effects| {
+                if let CenterEvent::Notify(id) = event {
+                    ctx.toasts.push(effects.spawn(id.clone(), toast_machine()));
+                }
+            })
+            .on_event(CenterEvent::DismissAll)
+            .action_with_context(|ctx: &mut CenterContext, _, effects| {
+                for toast in &ctx.toasts {
+                    effects.send(toast, ToastEvent::Dismiss);
+                }
+            })
+            .on_event(CenterEvent::Clicked)
+            .action(FunctionAction::new(
+                |ctx: &mut CenterContext, _: &CenterEvent| ctx.clicks += 1,
+            ))
+            .on_event(CenterEvent::ToastDone(ActorDone {
+                id: String::new(),
+                state: String::new(),
+            }))
+            .match_variant()
+            .action(FunctionAction::new(
+                |ctx: &mut CenterContext, event: &CenterEvent| {
+                    if let CenterEvent::ToastDone(done) = event {
+                        ctx.dismissed.push(format!("{} {}", done.id, done.state));
+                    }
+                },
+            ))
+            .initial("active")
+            .build()
+    }
+
+    #[test]
+    fn spawned_children_notify_their_parent_when_done() {
+        let mut system = ActorSystem::new();
+        let center = system.spawn("center", center_machine());
+
+        system
+            .send(&center, CenterEvent::Notify("saved".to_string()))
+            .unwrap();
+        system
+            .send(&center, CenterEvent::Notify("synced".to_string()))
+            .unwrap();
+        assert_eq!(
+            system.children("center"),
+            vec!["center/saved", "center/synced"]
+        );
+
+        // Done children are stopped once their parent has been told
+        system.send(&center, CenterEvent::DismissAll).unwrap();
+        let context = system.state(&center).unwrap().context().clone();
+        assert_eq!(
+            context.dismissed,
+            vec!["center/saved dismissed", "center/synced dismissed"]
+        );
+        assert!(system.children("center").is_empty());
+        assert!(system.state(&context.toasts[0]).is_none());
+    }
+
+    #[test]
+    fn children_ids_are_scoped_under_their_parent() {
+        let mut system = ActorSystem::new();
+        let left = system.spawn("left", center_machine());
+        let right = system.spawn("right", center_machine());
+
+        system
+            .send(&left, CenterEvent::Notify("saved".to_string()))
+            .unwrap();
+        system
+            .send(&right, CenterEvent::Notify("saved".to_string()))
+            .unwrap();
+        assert_eq!(system.children("left"), vec!["left/saved"]);
+        assert_eq!(system.children("right"), vec!["right/saved"]);
+        assert_eq!(system.len(), 4);
+    }
+
+    #[test]
+    fn events_sent_while_starting_are_delivered() {
+        let toast = MachineBuilder::<ToastContext, ToastEvent>::new()
+            .state("opening")
+            .always("visible")
+            .action(ContextAction::new(
+                |_: &mut ToastContext, _: &(), effects: &mut ActionContext<()>| {
+                    effects.send_parent(CenterEvent::Clicked)
+                },
+            ))
+            .state("visible")
+            .initial("opening")
+            .build();
+        let center = MachineBuilder::<CenterContext, CenterEvent>::new()
+            .state("active")
+            .on_event(CenterEvent::Notify(String::new()))
+            .match_variant()
+            .action_with_context(move |ctx: &mut CenterContext, _, effects| {
+                ctx.toasts.push(effects.spawn("opening", toast.clone()));
+            })
+            .on_event(CenterEvent::Clicked)
+            .action(FunctionAction::new(
+                |ctx: &mut CenterContext, _: &CenterEvent| ctx.clicks += 1,
+            ))
+            .initial("active")
+            .build();
+
+        let mut system = ActorSystem::new();
+        let center = system.spawn("center", center);
+        system
+            .send(&center, CenterEvent::Notify("opening".to_string()))
+            .unwrap();
+
+        let context = system.state(&center).unwrap().context().clone();
+        assert_eq!(context.clicks, 1);
+        assert!(system.state(&context.toasts[0]).unwrap().matches("visible"));
+    }
+
+    #[test]
+    fn children_send_events_to_their_parent() {
+        let mut system = ActorSystem::new();
+        let center = system.spawn("center", center_machine());
+
+        system
+            .send(&center, CenterEvent::Notify("saved".to_string()))
+            .unwrap();
+        let toast = system.state(&center).unwrap().context().toasts[0].clone();
+        system.send(&toast, ToastEvent::Click).unwrap();
+        system.send(&toast, ToastEvent::Click).unwrap();
+
+        assert_eq!(system.state(&center).unwrap().context().clicks, 2);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct PingContext {
+        peer: Option<ActorRef<PingContext, PingEvent>>,
+        pings: usize,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum PingEvent {
+        Ping,
+    }
+
+    /// Answers every ping by pinging its peer
+    fn ping_machine() -> Machine<PingContext, PingEvent, PingContext> {
+        MachineBuilder::<PingContext, PingEvent>::new()
+            .state("ready")
+            .on_event(PingEvent::Ping)
+            .action_with_context(|ctx: &mut PingContext, _, effects| {
+                ctx.pings += 1;
+                if let Some(peer) = &ctx.peer {
+                    effects.send(peer, PingEvent::Ping);
+                }
+            })
+            .initial("ready")
+            .build()
+    }
+
+    fn peer_of(id: &str) -> PingContext {
+        PingContext {
+            peer: Some(ActorRef::new(id)),
+            pings: 0,
+        }
+    }
+
+    #[test]
+    fn actors_start_with_the_given_context() {
+        let mut system = ActorSystem::new();
+        let alone = system.spawn("alone", ping_machine());
+        let paired = system.spawn_with_context("paired", ping_machine(), peer_of("alone"));
+
+        system.send(&paired, PingEvent::Ping).unwrap();
+        assert_eq!(system.state(&paired).unwrap().context().pings, 1);
+        assert_eq!(system.state(&alone).unwrap().context().pings, 1);
+    }
+
+    #[test]
+    fn actors_messaging_each_other_forever_are_stopped() {
+        let mut system = ActorSystem::new();
+        let left = system.spawn_with_context("left", ping_machine(), peer_of("right"));
+        let right = system.spawn_with_context("right", ping_machine(), peer_of("left"));
+
+        let err = system.send(&left, PingEvent::Ping).unwrap_err();
+        assert!(matches!(err, MachineError::ActorMessageLoop(_)));
+        let pings =
+            |actor: &ActorRef<PingContext, PingEvent>| system.state(actor).unwrap().context().pings;
+        assert_eq!(pings(&left) + pings(&right), MAX_ACTOR_MESSAGES);
+    }
+
+    #[test]
+    fn stopping_an_actor_stops_its_children() {
+        let mut system = ActorSystem::new();
+        let center = system.spawn("center", center_machine());
+        system
+            .send(&center, CenterEvent::Notify("saved".to_string()))
+            .unwrap();
+        assert_eq!(system.len(), 2);
+
+        system.stop("center");
+        assert!(system.is_empty());
+        assert!(system.state(&center).is_none());
+    }
+}
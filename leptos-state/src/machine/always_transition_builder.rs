@@ -0,0 +1,246 @@
+use super::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builder for eventless ("always") transitions
+pub struct AlwaysTransitionBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    state_builder: StateBuilder<C, E>,
+    target: String,
+    guards: Vec<Arc<dyn Guard<C, ()>>>,
+    actions: Vec<Arc<dyn Action<C, ()>>>,
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > AlwaysTransitionBuilder<C, E>
+{
+    pub fn new(state_builder: StateBuilder<C, E>, target: String) -> Self {
+        Self {
+            state_builder,
+            target,
+            guards: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn guard<G: Guard<C, ()> + 'static>(mut self, guard: G) -> Self {
+        self.guards.push(Arc::new(guard));
+        self
+    }
+
+    /// Add a function-based guard, checked against the context
+    pub fn guard_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&C) -> bool + Clone + Send + Sync + 'static,
+    {
+        self.guards.push(Arc::new(guards::FunctionGuard::new(
+            move |ctx: &C, _: &()| func(ctx),
+        )));
+        self
+    }
+
+    pub fn action<A: Action<C, ()> + 'static>(mut self, action: A) -> Self {
+        self.actions.push(Arc::new(action));
+        self
+    }
+
+    /// Add a function-based action, run when the transition is taken
+    pub fn action_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C) + Clone + Send + Sync + 'static,
+    {
+        self.actions.push(Arc::new(actions::FunctionAction::new(
+            move |ctx: &mut C, _: &()| func(ctx),
+        )));
+        self
+    }
+
+    pub fn on(self, event: E, target: &str) -> TransitionBuilder<C, E> {
+        self.finish().on(event, target)
+    }
+
+    pub fn after(self, delay: Duration, target: &str) -> DelayedTransitionBuilder<C, E> {
+        self.finish().after(delay, target)
+    }
+
+    pub fn always(self, target: &str) -> AlwaysTransitionBuilder<C, E> {
+        self.finish().always(target)
+    }
+
+    pub fn state(self, id: &str) -> StateBuilder<C, E> {
+        self.finish().state(id)
+    }
+
+    /// Finish the always transition and return to the state containing the current one
+    pub fn parent(self) -> StateBuilder<C, E> {
+        self.finish().parent()
+    }
+
+    /// Finish the current transition and set the initial state on the underlying builder
+    pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
+        self.finish().initial(state_id)
+    }
+
+    pub fn build(self) -> Machine<C, E, C> {
+        self.finish().build()
+    }
+
+    /// Finish the current state and build the machine, rejecting invalid definitions
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        self.finish().try_build()
+    }
+
+    /// Finish the always transition and add it to the state
+    pub(crate) fn finish(self) -> StateBuilder<C, E> {
+        let always = AlwaysTransition {
+            target: self.target,
+            guards: self.guards,
+            actions: self.actions,
+        };
+
+        let mut state_builder = self.state_builder;
+        state_builder.always_transitions.push(always);
+        state_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Signal(MachineSignal),
+    }
+
+    impl From<MachineSignal> for TestEvent {
+        fn from(signal: MachineSignal) -> Self {
+            TestEvent::Signal(signal)
+        }
+    }
+
+    #[test]
+    fn always_transitions_settle_initial_state() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("routing")
+            .always("onboarded")
+            .guard_fn(|ctx: &TestContext| ctx.count > 0)
+            .always("onboarding")
+            .state("onboarded")
+            .state("onboarding")
+            .initial("routing")
+            .build();
+
+        let returning_user = machine.initial_with_context(TestContext { count: 1 });
+        assert!(returning_user.matches("onboarded"));
+
+        let new_user = machine.initial_state();
+        assert!(new_user.matches("onboarding"));
+    }
+
+    #[test]
+    fn always_transitions_on_start_run_entry_actions() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .signal_event(TestEvent::from)
+            .state("routing")
+            .on_exit_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 1)
+            .always("checking")
+            .state("checking")
+            .on_entry_fn(|ctx: &mut TestContext, event: &TestEvent| {
+                assert_eq!(*event, TestEvent::Signal(MachineSignal::Started));
+                ctx.count += 10;
+            })
+            .always("ready")
+            .state("ready")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 100)
+            .initial("routing")
+            .build();
+
+        let ready = machine.initial_state();
+        assert!(ready.matches("ready"));
+        assert_eq!(ready.context().count, 111);
+    }
+
+    #[test]
+    fn always_transitions_on_start_skip_entry_actions_without_signal_events() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("routing")
+            .on_exit_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 1)
+            .always("ready")
+            .action_fn(|ctx: &mut TestContext| ctx.count += 10)
+            .state("ready")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 100)
+            .initial("routing")
+            .build();
+
+        let ready = machine.initial_state();
+        assert!(ready.matches("ready"));
+        assert_eq!(ready.context().count, 10);
+    }
+
+    #[test]
+    fn always_transitions_chain_after_event() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "checking")
+            .state("checking")
+            .always("validated")
+            .action_fn(|ctx: &mut TestContext| ctx.count += 1)
+            .state("validated")
+            .always("running")
+            .action_fn(|ctx: &mut TestContext| ctx.count += 10)
+            .state("running")
+            .initial("idle")
+            .build();
+
+        let running = machine.transition(&machine.initial_state(), TestEvent::Start);
+        assert!(running.matches("running"));
+        assert_eq!(running.context().count, 11);
+    }
+
+    #[test]
+    fn child_states_keep_their_always_transitions() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("checkout")
+            .initial_child("routing")
+            .child_state("routing")
+            .always("payment")
+            .parent()
+            .child_state("payment")
+            .parent()
+            .initial("checkout")
+            .build();
+
+        assert!(machine.initial_state().matches("checkout.payment"));
+    }
+
+    #[test]
+    fn always_transition_loops_are_rejected() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "ping")
+            .state("ping")
+            .always("pong")
+            .state("pong")
+            .always("ping")
+            .initial("idle")
+            .build();
+
+        let idle = machine.initial_state();
+        assert!(matches!(
+            machine.try_transition(&idle, TestEvent::Start),
+            Err(MachineError::EventlessLoop(_))
+        ));
+        assert_eq!(machine.transition(&idle, TestEvent::Start), idle);
+    }
+}
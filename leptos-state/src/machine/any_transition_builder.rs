@@ -0,0 +1,255 @@
+use super::*;
+use std::sync::Arc;
+
+/// Builder for wildcard ("any event") transitions
+///
+/// `P` is the builder the transition is added to: a [`StateBuilder`] for a state's
+/// wildcard or the [`MachineBuilder`] for a machine-level one.
+pub struct AnyTransitionBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    P,
+> {
+    parent: P,
+    target: String,
+    guards: Vec<Arc<dyn Guard<C, E>>>,
+    actions: Vec<Arc<dyn Action<C, E>>>,
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+        P,
+    > AnyTransitionBuilder<C, E, P>
+{
+    pub fn new(parent: P, target: String) -> Self {
+        Self {
+            parent,
+            target,
+            guards: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn guard<G: Guard<C, E> + 'static>(mut self, guard: G) -> Self {
+        self.guards.push(Arc::new(guard));
+        self
+    }
+
+    /// Add a function-based guard
+    pub fn guard_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&C, &E) -> bool + Send + Sync + 'static,
+    {
+        self.guards.push(Arc::new(guards::FunctionGuard::new(func)));
+        self
+    }
+
+    pub fn action<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
+        self.actions.push(Arc::new(action));
+        self
+    }
+
+    /// Add a function-based action, run when the transition is taken
+    pub fn action_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
+    {
+        self.actions
+            .push(Arc::new(actions::FunctionAction::new(func)));
+        self
+    }
+
+    /// Add a function-based action that can raise follow-up events or send events
+    /// to other machines
+    pub fn action_with_context<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C, &E, &mut ActionContext<E>) + Clone + Send + Sync + 'static,
+    {
+        self.actions.push(Arc::new(ContextAction::new(func)));
+        self
+    }
+
+    fn into_parts(self) -> (P, AnyTransition<C, E>) {
+        let any = AnyTransition {
+            target: self.target,
+            guards: self.guards,
+            actions: self.actions,
+        };
+
+        (self.parent, any)
+    }
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > AnyTransitionBuilder<C, E, StateBuilder<C, E>>
+{
+    pub fn on(self, event: E, target: &str) -> TransitionBuilder<C, E> {
+        self.finish().on(event, target)
+    }
+
+    pub fn on_any(self, target: &str) -> AnyTransitionBuilder<C, E, StateBuilder<C, E>> {
+        self.finish().on_any(target)
+    }
+
+    /// Finish the current transition and forbid `event` from bubbling up from the current state
+    pub fn forbid(self, event: E) -> StateBuilder<C, E> {
+        self.finish().forbid(event)
+    }
+
+    pub fn state(self, id: &str) -> StateBuilder<C, E> {
+        self.finish().state(id)
+    }
+
+    /// Finish the current transition and return to the state containing the current one
+    pub fn parent(self) -> StateBuilder<C, E> {
+        self.finish().parent()
+    }
+
+    /// Finish the current transition and set the initial state on the underlying builder
+    pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
+        self.finish().initial(state_id)
+    }
+
+    pub fn build(self) -> Machine<C, E, C> {
+        self.finish().build()
+    }
+
+    /// Finish the current state and build the machine, rejecting invalid definitions
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        self.finish().try_build()
+    }
+
+    /// Finish the wildcard transition and add it to the state
+    pub(crate) fn finish(self) -> StateBuilder<C, E> {
+        let (mut state_builder, any) = self.into_parts();
+        state_builder.any_transitions.push(any);
+        state_builder
+    }
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > AnyTransitionBuilder<C, E, MachineBuilder<C, E>>
+{
+    pub fn on_any(self, target: &str) -> AnyTransitionBuilder<C, E, MachineBuilder<C, E>> {
+        self.finish().on_any(target)
+    }
+
+    pub fn state(self, id: &str) -> StateBuilder<C, E> {
+        self.finish().state(id)
+    }
+
+    /// Finish the current transition and set the initial state on the underlying builder
+    pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
+        self.finish().initial(state_id)
+    }
+
+    pub fn build(self) -> Machine<C, E, C> {
+        self.finish().build()
+    }
+
+    /// Finish the transition and build the machine, rejecting invalid definitions
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        self.finish().try_build()
+    }
+
+    /// Finish the wildcard transition and add it to the machine
+    pub(crate) fn finish(self) -> MachineBuilder<C, E> {
+        let (mut machine_builder, any) = self.into_parts();
+        machine_builder.any_transitions.push(any);
+        machine_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+        Increment,
+    }
+
+    #[test]
+    fn any_transitions_catch_events_without_a_specific_transition() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("editing")
+            .on(TestEvent::Stop, "saved")
+            .on_any("error")
+            .action_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 1)
+            .state("saved")
+            .state("error")
+            .initial("editing")
+            .build();
+
+        let saved = machine.transition(&machine.initial_state(), TestEvent::Stop);
+        assert!(saved.matches("saved"));
+        assert_eq!(saved.context().count, 0);
+
+        let error = machine.transition(&machine.initial_state(), TestEvent::Increment);
+        assert!(error.matches("error"));
+        assert_eq!(error.context().count, 1);
+    }
+
+    #[test]
+    fn child_states_keep_their_wildcard_transitions() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("editor")
+            .initial_child("editing")
+            .child_state("editing")
+            .on_any("saved")
+            .parent()
+            .child_state("saved")
+            .parent()
+            .initial("editor")
+            .build();
+
+        let saved = machine.transition(&machine.initial_state(), TestEvent::Stop);
+        assert!(saved.matches("editor.saved"));
+    }
+
+    #[test]
+    fn machine_level_any_transitions_leave_nested_states() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .on_any("cart")
+            .guard_fn(|_: &TestContext, event: &TestEvent| *event == TestEvent::Stop)
+            .state("cart")
+            .on(TestEvent::Start, "checkout.payment.card")
+            .state("checkout")
+            .initial_child("shipping")
+            .child_state("shipping")
+            .parent()
+            .child_state("payment")
+            .initial_child("card")
+            .child_state("card")
+            .parent()
+            .parent()
+            .initial("cart")
+            .build();
+
+        let card = machine.transition(&machine.initial_state(), TestEvent::Start);
+        assert!(card.matches("checkout.payment.card"));
+
+        let reset = machine.step(&card, TestEvent::Stop);
+        assert!(reset.state.matches("cart"));
+        assert_eq!(
+            reset.exited,
+            vec!["checkout.payment.card", "checkout.payment", "checkout"]
+        );
+
+        // Events the wildcard's guard rejects are ignored
+        let ignored = machine.step(&card, TestEvent::Increment);
+        assert!(!ignored.changed);
+    }
+}
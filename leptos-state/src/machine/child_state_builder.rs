@@ -1,16 +1,32 @@
 use super::*;
-use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Builder for child states in hierarchical machines
-pub struct ChildStateBuilder<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> {
+pub struct ChildStateBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
     pub parent_builder: StateBuilder<C, E>,
     pub child_id: String,
     pub transitions: Vec<Transition<C, E>>,
-    pub entry_actions: Vec<Box<dyn Action<C, E>>>,
-    pub exit_actions: Vec<Box<dyn Action<C, E>>>,
+    pub entry_actions: Vec<Arc<dyn Action<C, E>>>,
+    pub exit_actions: Vec<Arc<dyn Action<C, E>>>,
+    pub is_final: bool,
+    pub forbidden_events: Vec<MatchedEvent<E>>,
+    pub deferred_events: Vec<MatchedEvent<E>>,
+    pub tags: Vec<String>,
+    #[cfg(feature = "serde_json")]
+    pub meta: Option<serde_json::Value>,
+    pub history: Option<HistoryState>,
 }
 
-impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> ChildStateBuilder<C, E> {
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > ChildStateBuilder<C, E>
+{
     pub fn new(parent_builder: StateBuilder<C, E>, child_id: String) -> Self {
         Self {
             parent_builder,
@@ -18,20 +34,35 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> ChildSt
             transitions: Vec::new(),
             entry_actions: Vec::new(),
             exit_actions: Vec::new(),
+            is_final: false,
+            forbidden_events: Vec::new(),
+            deferred_events: Vec::new(),
+            tags: Vec::new(),
+            #[cfg(feature = "serde_json")]
+            meta: None,
+            history: None,
         }
     }
 
+    /// Transition to `target` on `event`; see `on_event` for a transition without one
     pub fn on(self, event: E, target: &str) -> ChildTransitionBuilder<C, E> {
         ChildTransitionBuilder::new(self, event, target.to_string())
     }
 
+    /// Handle `event` without leaving this state
+    ///
+    /// The targetless form of `on`, see `StateBuilder::on_event`.
+    pub fn on_event(self, event: E) -> ChildTransitionBuilder<C, E> {
+        ChildTransitionBuilder::new(self, event, String::new()).internal()
+    }
+
     pub fn on_entry<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.entry_actions.push(Box::new(action));
+        self.entry_actions.push(Arc::new(action));
         self
     }
 
     pub fn on_exit<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.exit_actions.push(Box::new(action));
+        self.exit_actions.push(Arc::new(action));
         self
     }
 
@@ -41,7 +72,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
@@ -51,29 +82,31 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
-    /// Add a log entry action
-    pub fn on_entry_log(mut self, message: impl Into<String>) -> Self
+    /// Add a function-based entry action that can raise follow-up events or send
+    /// events to other machines
+    pub fn on_entry_with_context<F>(mut self, func: F) -> Self
     where
-        C: std::fmt::Debug,
-        E: std::fmt::Debug,
+        F: Fn(&mut C, &E, &mut ActionContext<E>) + Clone + Send + Sync + 'static,
     {
+        self.entry_actions.push(Arc::new(ContextAction::new(func)));
+        self
+    }
+
+    /// Add a log entry action
+    pub fn on_entry_log(mut self, message: impl Into<String>) -> Self {
         self.entry_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message.into())));
         self
     }
 
     /// Add a log exit action
-    pub fn on_exit_log(mut self, message: impl Into<String>) -> Self
-    where
-        C: std::fmt::Debug,
-        E: std::fmt::Debug,
-    {
+    pub fn on_exit_log(mut self, message: impl Into<String>) -> Self {
         self.exit_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message.into())));
         self
     }
 
@@ -83,7 +116,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
@@ -93,43 +126,295 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
-    pub fn child_state(self, id: &str) -> ChildStateBuilder<C, E> {
-        // Finish current child state
-        let child_node = StateNode {
-            id: self.child_id.clone(),
-            transitions: self.transitions,
-            entry_actions: self.entry_actions,
-            exit_actions: self.exit_actions,
-            child_states: HashMap::new(),
-            initial_child: None,
-            _phantom: std::marker::PhantomData,
-        };
+    /// Swallow `event` in this state instead of letting it bubble up to the parent
+    pub fn forbid(self, event: E) -> Self {
+        self.forbid_matching(event, EventMatcher::Exact)
+    }
 
-        let mut parent = self.parent_builder;
-        parent.child_states.insert(self.child_id, child_node);
+    /// Forbid every event `matcher` matches against `event`
+    pub fn forbid_matching(mut self, event: E, matcher: EventMatcher<E>) -> Self {
+        self.forbidden_events.push(MatchedEvent { event, matcher });
+        self
+    }
+
+    /// Hold `event` while this child is active, replaying it once it is left
+    pub fn defer(self, event: E) -> Self {
+        self.defer_matching(event, EventMatcher::Exact)
+    }
+
+    /// Defer every event `matcher` matches against `event`
+    pub fn defer_matching(mut self, event: E, matcher: EventMatcher<E>) -> Self {
+        self.deferred_events.push(MatchedEvent { event, matcher });
+        self
+    }
+
+    /// Tag this child state, so `has_tag` reports it while the child is active
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Attach metadata to this child state, available from `Machine::meta`
+    #[cfg(feature = "serde_json")]
+    pub fn meta(mut self, meta: serde_json::Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Make this child a history pseudo-state of the parent
+    ///
+    /// Transitions targeting it re-enter the parent's children as they were when the
+    /// parent was last exited: only the direct child with shallow history, the
+    /// whole configuration below the parent with deep history.
+    pub fn history(mut self, history_type: HistoryType) -> Self {
+        self.history = Some(HistoryState::new(self.child_id.clone(), history_type));
+        self
+    }
+
+    /// Set the sibling entered through this history state before the parent has been
+    /// exited, instead of the parent's initial child
+    pub fn history_default(mut self, target: &str) -> Self {
+        let id = self.child_id.clone();
+        self.history
+            .get_or_insert_with(|| HistoryState::shallow(id))
+            .default_target = Some(target.to_string());
+        self
+    }
+
+    /// Mark this child as a final state
+    ///
+    /// Entering it completes the parent, which then takes its `on_done` transition.
+    pub fn final_state(mut self) -> Self {
+        self.is_final = true;
+        self
+    }
+
+    /// Transition to `target` once this child has been active for `delay`
+    pub fn after(self, delay: Duration, target: &str) -> DelayedTransitionBuilder<C, E> {
+        self.into_state().after(delay, target)
+    }
+
+    /// Transition to `target` as soon as this child is entered and the guards pass
+    pub fn always(self, target: &str) -> AlwaysTransitionBuilder<C, E> {
+        self.into_state().always(target)
+    }
+
+    /// Transition to `target` on any event this child has no transition for
+    pub fn on_any(self, target: &str) -> AnyTransitionBuilder<C, E, StateBuilder<C, E>> {
+        self.into_state().on_any(target)
+    }
+
+    /// Invoke an async service while this child is active, see `StateBuilder::invoke`
+    pub fn invoke<F, Fut, T, X>(self, service: F) -> InvokeBuilder<C, E, T, X>
+    where
+        F: Fn(&C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, X>> + MaybeSend + 'static,
+        T: 'static,
+        X: 'static,
+    {
+        self.into_state().invoke(service)
+    }
 
-        // Start new child state
-        parent.child_state(id)
+    /// Give this child state children of its own, entering `child_id` first
+    ///
+    /// Returns a builder for this child, so `child_state` declares its children and
+    /// `parent()` returns to the level above. States can be nested to any depth.
+    pub fn initial_child(self, child_id: &str) -> StateBuilder<C, E> {
+        self.into_state().initial_child(child_id)
+    }
+
+    /// Add a child state to this child, one level further down
+    ///
+    /// Each `child_state` opens a level and each `parent()` closes one, so siblings
+    /// are separated by `parent()`:
+    ///
+    /// ```ignore
+    /// .state("app")
+    ///     .child_state("settings")
+    ///         .child_state("profile")
+    ///             .child_state("editing")
+    ///             .parent()
+    ///         .parent()
+    ///         .child_state("privacy")
+    ///         .parent()
+    ///     .parent()
+    /// ```
+    pub fn child_state(self, id: &str) -> ChildStateBuilder<C, E> {
+        self.into_state().child_state(id)
     }
 
+    /// Finish this child state and return to its parent
     pub fn parent(self) -> StateBuilder<C, E> {
-        // Finish current child state and return to parent
-        let child_node = StateNode {
-            id: self.child_id.clone(),
-            transitions: self.transitions,
-            entry_actions: self.entry_actions,
-            exit_actions: self.exit_actions,
-            child_states: HashMap::new(),
-            initial_child: None,
-            _phantom: std::marker::PhantomData,
-        };
+        self.into_state().parent()
+    }
 
+    /// Continue building this child with a `StateBuilder` nested in the parent
+    ///
+    /// The nested builder supports everything a state does, and `parent()` adds the
+    /// finished child to the parent with all of it.
+    fn into_state(self) -> StateBuilder<C, E> {
         let mut parent = self.parent_builder;
-        parent.child_states.insert(self.child_id, child_node);
-        parent
+        // The nested state carries the machine builder until it is finished
+        let machine_builder = std::mem::take(&mut parent.machine_builder);
+
+        let mut state = StateBuilder::new(machine_builder, self.child_id);
+        state.transitions = self.transitions;
+        state.entry_actions = self.entry_actions;
+        state.exit_actions = self.exit_actions;
+        state.is_final = self.is_final;
+        state.forbidden_events = self.forbidden_events;
+        state.deferred_events = self.deferred_events;
+        state.tags = self.tags;
+        #[cfg(feature = "serde_json")]
+        {
+            state.meta = self.meta;
+        }
+        state.history = self.history;
+        state.parent_state = Some(Box::new(parent));
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::states::StateValue;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+        Increment,
+    }
+
+    fn checkout_machine() -> Machine<TestContext, TestEvent, TestContext> {
+        MachineBuilder::<TestContext, TestEvent>::new()
+            .state("cart")
+            .on(TestEvent::Start, "checkout.payment.card")
+            .on(TestEvent::Increment, "checkout")
+            .state("checkout")
+            .initial_child("shipping")
+            .child_state("shipping")
+            .on(TestEvent::Increment, "payment")
+            .parent()
+            .child_state("payment")
+            .initial_child("card")
+            .child_state("card")
+            .on(TestEvent::Increment, "paypal")
+            .parent()
+            .child_state("paypal")
+            .on(TestEvent::Stop, "cart")
+            .parent()
+            .parent()
+            .initial("cart")
+            .build()
+    }
+
+    #[test]
+    fn nested_states_enter_initial_children_at_every_level() {
+        let machine = checkout_machine();
+        assert!(machine.state_at("checkout.payment.card").is_some());
+
+        let shipping = machine.transition(&machine.initial_state(), TestEvent::Increment);
+        assert_eq!(shipping.value().to_string(), "checkout.shipping");
+
+        let payment = machine.step(&shipping, TestEvent::Increment);
+        assert_eq!(payment.state.value().to_string(), "checkout.payment.card");
+        assert_eq!(payment.exited, vec!["checkout.shipping"]);
+        assert_eq!(
+            payment.entered,
+            vec!["checkout.payment", "checkout.payment.card"]
+        );
+    }
+
+    #[test]
+    fn bare_ids_only_name_states_around_the_active_branch() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("checkout")
+            .initial_child("paying")
+            .child_state("paying")
+            .on(TestEvent::Start, "paid")
+            .parent()
+            .child_state("paid")
+            .final_state()
+            .parent()
+            .initial("checkout")
+            .build();
+
+        let paid = machine.transition(&machine.initial_state(), TestEvent::Start);
+        assert!(paid.matches("checkout.paid"));
+        assert!(machine.is_done(&paid));
+
+        // A top-level "paid" does not exist; the nested one needs its dotted path
+        let stray = MachineStateImpl::new(
+            StateValue::Simple("paid".to_string()),
+            TestContext::default(),
+        );
+        assert!(!machine.is_done(&stray));
+    }
+
+    #[test]
+    fn child_states_nest_until_parent_closes_them() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("app")
+            .initial_child("settings")
+            .child_state("settings")
+            .initial_child("profile")
+            .child_state("profile")
+            .initial_child("viewing")
+            .child_state("viewing")
+            .on(TestEvent::Start, "editing")
+            .parent()
+            .child_state("editing")
+            .on(TestEvent::Stop, "app.settings.privacy")
+            .parent()
+            .parent()
+            .child_state("privacy")
+            .parent()
+            .parent()
+            .initial("app")
+            .build();
+
+        let app = &machine.states["app"];
+        let settings = &app.child_states["settings"];
+        assert_eq!(app.child_states.len(), 1);
+        assert_eq!(settings.child_states.len(), 2);
+        assert_eq!(settings.child_states["profile"].child_states.len(), 2);
+
+        let viewing = machine.initial_state();
+        assert!(viewing.matches("app.settings.profile.viewing"));
+        let editing = machine.transition(&viewing, TestEvent::Start);
+        assert!(editing.matches("app.settings.profile.editing"));
+        let privacy = machine.transition(&editing, TestEvent::Stop);
+        assert!(privacy.matches("app.settings.privacy"));
+    }
+
+    #[test]
+    fn transitions_target_dotted_paths_and_relative_siblings() {
+        let machine = checkout_machine();
+
+        let card = machine.step(&machine.initial_state(), TestEvent::Start);
+        assert_eq!(card.state.value().to_string(), "checkout.payment.card");
+        assert_eq!(
+            card.entered,
+            vec!["checkout", "checkout.payment", "checkout.payment.card"]
+        );
+
+        let paypal = machine.transition(&card.state, TestEvent::Increment);
+        assert_eq!(paypal.value().to_string(), "checkout.payment.paypal");
+
+        let cart = machine.step(&paypal, TestEvent::Stop);
+        assert_eq!(cart.state.value().to_string(), "cart");
+        assert_eq!(
+            cart.exited,
+            vec!["checkout.payment.paypal", "checkout.payment", "checkout"]
+        );
     }
 }
@@ -1,29 +1,41 @@
 use super::*;
+use std::sync::Arc;
 
 /// Transition builder for child states
-pub struct ChildTransitionBuilder<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> {
+pub struct ChildTransitionBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
     child_builder: ChildStateBuilder<C, E>,
     event: E,
+    matcher: EventMatcher<E>,
     target: String,
-    guards: Vec<Box<dyn Guard<C, E>>>,
-    actions: Vec<Box<dyn Action<C, E>>>,
+    internal: bool,
+    guards: Vec<Arc<dyn Guard<C, E>>>,
+    actions: Vec<Arc<dyn Action<C, E>>>,
+    on_error: Option<ErrorTransition<E>>,
 }
 
-impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
-    ChildTransitionBuilder<C, E>
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > ChildTransitionBuilder<C, E>
 {
     pub fn new(child_builder: ChildStateBuilder<C, E>, event: E, target: String) -> Self {
         Self {
             child_builder,
             event,
+            matcher: EventMatcher::Exact,
             target,
+            internal: false,
             guards: Vec::new(),
             actions: Vec::new(),
+            on_error: None,
         }
     }
 
     pub fn guard<G: Guard<C, E> + 'static>(mut self, guard: G) -> Self {
-        self.guards.push(Box::new(guard));
+        self.guards.push(Arc::new(guard));
         self
     }
 
@@ -32,7 +44,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
     where
         F: Fn(&C, &E) -> bool + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FunctionGuard::new(func)));
+        self.guards.push(Arc::new(guards::FunctionGuard::new(func)));
         self
     }
 
@@ -42,7 +54,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
         F: Fn(&C) -> T + Clone + Send + Sync + 'static,
         T: PartialEq + Clone + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FieldEqualityGuard::new(
+        self.guards.push(Arc::new(guards::FieldEqualityGuard::new(
             field_extractor,
             expected_value,
         )));
@@ -56,52 +68,141 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
         T: PartialOrd + Send + Sync + 'static,
     {
         self.guards
-            .push(Box::new(guards::RangeGuard::new(field_extractor, min, max)));
+            .push(Arc::new(guards::RangeGuard::new(field_extractor, min, max)));
         self
     }
 
     /// Add a time limit guard
     pub fn guard_time_limit(mut self, duration: std::time::Duration) -> Self {
-        self.guards.push(Box::new(guards::TimeGuard::new(duration)));
+        self.guards.push(Arc::new(guards::TimeGuard::new(duration)));
         self
     }
 
     /// Add a counter guard
     pub fn guard_max_transitions(mut self, max_count: usize) -> Self {
         self.guards
-            .push(Box::new(guards::CounterGuard::new(max_count)));
+            .push(Arc::new(guards::CounterGuard::new(max_count)));
+        self
+    }
+
+    /// Trigger on any event of the same enum variant, whatever its payload
+    ///
+    /// `on(Submit(FormData::default()), "sending").match_variant()` takes every `Submit`.
+    pub fn match_variant(mut self) -> Self {
+        self.matcher = EventMatcher::Variant;
+        self
+    }
+
+    /// Trigger on any event reporting the same `Event::event_type()`
+    pub fn match_event_type(mut self) -> Self
+    where
+        E: Event,
+    {
+        self.matcher = EventMatcher::EventType(E::event_type);
+        self
+    }
+
+    /// Do not exit and re-enter the source state when taking this transition
+    ///
+    /// Targeting the source itself only runs the transition actions; targeting one
+    /// of its descendants only exits and enters the states below the source.
+    pub fn internal(mut self) -> Self {
+        self.internal = true;
         self
     }
 
     pub fn action<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.actions.push(Box::new(action));
+        self.actions.push(Arc::new(action));
+        self
+    }
+
+    /// Add a function-based action that can fail
+    pub fn try_action_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C, &E) -> Result<(), ActionError> + Clone + Send + Sync + 'static,
+    {
+        self.actions
+            .push(Arc::new(actions::FallibleAction::new(func)));
+        self
+    }
+
+    /// Go to `target` instead when an action fails while taking this transition
+    ///
+    /// Everything the failed attempt changed is rolled back, including the context.
+    /// The error target's entry actions receive the failure as an `E`.
+    pub fn on_error(mut self, target: &str) -> Self
+    where
+        E: From<ActionError>,
+    {
+        self.on_error = Some(ErrorTransition {
+            target: target.to_string(),
+            event: E::from,
+        });
+        self
+    }
+
+    /// Add a function-based action that can raise follow-up events or send events
+    /// to other machines
+    pub fn action_with_context<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C, &E, &mut ActionContext<E>) + Clone + Send + Sync + 'static,
+    {
+        self.actions.push(Arc::new(ContextAction::new(func)));
         self
     }
 
     pub fn on(self, event: E, target: &str) -> ChildTransitionBuilder<C, E> {
-        let transition = Transition {
-            event: self.event,
-            target: self.target,
-            guards: self.guards,
-            actions: self.actions,
-        };
+        ChildTransitionBuilder::new(self.finish(), event, target.to_string())
+    }
 
-        let mut child_builder = self.child_builder;
-        child_builder.transitions.push(transition);
+    /// Finish the current transition and add a targetless transition to the current state
+    pub fn on_event(self, event: E) -> ChildTransitionBuilder<C, E> {
+        self.finish().on_event(event)
+    }
+
+    /// Finish the current transition and forbid `event` from bubbling up from the child
+    pub fn forbid(self, event: E) -> ChildStateBuilder<C, E> {
+        self.finish().forbid(event)
+    }
 
-        ChildTransitionBuilder::new(child_builder, event, target.to_string())
+    /// Finish the current transition and defer `event` in the child
+    pub fn defer(self, event: E) -> ChildStateBuilder<C, E> {
+        self.finish().defer(event)
+    }
+
+    /// Finish the current transition and defer every event `matcher` matches
+    pub fn defer_matching(self, event: E, matcher: EventMatcher<E>) -> ChildStateBuilder<C, E> {
+        self.finish().defer_matching(event, matcher)
+    }
+
+    /// Finish the current transition and add a child state to the current child
+    pub fn child_state(self, id: &str) -> ChildStateBuilder<C, E> {
+        self.finish().child_state(id)
+    }
+
+    /// Finish the current transition and give the child state children of its own
+    pub fn initial_child(self, child_id: &str) -> StateBuilder<C, E> {
+        self.finish().initial_child(child_id)
     }
 
     pub fn parent(self) -> StateBuilder<C, E> {
+        self.finish().parent()
+    }
+
+    /// Finish the current transition and add it to the child state
+    pub(crate) fn finish(self) -> ChildStateBuilder<C, E> {
         let transition = Transition {
             event: self.event,
+            matcher: self.matcher,
             target: self.target,
+            internal: self.internal,
             guards: self.guards,
             actions: self.actions,
+            on_error: self.on_error,
         };
 
         let mut child_builder = self.child_builder;
         child_builder.transitions.push(transition);
-        child_builder.parent()
+        child_builder
     }
 }
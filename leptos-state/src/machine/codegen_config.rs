@@ -260,7 +260,7 @@ impl<C, E> {machine_name}<C, E> {{
 "#
             .to_string(),
             state_template: r#"
-    /// State: {state_name}
+    /// State: {state_name}{state_doc}
     const {state_name}: &str = "{state_name}";
 "#
             .to_string(),
@@ -342,7 +342,7 @@ export class {machine_name}<C, E> {{
 "#
             .to_string(),
             state_template: r#"
-    // State: {state_name}
+    // State: {state_name}{state_doc}
     public static readonly {state_name}: string = "{state_name}";
 "#
             .to_string(),
@@ -412,7 +412,7 @@ class {machine_name}:
 "#
             .to_string(),
             state_template: r#"
-    # State: {state_name}
+    # State: {state_name}{state_doc}
     {state_name} = "{state_name}"
 "#
             .to_string(),
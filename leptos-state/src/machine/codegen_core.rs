@@ -151,8 +151,21 @@ from enum import Enum
         let mut result = String::new();
 
         for state_name in machine.get_states() {
+            let state = match machine.states_map().get(&state_name) {
+                Some(node) => StateGenInfo::from_node(state_name.clone(), node),
+                None => StateGenInfo::new(state_name.clone()),
+            };
+            let mut doc = String::new();
+            if let Some(description) = &state.description {
+                doc.push_str(&format!(" - {}", description));
+            }
+            if !state.tags.is_empty() {
+                doc.push_str(&format!(" (tags: {})", state.tags.join(", ")));
+            }
+
             let mut state_code = self.templates.state_template.clone();
-            state_code = state_code.replace("{state_name}", state_name);
+            state_code = state_code.replace("{state_name}", &state_name);
+            state_code = state_code.replace("{state_doc}", &doc);
             result.push_str(&state_code);
             result.push('\n');
         }
@@ -187,6 +187,8 @@ pub struct StateGenInfo {
     pub is_initial: bool,
     /// Whether this is a final state
     pub is_final: bool,
+    /// Tags declared on the state
+    pub tags: Vec<String>,
 }
 
 impl StateGenInfo {
@@ -201,9 +203,38 @@ impl StateGenInfo {
             metadata: std::collections::HashMap::new(),
             is_initial: false,
             is_final: false,
+            tags: Vec::new(),
         }
     }
 
+    /// Create state info from a state node, including its tags and metadata
+    ///
+    /// Top-level metadata entries become `metadata`, with strings unquoted; a
+    /// `description` entry becomes the description.
+    pub fn from_node<C, E>(name: String, node: &StateNode<C, E, C>) -> Self
+    where
+        C: Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Clone + std::fmt::Debug + 'static,
+    {
+        let mut info = Self::new(name).final_state(node.is_final);
+        info.child_states = node.child_states.keys().cloned().collect();
+        info.tags = node.tags.clone();
+        #[cfg(feature = "serde_json")]
+        if let Some(serde_json::Value::Object(entries)) = &node.meta {
+            for (key, value) in entries {
+                let value = match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                if key == "description" {
+                    info.description = Some(value.clone());
+                }
+                info.metadata.insert(key.clone(), value);
+            }
+        }
+        info
+    }
+
     /// Set description
     pub fn with_description(mut self, description: String) -> Self {
         self.description = Some(description);
@@ -234,6 +265,12 @@ impl StateGenInfo {
         self
     }
 
+    /// Add tag
+    pub fn add_tag(mut self, tag: String) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
     /// Mark as initial state
     pub fn initial(mut self, initial: bool) -> Self {
         self.is_initial = initial;
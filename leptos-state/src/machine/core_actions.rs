@@ -1,6 +1,29 @@
+use crate::machine::action_context::ActionContext;
+use crate::machine::core_errors::ActionError;
+
 /// Action trait for state changes
 pub trait Action<C, E>: Send + Sync {
     fn execute(&self, context: &mut C, event: &E);
+    /// Execute, reporting a failure instead of panicking or ignoring it
+    ///
+    /// Fallible actions override this. The default runs `execute` and succeeds.
+    fn try_execute(&self, context: &mut C, event: &E) -> Result<(), ActionError> {
+        self.execute(context, event);
+        Ok(())
+    }
+    /// Execute as part of a running machine, with access to the action context
+    ///
+    /// Override this to raise follow-up events or send events to other machines.
+    /// The default runs `try_execute`.
+    fn execute_in(
+        &self,
+        context: &mut C,
+        event: &E,
+        effects: &mut ActionContext<E>,
+    ) -> Result<(), ActionError> {
+        let _ = effects;
+        self.try_execute(context, event)
+    }
     fn name(&self) -> &str;
     fn description(&self) -> String {
         self.name().to_string()
@@ -10,3 +33,9 @@ pub trait Action<C, E>: Send + Sync {
     }
     fn clone_action(&self) -> Box<dyn Action<C, E>>;
 }
+
+impl<C, E> std::fmt::Debug for dyn Action<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Action").field(&self.name()).finish()
+    }
+}
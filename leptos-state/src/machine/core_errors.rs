@@ -7,6 +7,13 @@ pub enum MachineError {
     MissingGuard(String),
     MissingAction(String),
     ContextError(String),
+    EventlessLoop(String),
+    RaisedEventLoop(String),
+    /// Actors were still messaging each other, the last time the named actor
+    ActorMessageLoop(String),
+    ActionFailed(ActionError),
+    /// The named invariant rejected the context a transition produced
+    InvariantViolated(String),
 }
 
 impl std::fmt::Display for MachineError {
@@ -18,6 +25,17 @@ impl std::fmt::Display for MachineError {
             MachineError::MissingGuard(s) => write!(f, "Missing guard: {}", s),
             MachineError::MissingAction(s) => write!(f, "Missing action: {}", s),
             MachineError::ContextError(s) => write!(f, "Context error: {}", s),
+            MachineError::EventlessLoop(s) => {
+                write!(f, "Eventless transitions did not settle: {}", s)
+            }
+            MachineError::RaisedEventLoop(s) => {
+                write!(f, "Raised events did not settle: {}", s)
+            }
+            MachineError::ActorMessageLoop(s) => {
+                write!(f, "Actor messages did not settle: {}", s)
+            }
+            MachineError::ActionFailed(e) => write!(f, "{}", e),
+            MachineError::InvariantViolated(s) => write!(f, "Invariant violated: {}", s),
         }
     }
 }
@@ -25,3 +43,149 @@ impl std::fmt::Display for MachineError {
 impl std::error::Error for MachineError {}
 
 pub type MachineResult<T> = Result<T, MachineError>;
+
+/// Failure reported by a fallible action
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionError {
+    /// Name of the action that failed, filled in by the machine
+    pub action: String,
+    pub message: String,
+    /// Set when the event should be delivered again after this delay instead of
+    /// taking the transition's error target
+    pub retry_after: Option<std::time::Duration>,
+}
+
+impl ActionError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            action: String::new(),
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// Ask the runtime to deliver the event again after `delay`
+    pub fn retry_after(mut self, delay: std::time::Duration) -> Self {
+        self.retry_after = Some(delay);
+        self
+    }
+}
+
+impl std::fmt::Display for ActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.action.is_empty() {
+            write!(f, "Action failed: {}", self.message)
+        } else {
+            write!(f, "Action {} failed: {}", self.action, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ActionError {}
+
+/// Structural problem that makes a machine definition invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineDefinitionError {
+    /// No initial state was set
+    EmptyInitial,
+    /// The initial state does not exist
+    UnknownInitial(String),
+    /// A transition of the state at `state` targets a state that does not exist
+    UnknownTarget { state: String, target: String },
+    /// The initial child of the state at `state` does not exist
+    UnknownInitialChild { state: String, child: String },
+    /// Two states were declared with the same path; the last one wins
+    DuplicateState(String),
+    /// The compound state at this path has no initial child
+    MissingInitialChild(String),
+    /// The state at `state` has a type the engine cannot run
+    UnsupportedStateType { state: String, state_type: String },
+    /// A transition of the state at `state` needs a target but has none
+    MissingTarget { state: String },
+    /// A transition of the state at `state` names no event
+    MissingEvent { state: String },
+    /// A transition starts from a state that does not exist
+    UnknownSource(String),
+    /// A transition of the state at `state` names an event with no implementation
+    UnknownEvent { state: String, event: String },
+    /// The state at `state` names a guard with no implementation
+    UnknownGuard { state: String, guard: String },
+    /// The state at `state` names an action with no implementation
+    UnknownAction { state: String, action: String },
+    /// The definition could not be parsed
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for MachineDefinitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineDefinitionError::EmptyInitial => write!(f, "No initial state set"),
+            MachineDefinitionError::UnknownInitial(s) => write!(f, "Unknown initial state: {}", s),
+            MachineDefinitionError::UnknownTarget { state, target } => {
+                write!(
+                    f,
+                    "Transition from {} targets unknown state: {}",
+                    state, target
+                )
+            }
+            MachineDefinitionError::UnknownInitialChild { state, child } => {
+                write!(f, "Unknown initial child of {}: {}", state, child)
+            }
+            MachineDefinitionError::DuplicateState(s) => write!(f, "Duplicate state: {}", s),
+            MachineDefinitionError::MissingInitialChild(s) => {
+                write!(f, "Compound state {} has no initial child", s)
+            }
+            MachineDefinitionError::UnsupportedStateType { state, state_type } => {
+                write!(f, "State {} has unsupported type: {}", state, state_type)
+            }
+            MachineDefinitionError::MissingTarget { state } => {
+                write!(f, "Transition from {} has no target", state)
+            }
+            MachineDefinitionError::MissingEvent { state } => {
+                write!(f, "Transition from {} has no event", state)
+            }
+            MachineDefinitionError::UnknownSource(s) => {
+                write!(f, "Transition from unknown state: {}", s)
+            }
+            MachineDefinitionError::UnknownEvent { state, event } => {
+                write!(f, "Transition from {} uses unknown event: {}", state, event)
+            }
+            MachineDefinitionError::UnknownGuard { state, guard } => {
+                write!(f, "State {} uses unknown guard: {}", state, guard)
+            }
+            MachineDefinitionError::UnknownAction { state, action } => {
+                write!(f, "State {} uses unknown action: {}", state, action)
+            }
+            MachineDefinitionError::InvalidFormat(message) => {
+                write!(f, "Invalid machine definition: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MachineDefinitionError {}
+
+/// Suspicious but valid part of a machine definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineDefinitionWarning {
+    /// No transition ever enters the state at this path
+    UnreachableState(String),
+    /// The transition at `index` of the state at `state` can never be taken because
+    /// an earlier unguarded transition handles the same event
+    ShadowedTransition { state: String, index: usize },
+}
+
+impl std::fmt::Display for MachineDefinitionWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineDefinitionWarning::UnreachableState(s) => write!(f, "Unreachable state: {}", s),
+            MachineDefinitionWarning::ShadowedTransition { state, index } => {
+                write!(
+                    f,
+                    "Transition {} of {} is shadowed by an earlier transition",
+                    index, state
+                )
+            }
+        }
+    }
+}
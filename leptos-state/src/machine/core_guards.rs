@@ -3,3 +3,9 @@ pub trait Guard<C, E>: Send + Sync {
     fn check(&self, context: &C, event: &E) -> bool;
     fn name(&self) -> &str;
 }
+
+impl<C, E> std::fmt::Debug for dyn Guard<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Guard").field(&self.name()).finish()
+    }
+}
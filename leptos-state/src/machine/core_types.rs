@@ -1,7 +1,12 @@
 use super::*;
+use crate::machine::action_context::Delivery;
 use crate::machine::states::StateValue;
 use crate::StateResult;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// State node in the machine definition
 #[derive(Debug)]
@@ -12,10 +17,36 @@ pub struct StateNode<
 > {
     pub id: String,
     pub transitions: Vec<Transition<C, E>>,
-    pub entry_actions: Vec<Box<dyn Action<C, E>>>,
-    pub exit_actions: Vec<Box<dyn Action<C, E>>>,
+    pub entry_actions: Vec<Arc<dyn Action<C, E>>>,
+    pub exit_actions: Vec<Arc<dyn Action<C, E>>>,
     pub child_states: HashMap<String, StateNode<C, E, C>>,
     pub initial_child: Option<String>,
+    pub delayed_transitions: Vec<DelayedTransition<C>>,
+    pub always_transitions: Vec<AlwaysTransition<C>>,
+    /// Parallel states activate all of their child states (regions) at once
+    pub is_parallel: bool,
+    /// Final states complete their parent state
+    pub is_final: bool,
+    /// Target taken once the state's children have completed
+    pub done_target: Option<String>,
+    /// Transitions taken for any event the state has no transition for
+    pub any_transitions: Vec<AnyTransition<C, E>>,
+    /// Events that must not bubble up to the parent state
+    pub forbidden_events: Vec<MatchedEvent<E>>,
+    /// Events held while the state is active and replayed once it is left
+    pub deferred_events: Vec<MatchedEvent<E>>,
+    /// Services started when the state is entered and cancelled when it is exited
+    pub invocations: Vec<Invocation<C, E>>,
+    /// Tags reported by `MachineStateImpl::has_tag` while the state is active
+    pub tags: Vec<String>,
+    /// Free-form metadata for tooling and the UI (labels, docs, analytics ids)
+    #[cfg(feature = "serde_json")]
+    pub meta: Option<serde_json::Value>,
+    /// Makes this state a history pseudo-state of its parent
+    ///
+    /// Targeting it re-enters the parent's child configuration from when the parent
+    /// was last exited. It is never active itself.
+    pub history: Option<HistoryState>,
     pub _phantom: std::marker::PhantomData<S>,
 }
 
@@ -26,13 +57,171 @@ pub struct Transition<
     E: Send + Clone + std::fmt::Debug + 'static,
 > {
     pub event: E,
+    pub matcher: EventMatcher<E>,
+    /// Target state; empty for targetless transitions, see `StateBuilder::on_event`
     pub target: String,
-    pub guards: Vec<Box<dyn Guard<C, E>>>,
-    pub actions: Vec<Box<dyn Action<C, E>>>,
+    /// Internal transitions to their own state or a descendant do not exit the state
+    pub internal: bool,
+    pub guards: Vec<Arc<dyn Guard<C, E>>>,
+    pub actions: Vec<Arc<dyn Action<C, E>>>,
+    /// Taken instead when an action fails while taking this transition
+    pub on_error: Option<ErrorTransition<E>>,
 }
 
-/// Complete machine implementation
+/// Where a transition goes when one of its actions fails
+#[derive(Debug, Clone)]
+pub struct ErrorTransition<E> {
+    pub target: String,
+    /// Turns the failure into the event handed to the error target's entry actions
+    pub event: fn(ActionError) -> E,
+}
+
+/// How a transition decides whether an incoming event triggers it
+///
+/// Guards and actions always receive the full incoming event, whichever way it matched.
+#[derive(Debug)]
+pub enum EventMatcher<E> {
+    /// The event must equal the transition's event, payload included
+    Exact,
+    /// The event must be the same enum variant as the transition's event; payloads are ignored
+    Variant,
+    /// The event must report the same `Event::event_type()` as the transition's event
+    EventType(fn(&E) -> &str),
+}
+
+/// Event a state forbids or defers, matched against incoming events like a transition's
+#[derive(Debug, Clone)]
+pub struct MatchedEvent<E> {
+    pub event: E,
+    pub matcher: EventMatcher<E>,
+}
+
+/// Wildcard transition, taken for any event not handled by a more specific transition
+#[derive(Debug)]
+pub struct AnyTransition<C, E> {
+    pub target: String,
+    pub guards: Vec<Arc<dyn Guard<C, E>>>,
+    pub actions: Vec<Arc<dyn Action<C, E>>>,
+}
+
+/// Something the machine did on its own, handed to exit and entry actions as an event
+///
+/// Only when the machine was built with `MachineBuilder::signal_event`, which turns
+/// signals into the machine's event type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MachineSignal {
+    /// The machine is settling its initial state
+    Started,
+    /// `delay` elapsed while the state at the `source` path was active
+    DelayElapsed { source: String, delay: Duration },
+}
+
+/// Delayed ("after") transition, taken once its source state has been active for `delay`
+///
+/// Delayed transitions are not triggered by an event, so their guards and actions
+/// only see the context (they are evaluated with the unit event `()`).
 #[derive(Debug)]
+pub struct DelayedTransition<C> {
+    pub delay: Duration,
+    pub target: String,
+    pub guards: Vec<Arc<dyn Guard<C, ()>>>,
+    pub actions: Vec<Arc<dyn Action<C, ()>>>,
+}
+
+/// Eventless ("always") transition, taken as soon as its guards pass
+///
+/// Always transitions are checked whenever the machine settles after a
+/// transition (and on start), so like delayed transitions they only see the context.
+/// Exit and entry actions get the event that was being handled.
+#[derive(Debug)]
+pub struct AlwaysTransition<C> {
+    pub target: String,
+    pub guards: Vec<Arc<dyn Guard<C, ()>>>,
+    pub actions: Vec<Arc<dyn Action<C, ()>>>,
+}
+
+/// Future returned by an invoked service
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+pub type InvokeFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// Future returned by an invoked service
+///
+/// Not `Send` in the browser, so services can await `JsFuture`s and `fetch`.
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+pub type InvokeFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
+
+/// Invoked service, with its result already turned into an event
+pub type InvokeService<C, E> =
+    Arc<dyn Fn(&C) -> InvokeFuture<Option<InvokeEvent<E>>> + Send + Sync>;
+
+/// Event an invoked service finished with
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvokeEvent<E> {
+    /// The service succeeded; taken to the invocation's done target
+    Done(E),
+    /// The service failed; taken to the invocation's error target
+    Error(E),
+}
+
+/// Service invoked by a state
+///
+/// The runtime starts the service with the context the state was entered with and
+/// cancels it when the state is exited. Its result is turned into an event and
+/// taken to the done or error target.
+pub struct Invocation<C, E> {
+    pub done_target: Option<String>,
+    pub error_target: Option<String>,
+    /// Starts the service; resolves to `None` when the result has no target
+    pub service: InvokeService<C, E>,
+}
+
+impl<C, E> std::fmt::Debug for Invocation<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Invocation")
+            .field("done_target", &self.done_target)
+            .field("error_target", &self.error_target)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Named condition the context must satisfy after every transition
+pub struct Invariant<C> {
+    pub name: String,
+    pub check: Arc<dyn Fn(&C) -> bool + Send + Sync>,
+}
+
+/// The parts of an event-driven or wildcard transition needed to take it
+struct TransitionRef<'a, C, E> {
+    target: &'a str,
+    internal: bool,
+    actions: &'a [Arc<dyn Action<C, E>>],
+    on_error: Option<&'a ErrorTransition<E>>,
+}
+
+/// States declared side by side, by id
+type StateMap<C, E> = HashMap<String, StateNode<C, E, C>>;
+
+/// Maximum number of eventless transitions taken while settling a single step
+pub const MAX_EVENTLESS_STEPS: usize = 100;
+
+/// Maximum number of raised events processed while handling a single step
+pub const MAX_RAISED_EVENTS: usize = 100;
+
+/// Order in which a transition exits and enters states and runs its actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransitionSemantics {
+    /// Run the transition actions, then exit the source state and its active children,
+    /// then enter the target; targets outside the source's siblings also exit the
+    /// source's ancestors up to the least common ancestor
+    #[default]
+    Legacy,
+    /// SCXML ordering: exit every active state below the least common ancestor of
+    /// source and target (innermost first), run the transition actions, then enter
+    /// the states down to the target (outermost first)
+    Scxml,
+}
+
+/// Complete machine implementation
 pub struct Machine<
     C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
     E: Send + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
@@ -40,49 +229,213 @@ pub struct Machine<
 > {
     pub states: HashMap<String, StateNode<C, E, C>>,
     pub initial: String,
+    pub semantics: TransitionSemantics,
+    /// Machine-level wildcard transitions, taken when no active state handles an event
+    pub any_transitions: Vec<AnyTransition<C, E>>,
+    /// Hooks run around every event, in registration order
+    pub middleware: Vec<Arc<dyn MachineMiddleware<C, E>>>,
+    /// Conditions checked on the context after every transition
+    pub invariants: Vec<Invariant<C>>,
+    /// Panic on a violated invariant in debug builds instead of rejecting the event
+    pub assert_invariants: bool,
+    /// Turns machine signals into events for the exit and entry actions of delayed
+    /// transitions and of transitions taken on start; without it those do not run
+    pub signal_event: Option<fn(MachineSignal) -> E>,
     pub _phantom: std::marker::PhantomData<S>,
 }
 
-// Manual Clone implementation for Transition since trait objects can't be cloned
-impl<C: Clone + Default, E: Clone + Send> Clone for Transition<C, E> {
+impl<C: Clone + std::fmt::Debug + Default, E: Clone + Send + std::fmt::Debug> Transition<C, E> {
+    /// Whether the transition handles its event without leaving or entering any state
+    pub fn is_targetless(&self) -> bool {
+        self.target.is_empty()
+    }
+}
+
+// Manual Clone implementation for Transition; guards and actions are shared, not copied
+impl<C: Clone + std::fmt::Debug + Default, E: Clone + Send + std::fmt::Debug> Clone
+    for Transition<C, E>
+{
     fn clone(&self) -> Self {
         Self {
             event: self.event.clone(),
+            matcher: self.matcher,
             target: self.target.clone(),
-            guards: Vec::new(), // Can't clone trait objects, so we create empty vectors
-            actions: Vec::new(),
+            internal: self.internal,
+            guards: self.guards.clone(),
+            actions: self.actions.clone(),
+            on_error: self.on_error.clone(),
+        }
+    }
+}
+
+impl<E> Clone for EventMatcher<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for EventMatcher<E> {}
+
+impl<E: PartialEq> EventMatcher<E> {
+    /// Whether `event` triggers a transition declared with `expected`
+    pub fn matches(&self, expected: &E, event: &E) -> bool {
+        match self {
+            EventMatcher::Exact => expected == event,
+            EventMatcher::Variant => {
+                std::mem::discriminant(expected) == std::mem::discriminant(event)
+            }
+            EventMatcher::EventType(event_type) => event_type(expected) == event_type(event),
         }
     }
 }
 
-// Manual Clone implementation for StateNode since Action trait objects can't be cloned
-impl<C: Clone + Default, E: Clone + Send> Clone for StateNode<C, E, C> {
+impl<E: PartialEq> MatchedEvent<E> {
+    /// Whether `event` is the forbidden or deferred event
+    pub fn matches(&self, event: &E) -> bool {
+        self.matcher.matches(&self.event, event)
+    }
+}
+
+// Manual Clone implementation for StateNode; entry and exit actions are shared, not copied
+impl<C: Clone + std::fmt::Debug + Default, E: Clone + Send + std::fmt::Debug> Clone
+    for StateNode<C, E, C>
+{
     fn clone(&self) -> Self {
         Self {
             id: self.id.clone(),
             transitions: self.transitions.clone(),
-            entry_actions: Vec::new(), // Can't clone trait objects, so we create empty vectors
-            exit_actions: Vec::new(),
+            entry_actions: self.entry_actions.clone(),
+            exit_actions: self.exit_actions.clone(),
             child_states: self.child_states.clone(),
             initial_child: self.initial_child.clone(),
+            delayed_transitions: self.delayed_transitions.clone(),
+            always_transitions: self.always_transitions.clone(),
+            is_parallel: self.is_parallel,
+            is_final: self.is_final,
+            done_target: self.done_target.clone(),
+            any_transitions: self.any_transitions.clone(),
+            forbidden_events: self.forbidden_events.clone(),
+            deferred_events: self.deferred_events.clone(),
+            invocations: self.invocations.clone(),
+            tags: self.tags.clone(),
+            #[cfg(feature = "serde_json")]
+            meta: self.meta.clone(),
+            history: self.history.clone(),
             _phantom: std::marker::PhantomData,
         }
     }
 }
 
-// Manual Clone implementation for Machine since trait objects can't be cloned
-impl<C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static> Clone for Machine<C, E, C> {
+// Manual Clone implementation for AnyTransition; guards and actions are shared, not copied
+impl<C, E> Clone for AnyTransition<C, E> {
+    fn clone(&self) -> Self {
+        Self {
+            target: self.target.clone(),
+            guards: self.guards.clone(),
+            actions: self.actions.clone(),
+        }
+    }
+}
+
+// Manual Clone implementation for Invocation; clones share the service
+impl<C, E> Clone for Invocation<C, E> {
+    fn clone(&self) -> Self {
+        Self {
+            done_target: self.done_target.clone(),
+            error_target: self.error_target.clone(),
+            service: self.service.clone(),
+        }
+    }
+}
+
+// Manual Clone implementation for DelayedTransition; guards and actions are shared, not copied
+impl<C> Clone for DelayedTransition<C> {
+    fn clone(&self) -> Self {
+        Self {
+            delay: self.delay,
+            target: self.target.clone(),
+            guards: self.guards.clone(),
+            actions: self.actions.clone(),
+        }
+    }
+}
+
+// Manual Clone implementation for AlwaysTransition; guards and actions are shared, not copied
+impl<C> Clone for AlwaysTransition<C> {
+    fn clone(&self) -> Self {
+        Self {
+            target: self.target.clone(),
+            guards: self.guards.clone(),
+            actions: self.actions.clone(),
+        }
+    }
+}
+
+impl<C> std::fmt::Debug for Invariant<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Invariant")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// Manual Clone implementation for Invariant; clones share the check
+impl<C> Clone for Invariant<C> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            check: self.check.clone(),
+        }
+    }
+}
+
+// Manual Clone implementation for Machine; clones share guards and actions and behave identically
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > Clone for Machine<C, E, C>
+{
     fn clone(&self) -> Self {
         Self {
             states: self.states.clone(),
             initial: self.initial.clone(),
+            semantics: self.semantics,
+            any_transitions: self.any_transitions.clone(),
+            middleware: self.middleware.clone(),
+            invariants: self.invariants.clone(),
+            assert_invariants: self.assert_invariants,
+            signal_event: self.signal_event,
             _phantom: std::marker::PhantomData,
         }
     }
 }
 
+// Manual Debug implementation for Machine; middleware is only counted
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+        S: Clone + std::fmt::Debug,
+    > std::fmt::Debug for Machine<C, E, S>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Machine")
+            .field("states", &self.states)
+            .field("initial", &self.initial)
+            .field("semantics", &self.semantics)
+            .field("any_transitions", &self.any_transitions)
+            .field("middleware", &self.middleware.len())
+            .field("invariants", &self.invariants)
+            .field("assert_invariants", &self.assert_invariants)
+            .field("signal_event", &self.signal_event.is_some())
+            .finish()
+    }
+}
 
-impl<C: Send + Sync + Clone + std::fmt::Debug + Default + 'static, E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash> Machine<C, E, C> {
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    > Machine<C, E, C>
+{
     /// Get all state IDs in the machine
     pub fn get_states(&self) -> Vec<String> {
         self.states.keys().cloned().collect()
@@ -98,6 +451,78 @@ impl<C: Send + Sync + Clone + std::fmt::Debug + Default + 'static, E: Clone + st
         &self.states
     }
 
+    /// Find a state node by id, searching child states as well as top-level states
+    pub fn find_state(&self, id: &str) -> Option<&StateNode<C, E, C>> {
+        let mut pending = vec![&self.states];
+        while let Some(nodes) = pending.pop() {
+            if let Some(node) = nodes.get(id) {
+                return Some(node);
+            }
+            pending.extend(nodes.values().map(|node| &node.child_states));
+        }
+        None
+    }
+
+    /// Find a state node by its dotted path from the top level (e.g. `"editor.bold.on"`)
+    pub fn state_at(&self, path: &str) -> Option<&StateNode<C, E, C>> {
+        let mut segments = path.split('.');
+        let mut node = self.states.get(segments.next()?)?;
+        for segment in segments {
+            node = node.child_states.get(segment)?;
+        }
+        Some(node)
+    }
+
+    /// Get the delayed transitions declared on the state at `path`
+    pub fn delayed_transitions(&self, path: &str) -> &[DelayedTransition<C>] {
+        self.state_at(path)
+            .map(|node| node.delayed_transitions.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Get the metadata declared on the state at `path`
+    #[cfg(feature = "serde_json")]
+    pub fn meta(&self, path: &str) -> Option<&serde_json::Value> {
+        self.state_at(path).and_then(|node| node.meta.as_ref())
+    }
+
+    /// Get the tags declared on the state at `path`
+    pub fn tags(&self, path: &str) -> &[String] {
+        self.state_at(path)
+            .map(|node| node.tags.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Build the state for `value`, collecting the tags of every active state
+    fn state_with(
+        &self,
+        value: StateValue,
+        context: C,
+        history: BTreeMap<String, StateValue>,
+        deferred: Vec<AnyEvent>,
+    ) -> MachineStateImpl<C> {
+        let tags = value
+            .active_paths()
+            .iter()
+            .flat_map(|path| self.tags(path))
+            .cloned()
+            .collect();
+        MachineStateImpl {
+            value,
+            context,
+            tags,
+            history,
+            deferred,
+        }
+    }
+
+    /// Get the services invoked by the state at `path`
+    pub fn invocations(&self, path: &str) -> &[Invocation<C, E>] {
+        self.state_at(path)
+            .map(|node| node.invocations.as_slice())
+            .unwrap_or(&[])
+    }
+
     /// Export a diagram of the machine
     pub fn export_diagram(
         &self,
@@ -114,164 +539,1810 @@ impl<C: Send + Sync + Clone + std::fmt::Debug + Default + 'static, E: Clone + st
     where
         C: Default,
     {
-        MachineStateImpl {
-            value: StateValue::Simple(self.initial.clone()),
-            context: Default::default(),
-        }
+        self.initial_with_context(Default::default())
     }
 
     pub fn initial_with_context(&self, context: C) -> MachineStateImpl<C> {
-        MachineStateImpl {
-            value: StateValue::Simple(self.initial.clone()),
+        self.start_in(Delivery::default(), context).state
+    }
+
+    /// Settle the initial state with `context` and report what the always and done
+    /// transitions taken on the way did
+    ///
+    /// The initial state's own entry actions do not run; the states exited and entered
+    /// by those transitions run their actions with `MachineSignal::Started`, if the
+    /// machine turns signals into events.
+    ///
+    /// Runs as described by `delivery`: as the actor it names, if any, so events sent
+    /// by the actions reach the actor system instead of being lost.
+    pub(crate) fn start_in(&self, delivery: Delivery, context: C) -> TransitionOutcome<C> {
+        let history = BTreeMap::new();
+        let initial = self.state_with(
+            self.resolve_target_state(&self.states, "", &self.initial, &history),
             context,
+            history,
+            Vec::new(),
+        );
+
+        // Let always transitions out of the initial state settle before anyone sees it
+        let mut outcome = TransitionOutcome::new(&initial);
+        outcome.delivery = delivery;
+        match self.settle(initial.clone(), None, &mut outcome) {
+            Ok(settled) => {
+                let outcome = outcome.finish(settled);
+                self.report(&initial, None, &outcome);
+                outcome
+            }
+            Err(err) => {
+                tracing::error!("Failed to settle initial state: {}", err);
+                self.report_failure(None, &initial, &err);
+                TransitionOutcome::rejected(&initial, &err)
+            }
         }
     }
 
     /// Transition from one state to another based on an event
+    ///
+    /// If the eventless transitions taken afterwards never settle, the event is
+    /// rejected and the current state is returned; use `try_transition` to see the error.
     pub fn transition(&self, state: &MachineStateImpl<C>, event: E) -> MachineStateImpl<C>
     where
         E: PartialEq,
     {
-        match &state.value() {
-            StateValue::Simple(id) => self.transition_simple(state, id, event),
-            StateValue::Compound { parent, child } => {
-                self.transition_hierarchical(state, parent, child, event)
-            }
-            StateValue::Parallel(states) => {
-                // Handle parallel states by transitioning each active region
-                let mut new_states = Vec::new();
-                let mut context = state.context().clone();
-
-                for parallel_state in states {
-                    let temp_state = MachineStateImpl {
-                        value: parallel_state.clone(),
-                        context: context.clone(),
-                    };
-                    let transitioned = self.transition(&temp_state, event.clone());
-                    new_states.push(transitioned.value().clone());
-                    context = transitioned.context().clone();
-                }
-
-                MachineStateImpl {
-                    value: StateValue::Parallel(new_states),
-                    context,
-                }
-            }
-        }
+        self.step(state, event).state
     }
 
-    fn transition_simple(
+    /// Transition based on an event, then take always transitions until the state is stable
+    pub fn try_transition(
         &self,
         state: &MachineStateImpl<C>,
-        state_id: &str,
         event: E,
-    ) -> MachineStateImpl<C>
+    ) -> MachineResult<MachineStateImpl<C>>
     where
         E: PartialEq,
     {
-        if let Some(state_node) = self.states.get(state_id) {
-            // Look for a matching transition
-            for transition in &state_node.transitions {
-                if transition.event == event {
-                    // Check all guards
-                    let guards_pass = transition
-                        .guards
-                        .iter()
-                        .all(|guard| guard.check(state.context(), &event));
-
-                    if guards_pass {
-                        let mut new_context = state.context().clone();
-
-                        // Execute transition actions
-                        for action in &transition.actions {
-                            action.execute(&mut new_context, &event);
-                        }
-
-                        // Execute exit actions for current state
-                        for action in &state_node.exit_actions {
-                            action.execute(&mut new_context, &event);
-                        }
-
-                        // Determine target state value (simple or compound)
-                        let new_value = self.resolve_target_state(&transition.target);
+        self.try_step(state, event).map(|outcome| outcome.state)
+    }
 
-                        let new_state = MachineStateImpl {
-                            value: new_value,
-                            context: new_context,
-                        };
+    /// Process an event and report everything the step did
+    ///
+    /// If the eventless transitions taken afterwards never settle, the event is
+    /// rejected and an unchanged outcome is returned, naming the violated invariant if
+    /// that is what rejected it; use `try_step` to see the error.
+    pub fn step(&self, state: &MachineStateImpl<C>, event: E) -> TransitionOutcome<C>
+    where
+        E: PartialEq,
+    {
+        self.try_step(state, event).unwrap_or_else(|err| {
+            tracing::error!("Transition rejected: {}", err);
+            TransitionOutcome::rejected(state, &err)
+        })
+    }
 
-                        // Execute entry actions for target state
-                        return self.execute_entry_actions(new_state, &transition.target, &event);
-                    }
-                }
-            }
-        }
+    /// Process an event, take always transitions until the state is stable and report
+    /// everything the step did
+    ///
+    /// Events raised by actions are processed in order before this returns, so the
+    /// caller only sees the state once the machine has run to completion. Middleware
+    /// may rewrite or drop the event first; a dropped event returns an unchanged outcome.
+    /// Middleware sees every transition the step took once the step has succeeded.
+    pub fn try_step(
+        &self,
+        state: &MachineStateImpl<C>,
+        event: E,
+    ) -> MachineResult<TransitionOutcome<C>>
+    where
+        E: PartialEq,
+    {
+        self.try_step_in(Delivery::default(), state, event)
+    }
 
-        // No valid transition found, return current state
-        state.clone()
+    /// `try_step` for a later delivery of `event`, counting from 1
+    ///
+    /// Actions see the number through `ActionContext::attempt`, so a `RetryAction`
+    /// only gives up (and takes its error target) on its last attempt. Callers must
+    /// send the event again themselves when an action rejects it with
+    /// `ActionError::retry_after`; `TimedMachine` does so on its scheduler.
+    pub fn try_step_attempt(
+        &self,
+        state: &MachineStateImpl<C>,
+        event: E,
+        attempt: u32,
+    ) -> MachineResult<TransitionOutcome<C>>
+    where
+        E: PartialEq,
+    {
+        let delivery = Delivery {
+            attempt,
+            retries: true,
+            ..Delivery::default()
+        };
+        self.try_step_in(delivery, state, event)
     }
 
-    fn transition_hierarchical(
+    /// `try_step` for an event delivered as described by `delivery`
+    ///
+    /// Actors spawned by its actions get ids scoped under the actor it names, if any.
+    pub(crate) fn try_step_in(
         &self,
+        delivery: Delivery,
         state: &MachineStateImpl<C>,
-        parent_id: &str,
-        child: &StateValue,
         event: E,
-    ) -> MachineStateImpl<C>
+    ) -> MachineResult<TransitionOutcome<C>>
     where
         E: PartialEq,
     {
-        // First try child state transitions
-        let child_state = MachineStateImpl {
-            value: (*child).clone(),
-            context: state.context().clone(),
+        let Some(event) = self.intercept(state, event) else {
+            return Ok(TransitionOutcome::new(state));
         };
 
-        let child_transitioned = self.transition(&child_state, event.clone());
+        let mut outcome = TransitionOutcome::new(state);
+        outcome.delivery = delivery;
+        let settled = self
+            .macrostep(state, &event, &mut outcome)
+            .and_then(|next| self.run_to_completion(next, &mut outcome));
+        match settled {
+            Ok(settled) => {
+                let outcome = outcome.finish(settled);
+                self.report(state, Some(&event), &outcome);
+                Ok(outcome)
+            }
+            Err(err) => {
+                self.report_failure(Some(&event), state, &err);
+                Err(err)
+            }
+        }
+    }
 
-        // If child transitioned, update the compound state
-        if child_transitioned.value() != child {
-            return MachineStateImpl {
-                value: StateValue::Compound {
-                    parent: parent_id.to_string(),
-                    child: Box::new(child_transitioned.value().clone()),
-                },
-                context: child_transitioned.context().clone(),
-            };
+    /// Run `event` through the middleware, or `None` if one of them dropped it
+    fn intercept(&self, state: &MachineStateImpl<C>, event: E) -> Option<E> {
+        self.middleware.iter().try_fold(event, |event, middleware| {
+            middleware.before_event(state, event)
+        })
+    }
+
+    /// Remember the state a transition led to, for `report`
+    ///
+    /// `event` is `None` for eventless transitions. Nothing is kept without middleware.
+    fn record(
+        &self,
+        outcome: &mut TransitionOutcome<C>,
+        event: Option<&E>,
+        state: &MachineStateImpl<C>,
+    ) {
+        if !self.middleware.is_empty() {
+            let event = event.map(|event| AnyEvent::new(event.clone()));
+            outcome.microsteps.push((event, state.clone()));
+        }
+    }
+
+    /// Hand the failures and transitions of a step that started in `start` and
+    /// succeeded to the middleware
+    ///
+    /// Failures handled by an error target are reported with `event`, the event the
+    /// step handled; transitions with the event that triggered each of them.
+    fn report(
+        &self,
+        start: &MachineStateImpl<C>,
+        event: Option<&E>,
+        outcome: &TransitionOutcome<C>,
+    ) {
+        for middleware in &self.middleware {
+            for error in &outcome.errors {
+                middleware.on_action_error(event, error);
+            }
         }
 
-        // If child didn't transition, try parent transitions
-        self.transition_simple(state, parent_id, event)
+        let mut old = start;
+        for (event, new) in &outcome.microsteps {
+            let event = event.as_ref().and_then(|event| event.downcast_ref::<E>());
+            for middleware in &self.middleware {
+                middleware.after_transition(event, old, new);
+            }
+            old = new;
+        }
     }
 
-    fn resolve_target_state(&self, target: &str) -> StateValue {
-        if let Some(state_node) = self.states.get(target) {
-            if !state_node.child_states.is_empty() {
-                // This is a compound state, resolve initial child
-                if let Some(initial_child) = &state_node.initial_child {
-                    return StateValue::Compound {
-                        parent: target.to_string(),
-                        child: Box::new(self.resolve_target_state(initial_child)),
-                    };
+    /// Hand the action failure or violated invariant that rejected a step from
+    /// `state` to the middleware
+    fn report_failure(&self, event: Option<&E>, state: &MachineStateImpl<C>, err: &MachineError) {
+        for middleware in &self.middleware {
+            match err {
+                MachineError::ActionFailed(error) => middleware.on_action_error(event, error),
+                MachineError::InvariantViolated(name) => {
+                    middleware.on_invariant_violated(event, state, name)
                 }
+                _ => {}
             }
         }
+    }
 
-        StateValue::Simple(target.to_string())
+    /// Take the transitions enabled by `event`, then always transitions until the
+    /// state is stable
+    fn macrostep(
+        &self,
+        state: &MachineStateImpl<C>,
+        event: &E,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<MachineStateImpl<C>> {
+        let mut next = state.clone();
+        let mut selected = Vec::new();
+        let deferred = outcome.deferred.len();
+        let changed = std::mem::take(&mut outcome.changed);
+        let handled = self.select_transitions(
+            &self.states,
+            "",
+            state.value(),
+            &mut next,
+            event,
+            outcome,
+            &mut selected,
+        )?;
+
+        // Hold the event once, however many regions defer it, unless another region
+        // already took a transition for it
+        let taken = outcome.changed || !selected.is_empty();
+        outcome.changed |= changed;
+        if taken {
+            outcome.deferred.truncate(deferred);
+        } else if outcome.deferred.len() > deferred {
+            next.deferred.push(AnyEvent::new(event.clone()));
+        }
+
+        // Machine-level wildcards catch whatever no active state handled
+        if !handled {
+            let any = select_any(&self.any_transitions, state.context(), event, "", outcome);
+            if let Some(any) = any {
+                let source = state.value().top_level().to_string();
+                self.take_or_select(&mut next, source, any, event, outcome, &mut selected)?;
+            }
+        }
+
+        // SCXML semantics take the selected transitions once every guard has been checked
+        for (source, transition) in selected {
+            // An earlier transition may already have exited this source
+            if next.value().find(&source).is_none() {
+                continue;
+            }
+
+            next = self.take_event_transition(&next, &source, transition, event, outcome)?;
+        }
+
+        self.record(outcome, Some(event), &next);
+        self.settle(next, Some(event), outcome)
     }
 
-    fn execute_entry_actions(
+    /// Process the events raised by actions, oldest first, until none is left
+    ///
+    /// Raised events go through the middleware like any other; held events went
+    /// through it when they arrived. Then held events that no active state defers
+    /// any more are replayed, oldest first. Fails with `MachineError::RaisedEventLoop` if actions are still raising
+    /// events after `MAX_RAISED_EVENTS` of them were processed.
+    fn run_to_completion(
         &self,
         mut state: MachineStateImpl<C>,
-        target_id: &str,
-        event: &E,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<MachineStateImpl<C>> {
+        let mut processed = 0;
+        loop {
+            let event = match outcome.raised.get(processed).cloned() {
+                Some(raised) => {
+                    if processed == MAX_RAISED_EVENTS {
+                        return Err(MachineError::RaisedEventLoop(state.value().to_string()));
+                    }
+                    processed += 1;
+
+                    // Delayed and always transitions have no event type of their own to raise
+                    let Some(event) = raised.downcast_ref::<E>().cloned() else {
+                        tracing::warn!(
+                            "Ignoring raised {} event, the machine handles {}",
+                            raised.type_name(),
+                            std::any::type_name::<E>()
+                        );
+                        continue;
+                    };
+                    let Some(event) = self.intercept(&state, event) else {
+                        continue;
+                    };
+                    event
+                }
+                None => match self.take_replayable(&mut state) {
+                    Some(event) => event,
+                    None => return Ok(state),
+                },
+            };
+            state = self.macrostep(&state, &event, outcome)?;
+        }
+    }
+
+    /// Remove the oldest held event that no active state defers
+    fn take_replayable(&self, state: &mut MachineStateImpl<C>) -> Option<E> {
+        let active = state.value().active_paths();
+        let deferred = |event: &E| {
+            active.iter().any(|path| {
+                self.state_at(path)
+                    .is_some_and(|node| matches_any(&node.deferred_events, event))
+            })
+        };
+
+        let index = state.deferred.iter().position(|held| {
+            held.downcast_ref::<E>()
+                .is_some_and(|event| !deferred(event))
+        })?;
+        state.deferred.remove(index).downcast_ref::<E>().cloned()
+    }
+
+    /// Take the delayed transition at `index` of the state at the `source` path.
+    ///
+    /// Called by the timer runtime once the delay has elapsed. The exit actions of
+    /// `source` and the target's entry actions get `MachineSignal::DelayElapsed`,
+    /// and only run if the machine turns signals into events.
+    pub fn transition_delayed(
+        &self,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        index: usize,
     ) -> MachineStateImpl<C> {
-        if let Some(target_node) = self.states.get(target_id) {
-            for action in &target_node.entry_actions {
-                action.execute(&mut state.context, event);
+        self.step_delayed(state, source, index).state
+    }
+
+    /// Take a delayed transition like `transition_delayed` and report everything it did
+    pub fn step_delayed(
+        &self,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        index: usize,
+    ) -> TransitionOutcome<C> {
+        self.step_delayed_in(Delivery::default(), state, source, index)
+    }
+
+    /// `step_delayed` for a timer fired as described by `delivery`
+    pub(crate) fn step_delayed_in(
+        &self,
+        delivery: Delivery,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        index: usize,
+    ) -> TransitionOutcome<C> {
+        let mut outcome = TransitionOutcome::new(state);
+        outcome.delivery = delivery;
+
+        // The timer may fire after its state was left; ignore it in that case
+        if state.value().find(source).is_none() {
+            return outcome;
+        }
+
+        let Some(source_node) = self.state_at(source) else {
+            return outcome;
+        };
+        let Some(delayed) = source_node.delayed_transitions.get(index) else {
+            return outcome;
+        };
+
+        if !check_guards(&delayed.guards, state.context(), &(), source, &mut outcome) {
+            return outcome;
+        }
+
+        let event = self.signal(MachineSignal::DelayElapsed {
+            source: source.to_string(),
+            delay: delayed.delay,
+        });
+        let settled = self
+            .take_transition(
+                state,
+                source,
+                &delayed.target,
+                event.as_ref(),
+                &mut outcome,
+                |context, outcome| run_actions(&delayed.actions, context, &(), outcome),
+            )
+            .and_then(|next| {
+                self.record(&mut outcome, None, &next);
+                self.settle(next, event.as_ref(), &mut outcome)
+            })
+            .and_then(|settled| self.run_to_completion(settled, &mut outcome));
+        match settled {
+            Ok(settled) => {
+                let outcome = outcome.finish(settled);
+                self.report(state, None, &outcome);
+                outcome
+            }
+            Err(err) => {
+                tracing::error!("Delayed transition rejected: {}", err);
+                self.report_failure(None, state, &err);
+                TransitionOutcome::rejected(state, &err)
+            }
+        }
+    }
+
+    /// Take the done or error transition of the invocation at `index` of the state
+    /// at the `source` path.
+    ///
+    /// Called by the runtime once the invoked service finished. The result event goes
+    /// through the middleware, then is handed to the exit actions of `source` and to
+    /// the target's entry actions.
+    /// Fails, leaving the state as it was, when an action fails or an invariant is
+    /// violated while taking the transition.
+    pub fn step_invoked(
+        &self,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        index: usize,
+        result: InvokeEvent<E>,
+    ) -> MachineResult<TransitionOutcome<C>> {
+        self.step_invoked_in(Delivery::default(), state, source, index, result)
+    }
+
+    /// `step_invoked` for a service result delivered as described by `delivery`
+    pub(crate) fn step_invoked_in(
+        &self,
+        delivery: Delivery,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        index: usize,
+        result: InvokeEvent<E>,
+    ) -> MachineResult<TransitionOutcome<C>> {
+        let mut outcome = TransitionOutcome::new(state);
+        outcome.delivery = delivery;
+
+        // The service may finish after its state was left; ignore it in that case
+        if state.value().find(source).is_none() {
+            return Ok(outcome);
+        }
+
+        let Some(invocation) = self.invocations(source).get(index) else {
+            return Ok(outcome);
+        };
+        let result = match result {
+            InvokeEvent::Done(event) => self.intercept(state, event).map(InvokeEvent::Done),
+            InvokeEvent::Error(event) => self.intercept(state, event).map(InvokeEvent::Error),
+        };
+        let Some(result) = result else {
+            return Ok(outcome);
+        };
+        let (target, event) = match &result {
+            InvokeEvent::Done(event) => (&invocation.done_target, event),
+            InvokeEvent::Error(event) => (&invocation.error_target, event),
+        };
+        let Some(target) = target else {
+            return Ok(outcome);
+        };
+
+        let settled = self
+            .take_transition(state, source, target, Some(event), &mut outcome, |_, _| {
+                Ok(())
+            })
+            .and_then(|next| {
+                self.record(&mut outcome, Some(event), &next);
+                self.settle(next, Some(event), &mut outcome)
+            })
+            .and_then(|settled| self.run_to_completion(settled, &mut outcome));
+        match settled {
+            Ok(settled) => {
+                let outcome = outcome.finish(settled);
+                self.report(state, Some(event), &outcome);
+                Ok(outcome)
+            }
+            Err(err) => {
+                self.report_failure(Some(event), state, &err);
+                Err(err)
             }
         }
+    }
+
+    /// Fail with `MachineError::InvariantViolated` if an invariant rejects `context`
+    ///
+    /// Every caller rejects the whole step on failure, which rolls the machine back
+    /// to the state the event found it in.
+    fn check_invariants(&self, context: &C) -> MachineResult<()> {
+        let Some(invariant) = self
+            .invariants
+            .iter()
+            .find(|invariant| !(invariant.check)(context))
+        else {
+            return Ok(());
+        };
+
+        let error = MachineError::InvariantViolated(invariant.name.clone());
+        if self.assert_invariants && cfg!(debug_assertions) {
+            panic!("{}", error);
+        }
+        Err(error)
+    }
+
+    /// Take always transitions until none is enabled
+    ///
+    /// Fails with `MachineError::EventlessLoop` if the machine is still moving
+    /// after `MAX_EVENTLESS_STEPS` transitions.
+    fn settle(
+        &self,
+        mut state: MachineStateImpl<C>,
+        event: Option<&E>,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<MachineStateImpl<C>> {
+        for _ in 0..MAX_EVENTLESS_STEPS {
+            match self.take_always(&state, event, outcome)? {
+                Some(next) => {
+                    self.record(outcome, None, &next);
+                    state = next;
+                }
+                None => return Ok(state),
+            }
+        }
+
+        Err(MachineError::EventlessLoop(state.value().to_string()))
+    }
+
+    /// Take the first enabled always transition, checking innermost states first
+    fn take_always(
+        &self,
+        state: &MachineStateImpl<C>,
+        event: Option<&E>,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<Option<MachineStateImpl<C>>> {
+        // Settling the initial state: there is no event being handled yet
+        let started = event
+            .is_none()
+            .then(|| self.signal(MachineSignal::Started))
+            .flatten();
+
+        for source in state.value().active_paths().iter().rev() {
+            let Some(source_node) = self.state_at(source) else {
+                continue;
+            };
+
+            for always in &source_node.always_transitions {
+                if check_guards(&always.guards, state.context(), &(), source, outcome) {
+                    return self
+                        .take_transition(
+                            state,
+                            source,
+                            &always.target,
+                            event.or(started.as_ref()),
+                            outcome,
+                            |context, outcome| run_actions(&always.actions, context, &(), outcome),
+                        )
+                        .map(Some);
+                }
+            }
+
+            // The done event of a compound or parallel state whose children completed
+            if let Some(done_target) = &source_node.done_target {
+                let completed = state.value().find(source).is_some_and(|value| {
+                    value.is_compound()
+                        && self.is_complete(self.scope_of(source), parent_path(source), value)
+                });
+
+                if completed {
+                    return self
+                        .take_transition(
+                            state,
+                            source,
+                            done_target,
+                            event.or(started.as_ref()),
+                            outcome,
+                            |_, _| Ok(()),
+                        )
+                        .map(Some);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Turn `signal` into an event for exit and entry actions, see `signal_event`
+    fn signal(&self, signal: MachineSignal) -> Option<E> {
+        self.signal_event.map(|event| event(signal))
+    }
+
+    /// Whether the machine has reached a final state
+    ///
+    /// A compound state counts as done once its active child is final, and a
+    /// parallel state once every region is done.
+    pub fn is_done(&self, state: &MachineStateImpl<C>) -> bool {
+        self.is_complete(&self.states, "", state.value())
+    }
+
+    /// Whether the state rooted at `value`, a child of the state at `prefix`, is final
+    /// or has completed its children
+    fn is_complete(&self, scope: &StateMap<C, E>, prefix: &str, value: &StateValue) -> bool {
+        match value {
+            StateValue::Simple(id) => self.is_final(scope, prefix, id),
+            StateValue::Compound { parent, child } => {
+                let Some(node) = self.lookup(scope, prefix, parent) else {
+                    return false;
+                };
+                let path = join_path(prefix, parent);
+
+                node.is_final
+                    || match child.as_ref() {
+                        StateValue::Parallel(regions) => regions
+                            .iter()
+                            .all(|region| self.is_complete(&node.child_states, &path, region)),
+                        StateValue::Simple(id) | StateValue::Compound { parent: id, .. } => {
+                            self.is_final(&node.child_states, &path, id)
+                        }
+                    }
+            }
+            StateValue::Parallel(regions) => regions
+                .iter()
+                .all(|region| self.is_complete(scope, prefix, region)),
+        }
+    }
+
+    fn is_final(&self, scope: &StateMap<C, E>, prefix: &str, id: &str) -> bool {
+        self.lookup(scope, prefix, id)
+            .is_some_and(|node| node.is_final)
+    }
+
+    /// Whether sending `event` in `state` would take a transition
+    ///
+    /// Transitions are selected the same way `step` selects them, so guards run
+    /// against the current context, but no action is executed. Targetless
+    /// transitions count; events forbidden by the active states do not.
+    pub fn can(&self, state: &MachineStateImpl<C>, event: &E) -> bool {
+        let mut scratch = TransitionOutcome::new(state);
+        match self.find_enabled(
+            &self.states,
+            "",
+            state.value(),
+            state.context(),
+            event,
+            &mut scratch,
+        ) {
+            Some(enabled) => enabled,
+            None => select_any(
+                &self.any_transitions,
+                state.context(),
+                event,
+                "",
+                &mut scratch,
+            )
+            .is_some(),
+        }
+    }
+
+    /// Whether an event the active states handle would currently lead to `target`
+    ///
+    /// `target` is a state id or a dotted path. Guards run against the current
+    /// context with the transition's own event. Wildcard, eventless and delayed
+    /// transitions are not considered.
+    pub fn can_transition_to(&self, state: &MachineStateImpl<C>, target: &str) -> bool {
+        targets_include(&self.targets(state.value(), state.context()), target)
+    }
+
+    /// Dotted paths of the states the event transitions of the active states lead
+    /// to, keeping those whose guards pass for the transition's own event
+    fn targets(&self, value: &StateValue, context: &C) -> BTreeSet<String> {
+        let mut targets = BTreeSet::new();
+        for path in value.active_paths() {
+            let Some(node) = self.state_at(&path) else {
+                continue;
+            };
+
+            for transition in node.transitions.iter().filter(|t| !t.is_targetless()) {
+                let enabled = transition
+                    .guards
+                    .iter()
+                    .all(|guard| guard.check(context, &transition.event));
+                if enabled {
+                    targets.insert(self.target_path(&path, &transition.target));
+                }
+            }
+        }
+        targets
+    }
+
+    /// Events of the active states' transitions that `state` can currently take
+    ///
+    /// Listed innermost state first, in definition order, without duplicates.
+    /// Wildcard transitions accept any event and are not listed.
+    pub fn enabled_events(&self, state: &MachineStateImpl<C>) -> Vec<E> {
+        let mut events: Vec<E> = Vec::new();
+        for path in state.value().active_paths().iter().rev() {
+            let Some(node) = self.state_at(path) else {
+                continue;
+            };
+
+            for transition in &node.transitions {
+                let listed = events
+                    .iter()
+                    .any(|listed| transition.matcher.matches(&transition.event, listed));
+                if !listed && self.can(state, &transition.event) {
+                    events.push(transition.event.clone());
+                }
+            }
+        }
+        events
+    }
+
+    /// Dry run of `select_transitions`
+    ///
+    /// Returns whether a transition is enabled, or `None` when no active state
+    /// handled the event and it bubbles up to the machine-level wildcards.
+    fn find_enabled(
+        &self,
+        scope: &StateMap<C, E>,
+        prefix: &str,
+        value: &StateValue,
+        context: &C,
+        event: &E,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> Option<bool> {
+        let id = match value {
+            StateValue::Simple(id) => id,
+            StateValue::Compound { parent, child } => {
+                let child_scope = self
+                    .lookup(scope, prefix, parent)
+                    .map(|node| &node.child_states)
+                    .unwrap_or(scope);
+                let child_prefix = join_path(prefix, parent);
+
+                if let Some(enabled) =
+                    self.find_enabled(child_scope, &child_prefix, child, context, event, outcome)
+                {
+                    return Some(enabled);
+                }
+                parent
+            }
+            StateValue::Parallel(regions) => {
+                let mut handled = None;
+                for region in regions {
+                    match self.find_enabled(scope, prefix, region, context, event, outcome) {
+                        Some(true) => return Some(true),
+                        Some(false) => handled = Some(false),
+                        None => {}
+                    }
+                }
+                return handled;
+            }
+        };
+
+        let path = join_path(prefix, id);
+        let node = self.lookup(scope, prefix, id)?;
+
+        let specific = node.transitions.iter().any(|transition| {
+            transition.matcher.matches(&transition.event, event)
+                && check_guards(&transition.guards, context, event, &path, outcome)
+        });
+        if specific {
+            Some(true)
+        } else if matches_any(&node.forbidden_events, event)
+            || matches_any(&node.deferred_events, event)
+        {
+            Some(false)
+        } else {
+            select_any(&node.any_transitions, context, event, &path, outcome).map(|_| true)
+        }
+    }
+
+    /// Take a transition triggered by `event` from the state at the `source` path
+    ///
+    /// If an action fails and the transition has an error target, everything the
+    /// attempt did is rolled back and the error target is entered instead, with the
+    /// failure turned into the event its entry actions see. A failure asking for a
+    /// retry rejects the event instead when the runtime retries (see
+    /// `ActionContext::retries`), leaving it to deliver the event again.
+    fn take_event_transition(
+        &self,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        transition: TransitionRef<'_, C, E>,
+        event: &E,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<MachineStateImpl<C>> {
+        let Some(on_error) = transition.on_error else {
+            return self.attempt_event_transition(state, source, transition, event, outcome);
+        };
+
+        let before = outcome.clone();
+        match self.attempt_event_transition(state, source, transition, event, outcome) {
+            Err(MachineError::ActionFailed(error))
+                if error.retry_after.is_none() || !outcome.delivery.retries =>
+            {
+                *outcome = before;
+                outcome.errors.push(error.clone());
+                let event = (on_error.event)(error);
+                self.take_transition(
+                    state,
+                    source,
+                    &on_error.target,
+                    Some(&event),
+                    outcome,
+                    |_, _| Ok(()),
+                )
+            }
+            result => result,
+        }
+    }
+
+    /// Take a transition triggered by `event`, failing if any of its actions fails
+    ///
+    /// Internal transitions targeting the source itself (including targetless
+    /// transitions) only run their actions. Internal transitions to a descendant
+    /// exit and enter the states below the source but never the source itself.
+    fn attempt_event_transition(
+        &self,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        transition: TransitionRef<'_, C, E>,
+        event: &E,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<MachineStateImpl<C>> {
+        let actions = |context: &mut C, outcome: &mut TransitionOutcome<C>| {
+            run_actions(transition.actions, context, event, outcome)
+        };
+
+        let internal_target = transition
+            .internal
+            .then(|| self.internal_target(source, transition.target))
+            .flatten();
+        let Some(target) = internal_target else {
+            return self.take_transition(
+                state,
+                source,
+                transition.target,
+                Some(event),
+                outcome,
+                actions,
+            );
+        };
+
+        if target == source {
+            let mut context = state.context().clone();
+            outcome.changed = true;
+            actions(&mut context, outcome)?;
+            self.check_invariants(&context)?;
+
+            return Ok(self.state_with(
+                state.value().clone(),
+                context,
+                state.history.clone(),
+                state.deferred.clone(),
+            ));
+        }
+
+        // Leave the active child on the way to the target (or its region of a parallel state)
+        let exit_root = match state.value().find(source) {
+            Some(StateValue::Compound { child, .. }) if !child.is_parallel() => {
+                join_path(source, child.top_level())
+            }
+            _ => child_of(source, &target),
+        };
+        self.microstep(
+            state,
+            source,
+            &exit_root,
+            &target,
+            Some(event),
+            outcome,
+            actions,
+        )
+    }
+
+    /// Resolve the target of an internal transition from the state at `source`
+    ///
+    /// Returns `None` unless the target is the source itself (or empty, for a
+    /// targetless transition) or one of its descendants.
+    pub(crate) fn internal_target(&self, source: &str, target: &str) -> Option<String> {
+        let id = source.rsplit('.').next().unwrap_or(source);
+        if target.is_empty() || target == id || target == source {
+            return Some(source.to_string());
+        }
+
+        if self
+            .state_at(source)
+            .is_some_and(|node| node.child_states.contains_key(target))
+        {
+            return Some(join_path(source, target));
+        }
+
+        (target.starts_with(&format!("{}.", source)) && self.state_at(target).is_some())
+            .then(|| target.to_string())
+    }
+
+    /// Leave the state at the `source` path for `target`
+    ///
+    /// `target` is the id of a sibling of the source or a dotted path from the top
+    /// level. `event` is handed to exit and entry actions; without one (a done
+    /// transition taken on start) only the transition `actions` run.
+    fn take_transition(
+        &self,
+        state: &MachineStateImpl<C>,
+        source: &str,
+        target: &str,
+        event: Option<&E>,
+        outcome: &mut TransitionOutcome<C>,
+        actions: impl FnOnce(&mut C, &mut TransitionOutcome<C>) -> MachineResult<()>,
+    ) -> MachineResult<MachineStateImpl<C>> {
+        let scope = self.scope_of(source);
+        if self.semantics == TransitionSemantics::Scxml || !scope.contains_key(target) {
+            let target = self.target_path(source, target);
+            let domain = self.transition_domain(source, &target);
+            let exit_root = child_of(&domain, source);
+            return self.microstep(state, &domain, &exit_root, &target, event, outcome, actions);
+        }
+
+        let prefix = parent_path(source);
+        let mut new_context = state.context().clone();
+        outcome.changed = true;
+
+        // Execute transition actions
+        actions(&mut new_context, outcome)?;
+
+        // Exited states are recorded before the target is resolved, so targeting a
+        // history state restores what was just left
+        let mut history = state.history.clone();
+        let source_value = state.value().find(source);
+        if let Some(source_value) = source_value {
+            self.record_history(&mut history, prefix, source_value);
+        }
+        let target_value = self.resolve_target_state(scope, prefix, target, &history);
+
+        if let Some(source_value) = source_value {
+            self.execute_exit_actions(
+                scope,
+                prefix,
+                source_value,
+                &mut new_context,
+                event,
+                outcome,
+            )?;
+        }
+        self.execute_entry_actions(
+            scope,
+            prefix,
+            &target_value,
+            &mut new_context,
+            event,
+            outcome,
+        )?;
+        self.check_invariants(&new_context)?;
+
+        Ok(self.state_with(
+            state.value().replace(source, target_value),
+            new_context,
+            history,
+            state.deferred.clone(),
+        ))
+    }
+
+    /// Find the transition each active region takes on `event`, innermost first
+    ///
+    /// Child states are checked before their parent and every region of a parallel
+    /// state may take its own transition. Each state tries its own transitions, then
+    /// stops forbidden events, then tries its wildcards before the event bubbles up. With legacy semantics each transition is
+    /// taken as soon as it is found, so later guards see its effects; with SCXML
+    /// semantics it is added to `selected` and guards see the context from before
+    /// the step. Returns whether anything in `value` took or selected a transition.
+    #[allow(clippy::too_many_arguments)]
+    fn select_transitions<'a>(
+        &'a self,
+        scope: &'a StateMap<C, E>,
+        prefix: &str,
+        value: &StateValue,
+        current: &mut MachineStateImpl<C>,
+        event: &E,
+        outcome: &mut TransitionOutcome<C>,
+        selected: &mut Vec<(String, TransitionRef<'a, C, E>)>,
+    ) -> MachineResult<bool>
+    where
+        E: PartialEq,
+    {
+        let id = match value {
+            StateValue::Simple(id) => id,
+            StateValue::Compound { parent, child } => {
+                let child_scope = self
+                    .lookup(scope, prefix, parent)
+                    .map(|node| &node.child_states)
+                    .unwrap_or(scope);
+                let child_prefix = join_path(prefix, parent);
+
+                if self.select_transitions(
+                    child_scope,
+                    &child_prefix,
+                    child,
+                    current,
+                    event,
+                    outcome,
+                    selected,
+                )? {
+                    return Ok(true);
+                }
+                parent
+            }
+            StateValue::Parallel(regions) => {
+                let mut found = false;
+                for region in regions {
+                    found |= self.select_transitions(
+                        scope, prefix, region, current, event, outcome, selected,
+                    )?;
+                }
+                return Ok(found);
+            }
+        };
+
+        let path = join_path(prefix, id);
+        // A transition taken in an earlier region may have left this state
+        if current.value().find(&path).is_none() {
+            return Ok(false);
+        }
+        let Some(node) = self.lookup(scope, prefix, id) else {
+            return Ok(false);
+        };
+
+        let specific = node.transitions.iter().find(|transition| {
+            transition.matcher.matches(&transition.event, event)
+                && check_guards(&transition.guards, current.context(), event, &path, outcome)
+        });
+        let transition = match specific {
+            Some(transition) => TransitionRef {
+                target: &transition.target,
+                internal: transition.internal,
+                actions: &transition.actions,
+                on_error: transition.on_error.as_ref(),
+            },
+            // Forbidden events are handled here, without a transition
+            None if matches_any(&node.forbidden_events, event) => return Ok(true),
+            // Deferred events are held until no active state defers them
+            None if matches_any(&node.deferred_events, event) => {
+                outcome.deferred.push(path);
+                return Ok(true);
+            }
+            None => {
+                match select_any(
+                    &node.any_transitions,
+                    current.context(),
+                    event,
+                    &path,
+                    outcome,
+                ) {
+                    Some(any) => any,
+                    None => return Ok(false),
+                }
+            }
+        };
+
+        self.take_or_select(current, path, transition, event, outcome, selected)?;
+        Ok(true)
+    }
+
+    /// Take `transition` right away with legacy semantics, or add it to `selected`
+    /// to be taken after every guard was checked with SCXML semantics
+    fn take_or_select<'a>(
+        &self,
+        current: &mut MachineStateImpl<C>,
+        source: String,
+        transition: TransitionRef<'a, C, E>,
+        event: &E,
+        outcome: &mut TransitionOutcome<C>,
+        selected: &mut Vec<(String, TransitionRef<'a, C, E>)>,
+    ) -> MachineResult<()> {
+        match self.semantics {
+            TransitionSemantics::Legacy => {
+                *current =
+                    self.take_event_transition(current, &source, transition, event, outcome)?;
+            }
+            TransitionSemantics::Scxml => selected.push((source, transition)),
+        }
+        Ok(())
+    }
+
+    /// Exit the subtree at the `exit_root` path and enter the states below `domain`
+    /// down to the `target` path
+    ///
+    /// `domain` is the least common ancestor of source and target, and `exit_root`
+    /// its active child being left. Transition actions run before the exit actions
+    /// with legacy semantics and between exit and entry actions with SCXML semantics.
+    #[allow(clippy::too_many_arguments)]
+    fn microstep(
+        &self,
+        state: &MachineStateImpl<C>,
+        domain: &str,
+        exit_root: &str,
+        target: &str,
+        event: Option<&E>,
+        outcome: &mut TransitionOutcome<C>,
+        actions: impl FnOnce(&mut C, &mut TransitionOutcome<C>) -> MachineResult<()>,
+    ) -> MachineResult<MachineStateImpl<C>> {
+        let entry_root = child_of(domain, target);
+        let scope = self.scope_of(exit_root);
+        let mut context = state.context().clone();
+        outcome.changed = true;
+
+        let exited = state.value().find(exit_root);
+        match self.semantics {
+            TransitionSemantics::Legacy => {
+                actions(&mut context, outcome)?;
+                if let Some(exited) = exited {
+                    self.execute_exit_actions(scope, domain, exited, &mut context, event, outcome)?;
+                }
+            }
+            TransitionSemantics::Scxml => {
+                if let Some(exited) = exited {
+                    self.execute_exit_actions(scope, domain, exited, &mut context, event, outcome)?;
+                }
+                actions(&mut context, outcome)?;
+            }
+        }
+
+        let mut history = state.history.clone();
+        if let Some(exited) = exited {
+            self.record_history(&mut history, domain, exited);
+        }
+        let entered = self.entry_value(scope, &entry_root, target, &history);
+        self.execute_entry_actions(scope, domain, &entered, &mut context, event, outcome)?;
+        self.check_invariants(&context)?;
+
+        Ok(self.state_with(
+            state.value().replace(exit_root, entered),
+            context,
+            history,
+            state.deferred.clone(),
+        ))
+    }
+
+    /// Resolve a transition target to a dotted path
+    ///
+    /// Siblings of the source win, then a dotted path from the top level, then the
+    /// first state with that id anywhere in the machine.
+    pub(crate) fn target_path(&self, source: &str, target: &str) -> String {
+        let parent = parent_path(source);
+
+        if self.scope_of(source).contains_key(target) {
+            return join_path(parent, target);
+        }
+        if self.state_at(target).is_some() {
+            return target.to_string();
+        }
+
+        self.path_of(target)
+            .unwrap_or_else(|| join_path(parent, target))
+    }
+
+    /// Find the dotted path of the shallowest state with the given id
+    fn path_of(&self, id: &str) -> Option<String> {
+        let mut pending = std::collections::VecDeque::from([(String::new(), &self.states)]);
+        while let Some((prefix, nodes)) = pending.pop_front() {
+            if nodes.contains_key(id) {
+                return Some(join_path(&prefix, id));
+            }
+
+            let mut children: Vec<_> = nodes.iter().collect();
+            children.sort_by_key(|(child_id, _)| *child_id);
+            for (child_id, node) in children {
+                pending.push_back((join_path(&prefix, child_id), &node.child_states));
+            }
+        }
+        None
+    }
+
+    /// Get the least common compound ancestor of a transition's source and target
+    ///
+    /// Empty for the top level. The source itself never counts, so self-transitions
+    /// exit and re-enter their source.
+    fn transition_domain(&self, source: &str, target: &str) -> String {
+        let mut domain = parent_path(source);
+        while !domain.is_empty() && !target.starts_with(&format!("{}.", domain)) {
+            domain = parent_path(domain);
+        }
+
+        // Regions of a parallel state cannot be left on their own
+        while !domain.is_empty() && self.state_at(domain).is_some_and(|node| node.is_parallel) {
+            domain = parent_path(domain);
+        }
+
+        domain.to_string()
+    }
+
+    /// Build the value entered at `entry_root` on the way down to `target`
+    ///
+    /// Every state on the path is entered, the other regions of parallel states
+    /// on the way enter their defaults, and `target` enters its initial children.
+    fn entry_value(
+        &self,
+        scope: &StateMap<C, E>,
+        entry_root: &str,
+        target: &str,
+        history: &BTreeMap<String, StateValue>,
+    ) -> StateValue {
+        let id = entry_root.rsplit('.').next().unwrap_or(entry_root);
+        if entry_root == target {
+            return self.resolve_target_state(scope, parent_path(entry_root), id, history);
+        }
+
+        let Some(node) = self.lookup(scope, parent_path(entry_root), id) else {
+            return StateValue::Simple(id.to_string());
+        };
+        let next_root = child_of(entry_root, target);
+        let next = self.entry_value(&node.child_states, &next_root, target, history);
+
+        let child = if node.is_parallel {
+            let mut regions: Vec<&String> = node.child_states.keys().collect();
+            regions.sort();
+
+            StateValue::Parallel(
+                regions
+                    .into_iter()
+                    .map(|region| {
+                        if join_path(entry_root, region) == next_root {
+                            next.clone()
+                        } else {
+                            self.resolve_target_state(
+                                &node.child_states,
+                                entry_root,
+                                region,
+                                history,
+                            )
+                        }
+                    })
+                    .collect(),
+            )
+        } else {
+            next
+        };
+
+        StateValue::Compound {
+            parent: id.to_string(),
+            child: Box::new(child),
+        }
+    }
+
+    /// Resolve the state value entered by targeting `target` among the children of
+    /// the state at `prefix` (`""` at the top level)
+    ///
+    /// Compound states enter their initial child and parallel states enter every
+    /// region, ordered by id. History states restore what `history` recorded for
+    /// their parent.
+    fn resolve_target_state(
+        &self,
+        scope: &StateMap<C, E>,
+        prefix: &str,
+        target: &str,
+        history: &BTreeMap<String, StateValue>,
+    ) -> StateValue {
+        if let Some(state_node) = self.lookup(scope, prefix, target) {
+            if let Some(history_state) = &state_node.history {
+                return self.restore_history(scope, prefix, target, history_state, history);
+            }
+
+            let path = join_path(prefix, target);
+            if !state_node.child_states.is_empty() {
+                if state_node.is_parallel {
+                    let mut regions: Vec<&String> = state_node.child_states.keys().collect();
+                    regions.sort();
+
+                    return StateValue::Compound {
+                        parent: target.to_string(),
+                        child: Box::new(StateValue::Parallel(
+                            regions
+                                .into_iter()
+                                .map(|region| {
+                                    self.resolve_target_state(
+                                        &state_node.child_states,
+                                        &path,
+                                        region,
+                                        history,
+                                    )
+                                })
+                                .collect(),
+                        )),
+                    };
+                }
+
+                // This is a compound state, resolve initial child
+                if let Some(initial_child) = &state_node.initial_child {
+                    return StateValue::Compound {
+                        parent: target.to_string(),
+                        child: Box::new(self.resolve_target_state(
+                            &state_node.child_states,
+                            &path,
+                            initial_child,
+                            history,
+                        )),
+                    };
+                }
+            }
+        }
+
+        StateValue::Simple(target.to_string())
+    }
+
+    /// Resolve the value entered through the history state `id` of the state at `prefix`
+    ///
+    /// Deep history restores the whole recorded configuration, shallow history only
+    /// the recorded child, entering its defaults. Without a record the default target
+    /// is entered, or else the parent's initial child.
+    fn restore_history(
+        &self,
+        scope: &StateMap<C, E>,
+        prefix: &str,
+        id: &str,
+        history_state: &HistoryState,
+        history: &BTreeMap<String, StateValue>,
+    ) -> StateValue {
+        let recorded = history.get(prefix).filter(|_| history_state.enabled);
+        if let Some(recorded) = recorded {
+            if history_state.history_type.is_deep() {
+                return recorded.clone();
+            }
+            return self.resolve_target_state(scope, prefix, recorded.top_level(), history);
+        }
+
+        let fallback = match &history_state.default_target {
+            Some(target) => Some(target),
+            None if prefix.is_empty() => Some(&self.initial),
+            None => self
+                .state_at(prefix)
+                .and_then(|parent| parent.initial_child.as_ref()),
+        };
+        match fallback {
+            Some(target) if target != id => {
+                self.resolve_target_state(scope, prefix, target, history)
+            }
+            _ => StateValue::Simple(id.to_string()),
+        }
+    }
+
+    /// Record the child configuration of every state in `value` that has a history state
+    ///
+    /// `value` is rooted at a child of the state at `prefix`.
+    fn record_history(
+        &self,
+        history: &mut BTreeMap<String, StateValue>,
+        prefix: &str,
+        value: &StateValue,
+    ) {
+        match value {
+            StateValue::Simple(_) => {}
+            StateValue::Compound { parent, child } => {
+                let path = join_path(prefix, parent);
+                let has_history = self.state_at(&path).is_some_and(|node| {
+                    node.child_states
+                        .values()
+                        .any(|child| child.history.is_some())
+                });
+                if has_history {
+                    history.insert(path.clone(), (**child).clone());
+                }
+                self.record_history(history, &path, child);
+            }
+            StateValue::Parallel(regions) => {
+                for region in regions {
+                    self.record_history(history, prefix, region);
+                }
+            }
+        }
+    }
+
+    /// Look up `id` among the states of `scope`, the children of the state at `prefix`
+    ///
+    /// Falls back to the states declared around each ancestor of `prefix`, innermost
+    /// first, then to `id` as a dotted path from the top level. States anywhere else
+    /// must be named by their dotted path.
+    fn lookup<'a>(
+        &'a self,
+        scope: &'a StateMap<C, E>,
+        prefix: &str,
+        id: &str,
+    ) -> Option<&'a StateNode<C, E, C>> {
+        if let Some(node) = scope.get(id) {
+            return Some(node);
+        }
+
+        let mut ancestor = prefix;
+        while !ancestor.is_empty() {
+            if let Some(node) = self.scope_of(ancestor).get(id) {
+                return Some(node);
+            }
+            ancestor = parent_path(ancestor);
+        }
+        self.state_at(id)
+    }
+
+    /// Get the states the state at `path` is declared among
+    fn scope_of(&self, path: &str) -> &StateMap<C, E> {
+        path.rsplit_once('.')
+            .and_then(|(parent, _)| self.state_at(parent))
+            .map(|parent| &parent.child_states)
+            .unwrap_or(&self.states)
+    }
+
+    /// Enter every state in `value`, outermost first
+    ///
+    /// Entry actions only run when there is an `event` to hand them.
+    fn execute_entry_actions(
+        &self,
+        scope: &StateMap<C, E>,
+        prefix: &str,
+        value: &StateValue,
+        context: &mut C,
+        event: Option<&E>,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<()> {
+        match value {
+            StateValue::Simple(id) => {
+                outcome.entered.push(join_path(prefix, id));
+                if let (Some(node), Some(event)) = (self.lookup(scope, prefix, id), event) {
+                    run_actions(&node.entry_actions, context, event, outcome)?;
+                }
+            }
+            StateValue::Compound { parent, child } => {
+                let path = join_path(prefix, parent);
+                let node = self.lookup(scope, prefix, parent);
+
+                outcome.entered.push(path.clone());
+                if let (Some(node), Some(event)) = (node, event) {
+                    run_actions(&node.entry_actions, context, event, outcome)?;
+                }
+
+                let child_scope = node.map(|node| &node.child_states).unwrap_or(scope);
+                self.execute_entry_actions(child_scope, &path, child, context, event, outcome)?;
+            }
+            StateValue::Parallel(regions) => {
+                for region in regions {
+                    self.execute_entry_actions(scope, prefix, region, context, event, outcome)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Exit every state in `value`, innermost first
+    ///
+    /// Exit actions only run when there is an `event` to hand them.
+    fn execute_exit_actions(
+        &self,
+        scope: &StateMap<C, E>,
+        prefix: &str,
+        value: &StateValue,
+        context: &mut C,
+        event: Option<&E>,
+        outcome: &mut TransitionOutcome<C>,
+    ) -> MachineResult<()> {
+        match value {
+            StateValue::Simple(id) => {
+                outcome.exited.push(join_path(prefix, id));
+                if let (Some(node), Some(event)) = (self.lookup(scope, prefix, id), event) {
+                    run_actions(&node.exit_actions, context, event, outcome)?;
+                }
+            }
+            StateValue::Compound { parent, child } => {
+                let path = join_path(prefix, parent);
+                let node = self.lookup(scope, prefix, parent);
+
+                let child_scope = node.map(|node| &node.child_states).unwrap_or(scope);
+                self.execute_exit_actions(child_scope, &path, child, context, event, outcome)?;
+
+                outcome.exited.push(path);
+                if let (Some(node), Some(event)) = (node, event) {
+                    run_actions(&node.exit_actions, context, event, outcome)?;
+                }
+            }
+            StateValue::Parallel(regions) => {
+                for region in regions {
+                    self.execute_exit_actions(scope, prefix, region, context, event, outcome)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the dotted path of the parent of the state at `path` (`""` at the top level)
+fn parent_path(path: &str) -> &str {
+    path.rsplit_once('.').map_or("", |(parent, _)| parent)
+}
+
+/// Get the path of the child of `ancestor` on the way to `path`
+fn child_of(ancestor: &str, path: &str) -> String {
+    let rest = if ancestor.is_empty() {
+        path
+    } else {
+        &path[ancestor.len() + 1..]
+    };
+    join_path(ancestor, rest.split('.').next().unwrap_or(rest))
+}
+
+/// Find the first wildcard transition whose guards pass
+fn select_any<'a, C: Send + Sync, E>(
+    transitions: &'a [AnyTransition<C, E>],
+    context: &C,
+    event: &E,
+    path: &str,
+    outcome: &mut TransitionOutcome<C>,
+) -> Option<TransitionRef<'a, C, E>> {
+    transitions
+        .iter()
+        .find(|any| check_guards(&any.guards, context, event, path, outcome))
+        .map(|any| TransitionRef {
+            target: &any.target,
+            internal: false,
+            actions: &any.actions,
+            on_error: None,
+        })
+}
+
+/// Whether any of `events` matches `event`
+fn matches_any<E: PartialEq>(events: &[MatchedEvent<E>], event: &E) -> bool {
+    events.iter().any(|candidate| candidate.matches(event))
+}
+
+/// Whether `target`, a state id or dotted path, names one of the dotted `targets`
+fn targets_include(targets: &BTreeSet<String>, target: &str) -> bool {
+    targets
+        .iter()
+        .any(|path| path == target || path.rsplit('.').next() == Some(target))
+}
+
+/// Join a state id onto the dotted path of its parent (`""` at the top level)
+pub(crate) fn join_path(prefix: &str, id: &str) -> String {
+    if prefix.is_empty() {
+        id.to_string()
+    } else {
+        format!("{}.{}", prefix, id)
+    }
+}
+
+/// Check guards in order until one fails, recording every evaluation
+fn check_guards<C: Send + Sync, Ev>(
+    guards: &[Arc<dyn Guard<C, Ev>>],
+    context: &C,
+    event: &Ev,
+    path: &str,
+    outcome: &mut TransitionOutcome<C>,
+) -> bool {
+    guards.iter().all(|guard| {
+        let passed = guard.check(context, event);
+        outcome.guards.push(TransitionGuardEvaluation {
+            state: path.to_string(),
+            guard: guard.name().to_string(),
+            passed,
+        });
+        passed
+    })
+}
+
+/// Run actions in order, recording their names and the events they raise or send
+///
+/// Stops at the first action that fails; the events raised so far are dropped.
+fn run_actions<C: Send + Sync, Ev: PartialEq + Send + Sync + 'static>(
+    actions: &[Arc<dyn Action<C, Ev>>],
+    context: &mut C,
+    event: &Ev,
+    outcome: &mut TransitionOutcome<C>,
+) -> MachineResult<()> {
+    let mut effects = ActionContext::for_delivery(&outcome.delivery);
+    for action in actions {
+        outcome.actions.push(action.name().to_string());
+        if let Err(mut error) = action.execute_in(context, event, &mut effects) {
+            if error.action.is_empty() {
+                error.action = action.name().to_string();
+            }
+            return Err(MachineError::ActionFailed(error));
+        }
+    }
+
+    let (raised, sent, spawned) = effects.into_parts();
+    outcome.raised.extend(raised.into_iter().map(AnyEvent::new));
+    outcome.sent.extend(sent);
+    outcome.spawned.extend(spawned);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+        Increment,
+    }
+
+    #[test]
+    fn cloned_machine_keeps_guards_and_actions() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "running")
+            .guard_fn(|ctx: &TestContext, _: &TestEvent| ctx.count < 200)
+            .action(FunctionAction::new(
+                |ctx: &mut TestContext, _: &TestEvent| ctx.count += 1,
+            ))
+            .state("running")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 10)
+            .on_exit_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 100)
+            .on(TestEvent::Stop, "idle")
+            .initial("idle")
+            .build();
+        let cloned = machine.clone();
+
+        let events = [
+            TestEvent::Start,
+            TestEvent::Stop,
+            TestEvent::Start,
+            TestEvent::Stop,
+            TestEvent::Start,
+        ];
+        let mut original_state = machine.initial_state();
+        let mut cloned_state = cloned.initial_state();
+        for event in events {
+            let original = machine.step(&original_state, event.clone());
+            let copy = cloned.step(&cloned_state, event);
+            assert_eq!(original, copy);
+
+            original_state = original.state;
+            cloned_state = copy.state;
+        }
+
+        // Two runs through every action, then the guard rejects the last Start
+        assert!(cloned_state.matches("idle"));
+        assert_eq!(cloned_state.context().count, 222);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct Trace {
+        log: Vec<&'static str>,
+    }
+
+    fn player_machine(semantics: TransitionSemantics) -> Machine<Trace, TestEvent, Trace> {
+        MachineBuilder::<Trace, TestEvent>::new()
+            .semantics(semantics)
+            .state("player")
+            .initial_child("playing")
+            .on_exit_fn(|ctx: &mut Trace, _: &TestEvent| ctx.log.push("exit player"))
+            .child_state("playing")
+            .on_exit_fn(|ctx: &mut Trace, _: &TestEvent| ctx.log.push("exit playing"))
+            .on(TestEvent::Stop, "stopped")
+            .action(FunctionAction::new(|ctx: &mut Trace, _: &TestEvent| {
+                ctx.log.push("stop")
+            }))
+            .parent()
+            .state("stopped")
+            .on_entry_fn(|ctx: &mut Trace, _: &TestEvent| ctx.log.push("enter stopped"))
+            .initial("player")
+            .build()
+    }
+
+    #[test]
+    fn scxml_semantics_exit_before_actions_before_entry() {
+        let machine = player_machine(TransitionSemantics::Scxml);
+
+        let outcome = machine.step(&machine.initial_state(), TestEvent::Stop);
+        assert!(outcome.state.matches("stopped"));
+        assert_eq!(
+            outcome.state.context().log,
+            vec!["exit playing", "exit player", "stop", "enter stopped"]
+        );
+        assert_eq!(outcome.exited, vec!["player.playing", "player"]);
+        assert_eq!(outcome.entered, vec!["stopped"]);
+    }
+
+    #[test]
+    fn legacy_semantics_run_transition_actions_first() {
+        let machine = player_machine(TransitionSemantics::default());
+
+        let stopped = machine.transition(&machine.initial_state(), TestEvent::Stop);
+        assert_eq!(stopped.context().log.first(), Some(&"stop"));
+    }
+
+    #[test]
+    fn scxml_semantics_stay_inside_the_common_ancestor() {
+        let machine = MachineBuilder::<Trace, TestEvent>::new()
+            .semantics(TransitionSemantics::Scxml)
+            .state("player")
+            .initial_child("playing")
+            .on_exit_fn(|ctx: &mut Trace, _: &TestEvent| ctx.log.push("exit player"))
+            .child_state("playing")
+            .on(TestEvent::Stop, "paused")
+            .parent()
+            .child_state("paused")
+            .on_entry_fn(|ctx: &mut Trace, _: &TestEvent| ctx.log.push("enter paused"))
+            .parent()
+            .initial("player")
+            .build();
+
+        let outcome = machine.step(&machine.initial_state(), TestEvent::Stop);
+        assert!(outcome.state.matches("player.paused"));
+        assert_eq!(outcome.state.context().log, vec!["enter paused"]);
+        assert_eq!(outcome.exited, vec!["player.playing"]);
+    }
+
+    #[test]
+    fn can_checks_guards_without_running_actions() {
+        let runs = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = runs.clone();
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("locked")
+            .on(TestEvent::Start, "unlocked")
+            .guard_fn(|ctx: &TestContext, _| ctx.count > 0)
+            .action(FunctionAction::new(
+                move |_: &mut TestContext, _: &TestEvent| {
+                    counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                },
+            ))
+            .on_event(TestEvent::Increment)
+            .state("unlocked")
+            .initial("locked")
+            .build();
+
+        let locked = machine.initial_state();
+        assert!(!machine.can(&locked, &TestEvent::Start));
+        assert!(machine.can(&locked, &TestEvent::Increment));
+        assert!(!machine.can(&locked, &TestEvent::Stop));
+        assert_eq!(machine.enabled_events(&locked), vec![TestEvent::Increment]);
+
+        assert!(!machine.can_transition_to(&locked, "unlocked"));
+
+        let unlockable = machine.initial_with_context(TestContext { count: 1 });
+        assert!(machine.can(&unlockable, &TestEvent::Start));
+        assert!(machine.can_transition_to(&unlockable, "unlocked"));
+        assert_eq!(
+            machine.enabled_events(&unlockable),
+            vec![TestEvent::Start, TestEvent::Increment]
+        );
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn guards_only_run_for_their_own_event_or_a_query() {
+        let checks = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = checks.clone();
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on_event(TestEvent::Increment)
+            .on(TestEvent::Start, "running")
+            .guard_fn(move |_: &TestContext, _: &TestEvent| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                true
+            })
+            .state("running")
+            .initial("idle")
+            .build();
+
+        let idle = machine.initial_state();
+        let idle = machine.transition(&idle, TestEvent::Increment);
+        let _ = machine.transition(&idle, TestEvent::Increment);
+        assert_eq!(checks.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        assert!(machine.can_transition_to(&idle, "running"));
+        assert_eq!(checks.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn enabled_events_respect_forbidden_events_and_bubbling() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("form")
+            .initial_child("editing")
+            .child_state("editing")
+            .on(TestEvent::Increment, "review")
+            .forbid(TestEvent::Stop)
+            .parent()
+            .child_state("review")
+            .parent()
+            .on(TestEvent::Stop, "closed")
+            .on_any("closed")
+            .state("closed")
+            .initial("form")
+            .build();
+
+        let editing = machine.initial_state();
+        assert!(!machine.can(&editing, &TestEvent::Stop));
+        // Wildcards accept any event without being listed
+        assert!(machine.can(&editing, &TestEvent::Start));
+        assert_eq!(machine.enabled_events(&editing), vec![TestEvent::Increment]);
 
-        state
+        let review = machine.transition(&editing, TestEvent::Increment);
+        assert!(machine.can(&review, &TestEvent::Stop));
+        assert_eq!(machine.enabled_events(&review), vec![TestEvent::Stop]);
     }
 }
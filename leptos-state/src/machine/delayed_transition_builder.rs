@@ -0,0 +1,112 @@
+use super::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builder for delayed ("after") transitions
+pub struct DelayedTransitionBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    state_builder: StateBuilder<C, E>,
+    delay: Duration,
+    target: String,
+    guards: Vec<Arc<dyn Guard<C, ()>>>,
+    actions: Vec<Arc<dyn Action<C, ()>>>,
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > DelayedTransitionBuilder<C, E>
+{
+    pub fn new(state_builder: StateBuilder<C, E>, delay: Duration, target: String) -> Self {
+        Self {
+            state_builder,
+            delay,
+            target,
+            guards: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn guard<G: Guard<C, ()> + 'static>(mut self, guard: G) -> Self {
+        self.guards.push(Arc::new(guard));
+        self
+    }
+
+    /// Add a function-based guard, checked against the context when the timer fires
+    pub fn guard_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&C) -> bool + Clone + Send + Sync + 'static,
+    {
+        self.guards.push(Arc::new(guards::FunctionGuard::new(
+            move |ctx: &C, _: &()| func(ctx),
+        )));
+        self
+    }
+
+    pub fn action<A: Action<C, ()> + 'static>(mut self, action: A) -> Self {
+        self.actions.push(Arc::new(action));
+        self
+    }
+
+    /// Add a function-based action, run when the timer fires
+    pub fn action_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C) + Clone + Send + Sync + 'static,
+    {
+        self.actions.push(Arc::new(actions::FunctionAction::new(
+            move |ctx: &mut C, _: &()| func(ctx),
+        )));
+        self
+    }
+
+    pub fn on(self, event: E, target: &str) -> TransitionBuilder<C, E> {
+        self.finish().on(event, target)
+    }
+
+    pub fn after(self, delay: Duration, target: &str) -> DelayedTransitionBuilder<C, E> {
+        self.finish().after(delay, target)
+    }
+
+    pub fn always(self, target: &str) -> AlwaysTransitionBuilder<C, E> {
+        self.finish().always(target)
+    }
+
+    pub fn state(self, id: &str) -> StateBuilder<C, E> {
+        self.finish().state(id)
+    }
+
+    /// Finish the delayed transition and return to the state containing the current one
+    pub fn parent(self) -> StateBuilder<C, E> {
+        self.finish().parent()
+    }
+
+    /// Finish the current transition and set the initial state on the underlying builder
+    pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
+        self.finish().initial(state_id)
+    }
+
+    pub fn build(self) -> Machine<C, E, C> {
+        self.finish().build()
+    }
+
+    /// Finish the current state and build the machine, rejecting invalid definitions
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        self.finish().try_build()
+    }
+
+    /// Finish the delayed transition and add it to the state
+    pub(crate) fn finish(self) -> StateBuilder<C, E> {
+        let delayed = DelayedTransition {
+            delay: self.delay,
+            target: self.target,
+            guards: self.guards,
+            actions: self.actions,
+        };
+
+        let mut state_builder = self.state_builder;
+        state_builder.delayed_transitions.push(delayed);
+        state_builder
+    }
+}
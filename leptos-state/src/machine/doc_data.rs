@@ -101,6 +101,11 @@ pub struct StateInfo {
     pub is_initial: bool,
     /// Whether this is a final state
     pub is_final: bool,
+    /// Tags declared on the state
+    pub tags: Vec<String>,
+    /// Metadata declared on the state
+    #[cfg(feature = "serde_json")]
+    pub meta: Option<serde_json::Value>,
 }
 
 impl StateInfo {
@@ -113,9 +118,37 @@ impl StateInfo {
             exit_actions: Vec::new(),
             is_initial: false,
             is_final: false,
+            tags: Vec::new(),
+            #[cfg(feature = "serde_json")]
+            meta: None,
         }
     }
 
+    /// Take the tags and metadata of a state node
+    ///
+    /// A `description` string in the metadata becomes the state description.
+    pub fn with_node<C, E>(mut self, node: &StateNode<C, E, C>) -> Self
+    where
+        C: Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Clone + std::fmt::Debug + 'static,
+    {
+        self.tags = node.tags.clone();
+        #[cfg(feature = "serde_json")]
+        {
+            self.meta = node.meta.clone();
+            if let Some(description) = self
+                .meta
+                .as_ref()
+                .and_then(|meta| meta.get("description"))
+                .and_then(|description| description.as_str())
+            {
+                self.description = Some(description.to_string());
+            }
+        }
+        self.is_final = node.is_final;
+        self
+    }
+
     /// Set the description
     pub fn with_description(mut self, description: String) -> Self {
         self.description = Some(description);
@@ -259,8 +292,8 @@ pub struct DocumentationData {
 impl DocumentationData {
     /// Create new documentation data from a machine
     pub fn new<
-        C: Send + Sync + Clone + PartialEq + 'static,
-        E: Clone + Send + Sync + Hash + Eq + 'static,
+        C: Send + Sync + Clone + PartialEq + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + Hash + Eq + std::fmt::Debug + 'static,
     >(
         machine: Machine<C, E, C>,
     ) -> Self {
@@ -282,8 +315,8 @@ impl DocumentationData {
 
     /// Populate data from a machine
     fn populate_from_machine<
-        C: Send + Sync + Clone + PartialEq + 'static,
-        E: Clone + Send + Sync + Hash + Eq + 'static,
+        C: Send + Sync + Clone + PartialEq + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + Hash + Eq + std::fmt::Debug + 'static,
     >(
         &mut self,
         machine: Machine<C, E, C>,
@@ -295,6 +328,9 @@ impl DocumentationData {
         // Populate states
         for state_name in machine.get_states() {
             let mut state_info = StateInfo::new(state_name.clone());
+            if let Some(node) = machine.states.get(&state_name) {
+                state_info = state_info.with_node(node);
+            }
             if state_name == machine.initial {
                 state_info.mark_initial();
             }
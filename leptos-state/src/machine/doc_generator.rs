@@ -70,13 +70,14 @@ impl<
 
         // States section
         content.push_str("## States\n\n");
-        content.push_str("| State | Description |\n");
-        content.push_str("|-------|-------------|\n");
+        content.push_str("| State | Description | Tags |\n");
+        content.push_str("|-------|-------------|------|\n");
         for state in &self.data.states {
             content.push_str(&format!(
-                "| {} | {} |\n",
+                "| {} | {} | {} |\n",
                 state.name,
-                state.description.as_deref().unwrap_or("")
+                state.description.as_deref().unwrap_or(""),
+                state.tags.join(", ")
             ));
         }
         content.push_str("\n");
@@ -160,12 +161,13 @@ impl<
         );
 
         // States section
-        content.push_str("<h2>States</h2>\n<table>\n<thead>\n<tr><th>State</th><th>Description</th></tr>\n</thead>\n<tbody>\n");
+        content.push_str("<h2>States</h2>\n<table>\n<thead>\n<tr><th>State</th><th>Description</th><th>Tags</th></tr>\n</thead>\n<tbody>\n");
         for state in &self.data.states {
             content.push_str(&format!(
-                "<tr><td>{}</td><td>{}</td></tr>\n",
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
                 state.name,
-                state.description.as_deref().unwrap_or("")
+                state.description.as_deref().unwrap_or(""),
+                state.tags.join(", ")
             ));
         }
         content.push_str("</tbody>\n</table>\n");
@@ -223,6 +225,9 @@ impl<
                 state.name,
                 state.description.as_deref().unwrap_or("")
             ));
+            if !state.tags.is_empty() {
+                content.push_str(&format!("    Tags: {}\n", state.tags.join(", ")));
+            }
         }
 
         content.push_str("\nTRANSITIONS:\n");
@@ -341,12 +346,14 @@ impl<
 
     /// Generate states table for templates
     fn generate_states_table(&self) -> String {
-        let mut table = "| State | Description |\n|-------|-------------|\n".to_string();
+        let mut table =
+            "| State | Description | Tags |\n|-------|-------------|------|\n".to_string();
         for state in &self.data.states {
             table.push_str(&format!(
-                "| {} | {} |\n",
+                "| {} | {} | {} |\n",
                 state.name,
-                state.description.as_deref().unwrap_or("")
+                state.description.as_deref().unwrap_or(""),
+                state.tags.join(", ")
             ));
         }
         table
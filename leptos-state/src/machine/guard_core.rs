@@ -22,6 +22,7 @@ pub struct FunctionGuard<C, E, F> {
     pub func: F,
     /// Description of the guard
     pub description: String,
+    _phantom: std::marker::PhantomData<fn(&C, &E)>,
 }
 
 impl<C, E, F> FunctionGuard<C, E, F>
@@ -30,21 +31,22 @@ where
 {
     /// Create a new function guard
     pub fn new(func: F) -> Self {
-        Self {
-            func,
-            description: "Function Guard".to_string(),
-        }
+        Self::with_description(func, "Function Guard".to_string())
     }
 
     /// Create a new function guard with description
     pub fn with_description(func: F, description: String) -> Self {
-        Self { func, description }
+        Self {
+            func,
+            description,
+            _phantom: std::marker::PhantomData,
+        }
     }
 }
 
 impl<C: std::fmt::Debug + 'static, E: std::fmt::Debug + PartialEq + 'static, F> GuardEvaluator<C, E> for FunctionGuard<C, E, F>
 where
-    F: Fn(&C, &E) -> bool + Clone + 'static,
+    F: Fn(&C, &E) -> bool + Clone + Send + Sync + 'static,
 {
     fn check(&self, context: &C, event: &E) -> bool {
         (self.func)(context, event)
@@ -55,10 +57,23 @@ where
     }
 
     fn clone_guard(&self) -> Box<dyn GuardEvaluator<C, E>> {
-        Box::new(Self {
-            func: self.func.clone(),
-            description: self.description.clone(),
-        })
+        Box::new(Self::with_description(
+            self.func.clone(),
+            self.description.clone(),
+        ))
+    }
+}
+
+impl<C, E, F> Guard<C, E> for FunctionGuard<C, E, F>
+where
+    F: Fn(&C, &E) -> bool + Send + Sync + 'static,
+{
+    fn check(&self, context: &C, event: &E) -> bool {
+        (self.func)(context, event)
+    }
+
+    fn name(&self) -> &str {
+        &self.description
     }
 }
 
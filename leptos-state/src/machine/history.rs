@@ -4,6 +4,10 @@
 //! allowing them to remember previous states and restore them when entering
 //! history states.
 //!
+//! Machines can also declare history pseudo-states directly with
+//! `StateBuilder::history`; the core engine then restores them when a transition
+//! targets them, without wrapping the machine in a `HistoryMachine`.
+//!
 //! The history module has been split into multiple modules for better organization:
 //! - `history_core`: Core history types and configurations
 //! - `history_machine`: HistoryMachine implementation with state tracking
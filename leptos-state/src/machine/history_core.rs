@@ -335,3 +335,76 @@ impl HistoryStats {
         format!("{:.2} {}", size, UNITS[unit_index])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+        Increment,
+    }
+
+    fn document_machine(history_type: HistoryType) -> Machine<TestContext, TestEvent, TestContext> {
+        MachineBuilder::<TestContext, TestEvent>::new()
+            .state("editor")
+            .initial_child("text")
+            .child_state("text")
+            .on(TestEvent::Increment, "format")
+            .parent()
+            .child_state("format")
+            .initial_child("bold")
+            .child_state("bold")
+            .on(TestEvent::Increment, "italic")
+            .parent()
+            .child_state("italic")
+            .parent()
+            .parent()
+            .child_state("hist")
+            .history(history_type)
+            .parent()
+            .on(TestEvent::Stop, "preview")
+            .state("preview")
+            .on(TestEvent::Start, "editor.hist")
+            .initial("editor")
+            .build()
+    }
+
+    #[test]
+    fn history_states_restore_the_configuration_left_behind() {
+        let deep = document_machine(HistoryType::Deep);
+
+        // Until the editor has been exited, its history enters the initial child
+        let preview = deep.transition(&deep.initial_state(), TestEvent::Stop);
+        let text = deep.transition(&preview, TestEvent::Start);
+        assert_eq!(text.value().to_string(), "editor.text");
+
+        let format = deep.transition(&text, TestEvent::Increment);
+        let italic = deep.transition(&format, TestEvent::Increment);
+        let preview = deep.transition(&italic, TestEvent::Stop);
+        let restored = deep.step(&preview, TestEvent::Start);
+        assert_eq!(restored.state.value().to_string(), "editor.format.italic");
+        assert_eq!(
+            restored.entered,
+            vec!["editor", "editor.format", "editor.format.italic"]
+        );
+
+        // Shallow history only restores the direct child, which enters its default
+        let shallow = document_machine(HistoryType::Shallow);
+        let mut state = shallow.initial_state();
+        for event in [
+            TestEvent::Increment,
+            TestEvent::Increment,
+            TestEvent::Stop,
+            TestEvent::Start,
+        ] {
+            state = shallow.transition(&state, event);
+        }
+        assert_eq!(state.value().to_string(), "editor.format.bold");
+    }
+}
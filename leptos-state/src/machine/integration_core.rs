@@ -23,6 +23,7 @@ pub struct IntegrationManager<
     pub filters: Vec<EventFilter>,
     /// Background task handles
     pub task_handles: std::sync::Mutex<Vec<tokio::task::JoinHandle<()>>>,
+    _phantom: std::marker::PhantomData<fn() -> (C, E)>,
 }
 
 impl<C: Send + Sync + Clone + std::fmt::Debug + 'static, E: Clone + Send + Sync + Hash + Eq + std::fmt::Debug + 'static>
@@ -38,6 +39,7 @@ impl<C: Send + Sync + Clone + std::fmt::Debug + 'static, E: Clone + Send + Sync
             metrics: IntegrationMetrics::new(),
             filters: Vec::new(),
             task_handles: std::sync::Mutex::new(Vec::new()),
+            _phantom: std::marker::PhantomData,
         }
     }
 
@@ -271,6 +273,7 @@ impl<C: Send + Sync + Clone + std::fmt::Debug + 'static, E: Clone + Send + Sync
             metrics: self.metrics.clone(),
             filters: self.filters.clone(),
             task_handles: std::sync::Mutex::new(Vec::new()),
+            _phantom: std::marker::PhantomData,
         }
     }
 
@@ -0,0 +1,153 @@
+use super::*;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Service as passed to `StateBuilder::invoke`, before its result is turned into an event
+type Service<C, T, X> = Arc<dyn Fn(&C) -> InvokeFuture<Result<T, X>> + Send + Sync>;
+
+/// Builder for services invoked by a state
+///
+/// `T` and `X` are the success and error types of the service's result.
+pub struct InvokeBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    T,
+    X,
+> {
+    state_builder: StateBuilder<C, E>,
+    service: Service<C, T, X>,
+    done_target: Option<String>,
+    done_event: Option<fn(T) -> E>,
+    error_target: Option<String>,
+    error_event: Option<fn(X) -> E>,
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+        T: 'static,
+        X: 'static,
+    > InvokeBuilder<C, E, T, X>
+{
+    pub fn new<F, Fut>(state_builder: StateBuilder<C, E>, service: F) -> Self
+    where
+        F: Fn(&C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, X>> + MaybeSend + 'static,
+    {
+        Self {
+            state_builder,
+            service: Arc::new(move |context: &C| -> InvokeFuture<Result<T, X>> {
+                Box::pin(service(context))
+            }),
+            done_target: None,
+            done_event: None,
+            error_target: None,
+            error_event: None,
+        }
+    }
+
+    /// Transition to `target` when the service succeeds
+    ///
+    /// The result is converted into the event handed to the target's entry actions.
+    pub fn on_done(mut self, target: &str) -> Self
+    where
+        E: From<T>,
+    {
+        self.done_target = Some(target.to_string());
+        self.done_event = Some(E::from);
+        self
+    }
+
+    /// Transition to `target` when the service fails
+    ///
+    /// The error is converted into the event handed to the target's entry actions.
+    /// Failures without an error target are logged and ignored.
+    pub fn on_error(mut self, target: &str) -> Self
+    where
+        E: From<X>,
+    {
+        self.error_target = Some(target.to_string());
+        self.error_event = Some(E::from);
+        self
+    }
+
+    pub fn on(self, event: E, target: &str) -> TransitionBuilder<C, E> {
+        self.finish().on(event, target)
+    }
+
+    pub fn after(self, delay: Duration, target: &str) -> DelayedTransitionBuilder<C, E> {
+        self.finish().after(delay, target)
+    }
+
+    pub fn always(self, target: &str) -> AlwaysTransitionBuilder<C, E> {
+        self.finish().always(target)
+    }
+
+    pub fn invoke<F, Fut, T2, X2>(self, service: F) -> InvokeBuilder<C, E, T2, X2>
+    where
+        F: Fn(&C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T2, X2>> + MaybeSend + 'static,
+        T2: 'static,
+        X2: 'static,
+    {
+        self.finish().invoke(service)
+    }
+
+    pub fn state(self, id: &str) -> StateBuilder<C, E> {
+        self.finish().state(id)
+    }
+
+    /// Finish the invocation and return to the state containing the current one
+    pub fn parent(self) -> StateBuilder<C, E> {
+        self.finish().parent()
+    }
+
+    /// Finish the invocation and set the initial state on the underlying builder
+    pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
+        self.finish().initial(state_id)
+    }
+
+    pub fn build(self) -> Machine<C, E, C> {
+        self.finish().build()
+    }
+
+    /// Finish the current state and build the machine, rejecting invalid definitions
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        self.finish().try_build()
+    }
+
+    /// Finish the invocation and add it to the state
+    pub(crate) fn finish(self) -> StateBuilder<C, E> {
+        let service = self.service;
+        let (done, error) = (self.done_event, self.error_event);
+        let state = self.state_builder.current_state.clone();
+
+        let invocation = Invocation {
+            done_target: self.done_target,
+            error_target: self.error_target,
+            service: Arc::new(move |context: &C| -> InvokeFuture<Option<InvokeEvent<E>>> {
+                let result = service(context);
+                let state = state.clone();
+                Box::pin(async move {
+                    match result.await {
+                        Ok(data) => done.map(|event| InvokeEvent::Done(event(data))),
+                        Err(err) => {
+                            if error.is_none() {
+                                tracing::warn!(
+                                    "Service invoked by state {} failed without an error target",
+                                    state
+                                );
+                            }
+                            error.map(|event| InvokeEvent::Error(event(err)))
+                        }
+                    }
+                })
+            }),
+        };
+
+        let mut state_builder = self.state_builder;
+        state_builder.invocations.push(invocation);
+        state_builder
+    }
+}
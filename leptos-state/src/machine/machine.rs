@@ -4,6 +4,7 @@ use crate::StateResult;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 // Extension traits
 #[cfg(feature = "codegen")]
@@ -323,8 +324,8 @@ pub struct StateBuilder<C: Send + Sync, E: Send + Sync> {
     machine_builder: MachineBuilder<C, E>,
     current_state: String,
     transitions: Vec<Transition<C, E>>,
-    entry_actions: Vec<Box<dyn Action<C, E>>>,
-    exit_actions: Vec<Box<dyn Action<C, E>>>,
+    entry_actions: Vec<Arc<dyn Action<C, E>>>,
+    exit_actions: Vec<Arc<dyn Action<C, E>>>,
     child_states: HashMap<String, StateNode<C, E, C>>,
     initial_child: Option<String>,
 }
@@ -347,12 +348,12 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
     }
 
     pub fn on_entry<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.entry_actions.push(Box::new(action));
+        self.entry_actions.push(Arc::new(action));
         self
     }
 
     pub fn on_exit<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.exit_actions.push(Box::new(action));
+        self.exit_actions.push(Arc::new(action));
         self
     }
 
@@ -362,7 +363,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
@@ -372,7 +373,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
@@ -383,7 +384,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         E: std::fmt::Debug,
     {
         self.entry_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message)));
         self
     }
 
@@ -394,7 +395,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         E: std::fmt::Debug,
     {
         self.exit_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message)));
         self
     }
 
@@ -404,7 +405,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
@@ -414,7 +415,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
@@ -489,8 +490,8 @@ pub struct ChildStateBuilder<C: Send + Sync, E: Send + Sync> {
     parent_builder: StateBuilder<C, E>,
     child_id: String,
     transitions: Vec<Transition<C, E>>,
-    entry_actions: Vec<Box<dyn Action<C, E>>>,
-    exit_actions: Vec<Box<dyn Action<C, E>>>,
+    entry_actions: Vec<Arc<dyn Action<C, E>>>,
+    exit_actions: Vec<Arc<dyn Action<C, E>>>,
 }
 
 impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildStateBuilder<C, E> {
@@ -509,12 +510,12 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildSt
     }
 
     pub fn on_entry<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.entry_actions.push(Box::new(action));
+        self.entry_actions.push(Arc::new(action));
         self
     }
 
     pub fn on_exit<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.exit_actions.push(Box::new(action));
+        self.exit_actions.push(Arc::new(action));
         self
     }
 
@@ -524,7 +525,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
@@ -534,7 +535,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
@@ -545,7 +546,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildSt
         E: std::fmt::Debug,
     {
         self.entry_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message)));
         self
     }
 
@@ -556,7 +557,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildSt
         E: std::fmt::Debug,
     {
         self.exit_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message)));
         self
     }
 
@@ -566,7 +567,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
@@ -576,7 +577,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static> ChildSt
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
@@ -622,8 +623,8 @@ pub struct ChildTransitionBuilder<C: Send + Sync, E: Send + Sync> {
     child_builder: ChildStateBuilder<C, E>,
     event: E,
     target: String,
-    guards: Vec<Box<dyn Guard<C, E>>>,
-    actions: Vec<Box<dyn Action<C, E>>>,
+    guards: Vec<Arc<dyn Guard<C, E>>>,
+    actions: Vec<Arc<dyn Action<C, E>>>,
 }
 
 impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
@@ -640,7 +641,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
     }
 
     pub fn guard<G: Guard<C, E> + 'static>(mut self, guard: G) -> Self {
-        self.guards.push(Box::new(guard));
+        self.guards.push(Arc::new(guard));
         self
     }
 
@@ -649,7 +650,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
     where
         F: Fn(&C, &E) -> bool + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FunctionGuard::new(func)));
+        self.guards.push(Arc::new(guards::FunctionGuard::new(func)));
         self
     }
 
@@ -659,7 +660,7 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
         F: Fn(&C) -> T + Clone + Send + Sync + 'static,
         T: PartialEq + Clone + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FieldEqualityGuard::new(
+        self.guards.push(Arc::new(guards::FieldEqualityGuard::new(
             field_extractor,
             expected_value,
         )));
@@ -673,25 +674,25 @@ impl<C: Clone + 'static + Send + Sync, E: Clone + Send + Sync + 'static>
         T: PartialOrd + Send + Sync + 'static,
     {
         self.guards
-            .push(Box::new(guards::RangeGuard::new(field_extractor, min, max)));
+            .push(Arc::new(guards::RangeGuard::new(field_extractor, min, max)));
         self
     }
 
     /// Add a time limit guard
     pub fn guard_time_limit(mut self, duration: std::time::Duration) -> Self {
-        self.guards.push(Box::new(guards::TimeGuard::new(duration)));
+        self.guards.push(Arc::new(guards::TimeGuard::new(duration)));
         self
     }
 
     /// Add a counter guard
     pub fn guard_max_transitions(mut self, max_count: usize) -> Self {
         self.guards
-            .push(Box::new(guards::CounterGuard::new(max_count)));
+            .push(Arc::new(guards::CounterGuard::new(max_count)));
         self
     }
 
     pub fn action<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.actions.push(Box::new(action));
+        self.actions.push(Arc::new(action));
         self
     }
 
@@ -728,8 +729,8 @@ pub struct TransitionBuilder<C: Send + Sync, E: Send + Sync> {
     state_builder: StateBuilder<C, E>,
     event: E,
     target: String,
-    guards: Vec<Box<dyn Guard<C, E>>>,
-    actions: Vec<Box<dyn Action<C, E>>>,
+    guards: Vec<Arc<dyn Guard<C, E>>>,
+    actions: Vec<Arc<dyn Action<C, E>>>,
 }
 
 impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> TransitionBuilder<C, E> {
@@ -744,7 +745,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
     }
 
     pub fn guard<G: Guard<C, E> + 'static>(mut self, guard: G) -> Self {
-        self.guards.push(Box::new(guard));
+        self.guards.push(Arc::new(guard));
         self
     }
 
@@ -753,7 +754,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
     where
         F: Fn(&C, &E) -> bool + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FunctionGuard::new(func)));
+        self.guards.push(Arc::new(guards::FunctionGuard::new(func)));
         self
     }
 
@@ -763,7 +764,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
         F: Fn(&C) -> T + Clone + Send + Sync + 'static,
         T: PartialEq + Clone + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FieldEqualityGuard::new(
+        self.guards.push(Arc::new(guards::FieldEqualityGuard::new(
             field_extractor,
             expected_value,
         )));
@@ -777,25 +778,25 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
         T: PartialOrd + Send + Sync + 'static,
     {
         self.guards
-            .push(Box::new(guards::RangeGuard::new(field_extractor, min, max)));
+            .push(Arc::new(guards::RangeGuard::new(field_extractor, min, max)));
         self
     }
 
     /// Add a time limit guard
     pub fn guard_time_limit(mut self, duration: std::time::Duration) -> Self {
-        self.guards.push(Box::new(guards::TimeGuard::new(duration)));
+        self.guards.push(Arc::new(guards::TimeGuard::new(duration)));
         self
     }
 
     /// Add a counter guard
     pub fn guard_max_transitions(mut self, max_count: usize) -> Self {
         self.guards
-            .push(Box::new(guards::CounterGuard::new(max_count)));
+            .push(Arc::new(guards::CounterGuard::new(max_count)));
         self
     }
 
     pub fn action<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.actions.push(Box::new(action));
+        self.actions.push(Arc::new(action));
         self
     }
 
@@ -875,8 +876,8 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
 pub struct StateNode<C, E, S> {
     pub id: String,
     pub transitions: Vec<Transition<C, E>>,
-    pub entry_actions: Vec<Box<dyn Action<C, E>>>,
-    pub exit_actions: Vec<Box<dyn Action<C, E>>>,
+    pub entry_actions: Vec<Arc<dyn Action<C, E>>>,
+    pub exit_actions: Vec<Arc<dyn Action<C, E>>>,
     pub child_states: HashMap<String, StateNode<C, E, C>>,
     pub initial_child: Option<String>,
     _phantom: std::marker::PhantomData<S>,
@@ -886,8 +887,8 @@ pub struct StateNode<C, E, S> {
 pub struct Transition<C, E> {
     pub event: E,
     pub target: String,
-    pub guards: Vec<Box<dyn Guard<C, E>>>,
-    pub actions: Vec<Box<dyn Action<C, E>>>,
+    pub guards: Vec<Arc<dyn Guard<C, E>>>,
+    pub actions: Vec<Arc<dyn Action<C, E>>>,
 }
 
 /// Complete machine implementation
@@ -900,26 +901,26 @@ pub struct Machine<C: Send + Sync, E, S> {
 // Manual Clone implementation for Machine
 // Remove the manual Clone implementation since we have #[derive(Clone)]
 
-// Manual Clone implementation for Transition since trait objects can't be cloned
+// Manual Clone implementation for Transition; guards and actions are shared, not copied
 impl<C: Clone, E: Clone> Clone for Transition<C, E> {
     fn clone(&self) -> Self {
         Self {
             event: self.event.clone(),
             target: self.target.clone(),
-            guards: Vec::new(), // Can't clone trait objects, so we create empty vectors
-            actions: Vec::new(),
+            guards: self.guards.clone(),
+            actions: self.actions.clone(),
         }
     }
 }
 
-// Manual Clone implementation for StateNode since Action trait objects can't be cloned
+// Manual Clone implementation for StateNode; entry and exit actions are shared, not copied
 impl<C: Clone, E: Clone> Clone for StateNode<C, E, C> {
     fn clone(&self) -> Self {
         Self {
             id: self.id.clone(),
             transitions: self.transitions.clone(),
-            entry_actions: Vec::new(), // Can't clone trait objects, so we create empty vectors
-            exit_actions: Vec::new(),
+            entry_actions: self.entry_actions.clone(),
+            exit_actions: self.exit_actions.clone(),
             child_states: self.child_states.clone(),
             initial_child: self.initial_child.clone(),
             _phantom: std::marker::PhantomData,
@@ -970,17 +971,11 @@ impl<C: Send + Sync + Clone, E: Clone> Machine<C, E, C> {
     where
         C: Default,
     {
-        MachineStateImpl {
-            value: StateValue::Simple(self.initial.clone()),
-            context: Default::default(),
-        }
+        MachineStateImpl::new(StateValue::Simple(self.initial.clone()), Default::default())
     }
 
     pub fn initial_with_context(&self, context: C) -> MachineStateImpl<C> {
-        MachineStateImpl {
-            value: StateValue::Simple(self.initial.clone()),
-            context,
-        }
+        MachineStateImpl::new(StateValue::Simple(self.initial.clone()), context)
     }
 
     /// Transition from one state to another based on an event
@@ -999,19 +994,13 @@ impl<C: Send + Sync + Clone, E: Clone> Machine<C, E, C> {
                 let mut context = state.context.clone();
 
                 for parallel_state in states {
-                    let temp_state = MachineStateImpl {
-                        value: parallel_state.clone(),
-                        context: context.clone(),
-                    };
+                    let temp_state = MachineStateImpl::new(parallel_state.clone(), context.clone());
                     let transitioned = self.transition(&temp_state, event.clone());
                     new_states.push(transitioned.value);
                     context = transitioned.context;
                 }
 
-                MachineStateImpl {
-                    value: StateValue::Parallel(new_states),
-                    context,
-                }
+                MachineStateImpl::new(StateValue::Parallel(new_states), context)
             }
         }
     }
@@ -1051,10 +1040,7 @@ impl<C: Send + Sync + Clone, E: Clone> Machine<C, E, C> {
                         // Determine target state value (simple or compound)
                         let new_value = self.resolve_target_state(&transition.target);
 
-                        let new_state = MachineStateImpl {
-                            value: new_value,
-                            context: new_context,
-                        };
+                        let new_state = MachineStateImpl::new(new_value, new_context);
 
                         // Execute entry actions for target state
                         return self.execute_entry_actions(new_state, &transition.target, &event);
@@ -1078,22 +1064,19 @@ impl<C: Send + Sync + Clone, E: Clone> Machine<C, E, C> {
         E: PartialEq,
     {
         // First try child state transitions
-        let child_state = MachineStateImpl {
-            value: (*child).clone(),
-            context: state.context.clone(),
-        };
+        let child_state = MachineStateImpl::new((*child).clone(), state.context.clone());
 
         let child_transitioned = self.transition(&child_state, event.clone());
 
         // If child transitioned, update the compound state
         if child_transitioned.value != (*child).clone() {
-            return MachineStateImpl {
-                value: StateValue::Compound {
+            return MachineStateImpl::new(
+                StateValue::Compound {
                     parent: parent_id.to_string(),
                     child: Box::new(child_transitioned.value),
                 },
-                context: child_transitioned.context,
-            };
+                child_transitioned.context,
+            );
         }
 
         // If child didn't transition, try parent transitions
@@ -1303,6 +1286,41 @@ mod tests {
         assert_eq!(machine.states.len(), cloned_machine.states.len());
     }
 
+    #[test]
+    fn cloned_machine_keeps_guards_and_actions() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .initial("idle")
+            .state("idle")
+            .on(TestEvent::Start, "running")
+            .guard(FunctionGuard::new(|ctx: &TestContext, _: &TestEvent| {
+                ctx.count < 200
+            }))
+            .action(FunctionAction::new(
+                |ctx: &mut TestContext, _: &TestEvent| ctx.count += 1,
+            ))
+            .state("running")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 10)
+            .on_exit_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 100)
+            .on(TestEvent::Stop, "idle")
+            .build();
+        let cloned = machine.clone();
+
+        let mut state = cloned.initial_state();
+        for event in [
+            TestEvent::Start,
+            TestEvent::Stop,
+            TestEvent::Start,
+            TestEvent::Stop,
+            TestEvent::Start,
+        ] {
+            state = cloned.transition(&state, event);
+        }
+
+        // Two runs through every action, then the guard rejects the last Start
+        assert_eq!(state.value(), &StateValue::Simple("idle".to_string()));
+        assert_eq!(state.context().count, 222);
+    }
+
     #[test]
     fn machine_state_validation() {
         let machine = MachineBuilder::<TestContext, TestEvent>::new()
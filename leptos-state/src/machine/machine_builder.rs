@@ -1,6 +1,7 @@
 use super::*;
 use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 // Extension traits
 #[cfg(feature = "codegen")]
@@ -21,17 +22,44 @@ use crate::machine::performance::{
 };
 
 /// Builder for creating state machines
-pub struct MachineBuilderImpl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> {
+pub struct MachineBuilderImpl<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
     pub states: HashMap<String, StateNode<C, E, C>>,
     pub initial: String,
+    pub semantics: TransitionSemantics,
+    /// Wildcard transitions taken when no active state handles an event
+    pub any_transitions: Vec<AnyTransition<C, E>>,
+    /// Hooks run around every event, see `MachineMiddleware`
+    pub middleware: Vec<Arc<dyn MachineMiddleware<C, E>>>,
+    /// Conditions checked on the context after every transition
+    pub invariants: Vec<Invariant<C>>,
+    /// Panic on a violated invariant in debug builds, see `assert_invariants`
+    pub assert_invariants: bool,
+    /// Turns machine signals into events, see `signal_event`
+    pub signal_event: Option<fn(MachineSignal) -> E>,
+    /// Paths of states declared more than once, reported by `try_build`
+    pub(crate) duplicate_states: Vec<String>,
     _phantom: PhantomData<(C, E)>,
 }
 
-impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> MachineBuilderImpl<C, E> {
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > MachineBuilderImpl<C, E>
+{
     pub fn new() -> Self {
         Self {
             states: HashMap::new(),
             initial: String::new(),
+            semantics: TransitionSemantics::default(),
+            any_transitions: Vec::new(),
+            middleware: Vec::new(),
+            invariants: Vec::new(),
+            assert_invariants: false,
+            signal_event: None,
+            duplicate_states: Vec::new(),
             _phantom: PhantomData,
         }
     }
@@ -45,14 +73,102 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Machine
         self
     }
 
+    /// Transition to `target` on any event no active state handles
+    ///
+    /// Useful for events such as a global reset that every state would otherwise
+    /// have to repeat. The transition leaves the active top-level state.
+    pub fn on_any(self, target: &str) -> AnyTransitionBuilder<C, E, Self> {
+        AnyTransitionBuilder::new(self, target.to_string())
+    }
+
+    /// Run `middleware` around every event the machine handles
+    ///
+    /// Middleware runs in the order it was registered.
+    pub fn middleware(mut self, middleware: impl MachineMiddleware<C, E> + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Require `check` to hold for the context after every transition
+    ///
+    /// Checked once a transition's exit, transition and entry actions have run. If it
+    /// fails the event is rejected with `MachineError::InvariantViolated` and the
+    /// machine stays in the state it was in, like a store's `ValidationMiddleware`.
+    pub fn invariant<F>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn(&C) -> bool + Send + Sync + 'static,
+    {
+        self.invariants.push(Invariant {
+            name: name.to_string(),
+            check: Arc::new(check),
+        });
+        self
+    }
+
+    /// Panic on a violated invariant in debug builds instead of rejecting the event
+    ///
+    /// Surfaces broken invariants in tests, where a rejected event is easy to miss.
+    pub fn assert_invariants(mut self) -> Self {
+        self.assert_invariants = true;
+        self
+    }
+
+    /// Hand exit and entry actions the machine's own signals, turned into events by `event`
+    ///
+    /// Delayed transitions and transitions taken while settling the initial state are
+    /// not triggered by an event. With this set, the exit and entry actions they run
+    /// get `MachineSignal::DelayElapsed` or `MachineSignal::Started`; without it
+    /// only their transition actions run. Pass `E::from` if the event type
+    /// converts from `MachineSignal`.
+    pub fn signal_event(mut self, event: fn(MachineSignal) -> E) -> Self {
+        self.signal_event = Some(event);
+        self
+    }
+
+    /// Choose the order in which exit, transition and entry actions run
+    pub fn semantics(mut self, semantics: TransitionSemantics) -> Self {
+        self.semantics = semantics;
+        self
+    }
+
     pub fn build(self) -> Machine<C, E, C> {
         Machine {
             states: self.states,
             initial: self.initial,
+            semantics: self.semantics,
+            any_transitions: self.any_transitions,
+            middleware: self.middleware,
+            invariants: self.invariants,
+            assert_invariants: self.assert_invariants,
+            signal_event: self.signal_event,
             _phantom: std::marker::PhantomData,
         }
     }
 
+    /// Build the machine, rejecting structurally invalid definitions
+    ///
+    /// Fails with every empty or unknown initial state, transition to an unknown
+    /// state, unknown or missing initial child and duplicate state. Unreachable
+    /// states and shadowed transitions do not stop the build; they are returned
+    /// next to the machine, as `Machine::definition_warnings` reports them.
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        let mut errors: Vec<MachineDefinitionError> = self
+            .duplicate_states
+            .iter()
+            .cloned()
+            .map(MachineDefinitionError::DuplicateState)
+            .collect();
+
+        let machine = self.build();
+        errors.extend(machine.definition_errors());
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let warnings = machine.definition_warnings();
+        Ok((machine, warnings))
+    }
+
     // TODO: Implement persistence features
     // #[cfg(feature = "persist")]
     // /// Build a machine with persistence capabilities
@@ -278,8 +394,8 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Machine
 }
 
 impl<
-        C: Clone + 'static + std::fmt::Debug + Send + Sync,
-        E: Clone + 'static + std::fmt::Debug + Send + Sync,
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
     > Default for MachineBuilderImpl<C, E>
 {
     fn default() -> Self {
@@ -289,3 +405,110 @@ impl<
 
 /// Alias for backward compatibility
 pub type MachineBuilder<C, E> = MachineBuilderImpl<C, E>;
+
+/// Result of `MachineBuilder::try_build`: the machine with its definition warnings,
+/// or every definition error
+pub type MachineBuildResult<C, E> =
+    Result<(Machine<C, E, C>, Vec<MachineDefinitionWarning>), Vec<MachineDefinitionError>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Stop,
+        Increment,
+    }
+
+    fn counter_machine(assert: bool) -> Machine<TestContext, TestEvent, TestContext> {
+        let builder = MachineBuilder::<TestContext, TestEvent>::new()
+            .invariant("count stays below 3", |ctx: &TestContext| ctx.count < 3)
+            .state("counting")
+            .on_event(TestEvent::Increment)
+            .action(FunctionAction::new(
+                |ctx: &mut TestContext, _: &TestEvent| ctx.count += 1,
+            ))
+            .on(TestEvent::Stop, "stopped")
+            .state("stopped")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 10)
+            .initial("counting");
+
+        if assert {
+            builder.assert_invariants().build()
+        } else {
+            builder.build()
+        }
+    }
+
+    /// Records the invariants that rejected a step and the state it stayed in
+    #[derive(Clone, Default)]
+    struct Violations {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MachineMiddleware<TestContext, TestEvent> for Violations {
+        fn on_invariant_violated(
+            &self,
+            event: Option<&TestEvent>,
+            state: &MachineStateImpl<TestContext>,
+            invariant: &str,
+        ) {
+            let entry = format!("{:?} in {}: {}", event, state.value(), invariant);
+            self.log.lock().unwrap().push(entry);
+        }
+    }
+
+    #[test]
+    fn violated_invariants_roll_back_the_transition() {
+        let violations = Violations::default();
+        let mut machine = counter_machine(false);
+        machine.middleware.push(Arc::new(violations.clone()));
+        assert!(
+            format!("{:?}", machine).contains("Invariant { name: \"count stays below 3\", .. }")
+        );
+        let mut state = machine.initial_state();
+        for _ in 0..2 {
+            state = machine.transition(&state, TestEvent::Increment);
+        }
+        assert_eq!(state.context().count, 2);
+
+        let Err(MachineError::InvariantViolated(name)) =
+            machine.try_transition(&state, TestEvent::Increment)
+        else {
+            panic!("expected the invariant to reject the event");
+        };
+        assert_eq!(name, "count stays below 3");
+        let outcome = machine.step(&state, TestEvent::Increment);
+        assert_eq!(outcome.state, state);
+        assert_eq!(
+            outcome.violated_invariant.as_deref(),
+            Some("count stays below 3")
+        );
+        assert_eq!(
+            *violations.log.lock().unwrap(),
+            vec![
+                "Some(Increment) in counting: count stays below 3",
+                "Some(Increment) in counting: count stays below 3",
+            ]
+        );
+
+        // Entry actions count too: the target is never entered
+        let stopped = machine.transition(&state, TestEvent::Stop);
+        assert!(stopped.matches("counting"));
+        assert_eq!(stopped.context().count, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invariant violated: count stays below 3")]
+    fn asserted_invariants_panic_when_violated() {
+        let machine = counter_machine(true);
+        machine.transition(&machine.initial_state(), TestEvent::Stop);
+    }
+}
@@ -0,0 +1,892 @@
+//! Machines loaded from declarative definitions
+//!
+//! Machines are built from a [`SerializedMachine`], which describes states and
+//! transitions by name. The behavior behind the names (events, guards and actions)
+//! is registered in Rust with [`Implementations`].
+//!
+//! [`MachineDefinition`] is the nested form of the same description kept in JSON or
+//! YAML files, and converts into a `SerializedMachine`:
+//!
+//! ```yaml
+//! initial: editing
+//! states:
+//!   editing:
+//!     on:
+//!       Submit:
+//!         target: review
+//!         guards: [is_valid]
+//!         actions: [save_draft]
+//!   review:
+//!     tags: [pending]
+//!     meta: { label: Waiting for review }
+//!     entry: [notify_reviewers]
+//!     on:
+//!       Approve: published
+//!       Reject: editing
+//!   published:
+//!     type: final
+//! ```
+
+use super::*;
+use crate::machine::core_types::join_path;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Declarative description of a machine, as written in JSON or YAML files
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MachineDefinition {
+    /// Id of the top-level state the machine starts in
+    pub initial: String,
+    /// Top-level states by id
+    pub states: BTreeMap<String, StateDefinition>,
+}
+
+/// Declarative description of a state
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct StateDefinition {
+    /// `atomic` and `compound` states behave alike; `parallel`, `final` and
+    /// `history` are supported too
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub state_type: StateType,
+    /// Id of the child entered with the state, required for compound states
+    ///
+    /// For history states, the sibling entered before the parent has been exited.
+    pub initial: Option<String>,
+    /// Whether a history state restores the whole configuration below its parent
+    /// rather than only the direct child
+    pub deep: bool,
+    /// Names of the entry actions
+    pub entry: Vec<String>,
+    /// Names of the exit actions
+    pub exit: Vec<String>,
+    /// Transitions by event name; `*` declares wildcards for any other event
+    ///
+    /// An event's transitions are checked in order. Instead of a list, an event may
+    /// map to a single transition or to the target alone.
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "transition_format::deserialize")
+    )]
+    pub on: BTreeMap<String, Vec<TransitionDefinition>>,
+    /// Child states by id
+    pub states: BTreeMap<String, StateDefinition>,
+    /// Tags reported by `has_tag` while the state is active
+    pub tags: Vec<String>,
+    /// Free-form metadata, available from `Machine::meta`
+    #[cfg(feature = "serde_json")]
+    pub meta: Option<serde_json::Value>,
+    /// Names of the events held while the state is active, see `StateBuilder::defer`
+    pub defer: Vec<String>,
+}
+
+/// Declarative description of a transition
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct TransitionDefinition {
+    /// Target state, resolved like builder targets; `None` stays in the state
+    pub target: Option<String>,
+    /// Names of the guards that must all pass
+    pub guards: Vec<String>,
+    /// Names of the actions to run
+    pub actions: Vec<String>,
+}
+
+impl TransitionDefinition {
+    /// Transition to `target` without guards or actions
+    pub fn to(target: &str) -> Self {
+        Self {
+            target: Some(target.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod transition_format {
+    use super::*;
+    use serde::{Deserialize, Deserializer};
+
+    /// The forms an event's transitions can be written in
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Transitions {
+        Target(String),
+        One(TransitionDefinition),
+        Many(Vec<TransitionDefinition>),
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<BTreeMap<String, Vec<TransitionDefinition>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let on = BTreeMap::<String, Transitions>::deserialize(deserializer)?;
+        Ok(on
+            .into_iter()
+            .map(|(event, transitions)| {
+                let transitions = match transitions {
+                    Transitions::Target(target) => vec![TransitionDefinition::to(&target)],
+                    Transitions::One(transition) => vec![transition],
+                    Transitions::Many(transitions) => transitions,
+                };
+                (event, transitions)
+            })
+            .collect())
+    }
+}
+
+impl<C, E, S> From<&MachineDefinition> for SerializedMachine<C, E, S> {
+    fn from(definition: &MachineDefinition) -> Self {
+        let mut machine = SerializedMachine::new()
+            .initial_state(definition.initial.clone())
+            .current_state(definition.initial.clone());
+        for (id, state) in &definition.states {
+            let state = serialize_state(id, id, state, &mut machine.transitions);
+            machine.states.push(state);
+        }
+        machine
+    }
+}
+
+/// Convert the state at `path`, collecting its transitions and those of its children
+fn serialize_state<C, E>(
+    id: &str,
+    path: &str,
+    definition: &StateDefinition,
+    transitions: &mut Vec<SerializedTransition<E>>,
+) -> SerializedState<C> {
+    for (event, definitions) in &definition.on {
+        for transition in definitions {
+            let target = transition.target.clone().unwrap_or_default();
+            let mut serialized =
+                SerializedTransition::new(path.to_string(), target).event(event.clone());
+            serialized.guards = transition.guards.clone();
+            serialized.actions = transition.actions.clone();
+            transitions.push(serialized);
+        }
+    }
+
+    let child_states = definition
+        .states
+        .iter()
+        .map(|(child, state)| serialize_state(child, &join_path(path, child), state, transitions))
+        .collect();
+    SerializedState {
+        state_type: definition.state_type.clone(),
+        child_states,
+        initial_child: definition.initial.clone(),
+        entry_actions: definition.entry.clone(),
+        exit_actions: definition.exit.clone(),
+        tags: definition.tags.clone(),
+        #[cfg(feature = "serde_json")]
+        meta: definition.meta.clone(),
+        deferred_events: definition.defer.clone(),
+        deep_history: definition.deep,
+        ..SerializedState::new(id.to_string())
+    }
+}
+
+/// Event name matching any event, declared as a wildcard transition
+pub const ANY_EVENT: &str = "*";
+
+/// Closure registered as a guard
+type GuardFn<C, E> = Arc<dyn Fn(&C, &E) -> bool + Send + Sync>;
+
+/// Closure registered as an action
+type ActionFn<C, E> = Arc<dyn Fn(&mut C, &E) + Send + Sync>;
+
+/// Guard registered under a name, reported under that name
+struct NamedGuard<C, E> {
+    name: String,
+    check: GuardFn<C, E>,
+}
+
+impl<C, E> Guard<C, E> for NamedGuard<C, E> {
+    fn check(&self, context: &C, event: &E) -> bool {
+        (self.check)(context, event)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Action registered under a name, reported under that name
+struct NamedAction<C, E> {
+    name: String,
+    execute: ActionFn<C, E>,
+}
+
+impl<C: 'static, E: 'static> Action<C, E> for NamedAction<C, E> {
+    fn execute(&self, context: &mut C, event: &E) {
+        (self.execute)(context, event)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn clone_action(&self) -> Box<dyn Action<C, E>> {
+        Box::new(Self {
+            name: self.name.clone(),
+            execute: self.execute.clone(),
+        })
+    }
+}
+
+/// Registry of the events, guards and actions a definition refers to by name
+///
+/// Events are matched by variant, so a registered event with a payload accepts the
+/// same variant with any payload.
+pub struct Implementations<C, E> {
+    events: HashMap<String, E>,
+    guards: HashMap<String, Arc<dyn Guard<C, E>>>,
+    actions: HashMap<String, Arc<dyn Action<C, E>>>,
+}
+
+impl<C: 'static, E: 'static> Implementations<C, E> {
+    pub fn new() -> Self {
+        Self {
+            events: HashMap::new(),
+            guards: HashMap::new(),
+            actions: HashMap::new(),
+        }
+    }
+
+    /// Register the event a definition calls `name`
+    pub fn event(mut self, name: &str, event: E) -> Self {
+        self.events.insert(name.to_string(), event);
+        self
+    }
+
+    /// Register events under their `Event::event_type()`
+    pub fn events(mut self, events: impl IntoIterator<Item = E>) -> Self
+    where
+        E: Event,
+    {
+        for event in events {
+            self.events.insert(event.event_type().to_string(), event);
+        }
+        self
+    }
+
+    /// Register the guard a definition calls `name`
+    pub fn guard<F>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn(&C, &E) -> bool + Send + Sync + 'static,
+    {
+        let guard = NamedGuard {
+            name: name.to_string(),
+            check: Arc::new(check),
+        };
+        self.guards.insert(name.to_string(), Arc::new(guard));
+        self
+    }
+
+    /// Register the action a definition calls `name`
+    pub fn action<F>(mut self, name: &str, execute: F) -> Self
+    where
+        F: Fn(&mut C, &E) + Send + Sync + 'static,
+    {
+        let action = NamedAction {
+            name: name.to_string(),
+            execute: Arc::new(execute),
+        };
+        self.actions.insert(name.to_string(), Arc::new(action));
+        self
+    }
+}
+
+impl<C: 'static, E: 'static> Default for Implementations<C, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    > Machine<C, E, C>
+{
+    /// Build a machine from a definition, looking up every name it uses
+    ///
+    /// Only the structure is read; the context and current state are left to
+    /// persistence. Fails with every name missing from `implementations`, every
+    /// compound state without an initial child, every transition without a source,
+    /// event or required target, and every structural error `try_build` reports.
+    pub fn from_definition(
+        definition: &SerializedMachine<C, E, C>,
+        implementations: &Implementations<C, E>,
+    ) -> Result<Self, Vec<MachineDefinitionError>> {
+        let mut errors = Vec::new();
+        let states = build_states(
+            &definition.states,
+            "",
+            &definition.transitions,
+            implementations,
+            &mut errors,
+        );
+
+        let machine = Machine {
+            states,
+            initial: definition.initial_state.clone(),
+            semantics: TransitionSemantics::default(),
+            any_transitions: Vec::new(),
+            middleware: Vec::new(),
+            invariants: Vec::new(),
+            assert_invariants: false,
+            signal_event: None,
+            _phantom: std::marker::PhantomData,
+        };
+        for transition in &definition.transitions {
+            if machine.state_at(&transition.from_state).is_none() {
+                errors.push(MachineDefinitionError::UnknownSource(
+                    transition.from_state.clone(),
+                ));
+            }
+        }
+        errors.extend(machine.definition_errors());
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        for warning in machine.definition_warnings() {
+            tracing::warn!("{}", warning);
+        }
+
+        Ok(machine)
+    }
+
+    /// Build a machine from a JSON definition, see `from_definition`
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(
+        json: &str,
+        implementations: &Implementations<C, E>,
+    ) -> Result<Self, Vec<MachineDefinitionError>> {
+        let definition: MachineDefinition = serde_json::from_str(json)
+            .map_err(|err| vec![MachineDefinitionError::InvalidFormat(err.to_string())])?;
+        Self::from_definition(&(&definition).into(), implementations)
+    }
+
+    /// Build a machine from a YAML definition, see `from_definition`
+    #[cfg(feature = "serde_yaml")]
+    pub fn from_yaml(
+        yaml: &str,
+        implementations: &Implementations<C, E>,
+    ) -> Result<Self, Vec<MachineDefinitionError>> {
+        let definition: MachineDefinition = serde_yaml::from_str(yaml)
+            .map_err(|err| vec![MachineDefinitionError::InvalidFormat(err.to_string())])?;
+        Self::from_definition(&(&definition).into(), implementations)
+    }
+}
+
+/// Build the states declared side by side under the state at `prefix`
+fn build_states<C, E>(
+    definitions: &[SerializedState<C>],
+    prefix: &str,
+    transitions: &[SerializedTransition<E>],
+    implementations: &Implementations<C, E>,
+    errors: &mut Vec<MachineDefinitionError>,
+) -> HashMap<String, StateNode<C, E, C>>
+where
+    C: Clone + std::fmt::Debug + Default + 'static,
+    E: Clone + std::fmt::Debug + Send + 'static,
+{
+    let mut states = HashMap::new();
+    for definition in definitions {
+        let path = join_path(prefix, &definition.id);
+        let node = build_state(&path, definition, transitions, implementations, errors);
+        if states.insert(definition.id.clone(), node).is_some() {
+            errors.push(MachineDefinitionError::DuplicateState(path));
+        }
+    }
+    states
+}
+
+fn build_state<C, E>(
+    path: &str,
+    definition: &SerializedState<C>,
+    machine_transitions: &[SerializedTransition<E>],
+    implementations: &Implementations<C, E>,
+    errors: &mut Vec<MachineDefinitionError>,
+) -> StateNode<C, E, C>
+where
+    C: Clone + std::fmt::Debug + Default + 'static,
+    E: Clone + std::fmt::Debug + Send + 'static,
+{
+    let id = definition.id.as_str();
+    let is_parallel = definition.state_type == StateType::Parallel;
+    let history = (definition.state_type == StateType::History).then(|| {
+        let history_type = if definition.deep_history {
+            HistoryType::Deep
+        } else {
+            HistoryType::Shallow
+        };
+        let history = HistoryState::new(id.to_string(), history_type);
+        match &definition.initial_child {
+            Some(target) => history.default_target(target.clone()),
+            None => history,
+        }
+    });
+    if !is_parallel && !definition.child_states.is_empty() && definition.initial_child.is_none() {
+        errors.push(MachineDefinitionError::MissingInitialChild(
+            path.to_string(),
+        ));
+    }
+
+    let mut transitions = Vec::new();
+    let mut any_transitions = Vec::new();
+    for transition in machine_transitions.iter().filter(|t| t.from_state == path) {
+        let guards = lookup_all(&transition.guards, &implementations.guards, |guard| {
+            MachineDefinitionError::UnknownGuard {
+                state: path.to_string(),
+                guard,
+            }
+        });
+        let actions = lookup_all(&transition.actions, &implementations.actions, |action| {
+            MachineDefinitionError::UnknownAction {
+                state: path.to_string(),
+                action,
+            }
+        });
+        let (guards, actions) = match (guards, actions) {
+            (Ok(guards), Ok(actions)) => (guards, actions),
+            (guards, actions) => {
+                errors.extend(guards.err().into_iter().flatten());
+                errors.extend(actions.err().into_iter().flatten());
+                continue;
+            }
+        };
+        let Some(event_name) = &transition.event else {
+            errors.push(MachineDefinitionError::MissingEvent {
+                state: path.to_string(),
+            });
+            continue;
+        };
+        let target = (!transition.to_state.is_empty()).then(|| transition.to_state.clone());
+        if event_name == ANY_EVENT {
+            // Wildcards always leave their state, so they need a target
+            let Some(target) = target else {
+                errors.push(MachineDefinitionError::MissingTarget {
+                    state: path.to_string(),
+                });
+                continue;
+            };
+            any_transitions.push(AnyTransition {
+                target,
+                guards,
+                actions,
+            });
+            continue;
+        }
+
+        let Some(event) = implementations.events.get(event_name) else {
+            errors.push(MachineDefinitionError::UnknownEvent {
+                state: path.to_string(),
+                event: event_name.clone(),
+            });
+            continue;
+        };
+        transitions.push(Transition {
+            event: event.clone(),
+            matcher: EventMatcher::Variant,
+            // Targetless transitions stay in the source, like `on_event`
+            internal: target.is_none(),
+            target: target.unwrap_or_default(),
+            guards,
+            actions,
+            on_error: None,
+        });
+    }
+
+    let mut deferred_events = Vec::new();
+    for event_name in &definition.deferred_events {
+        match implementations.events.get(event_name) {
+            // Like the transitions above, deferred events match by variant
+            Some(event) => deferred_events.push(MatchedEvent {
+                event: event.clone(),
+                matcher: EventMatcher::Variant,
+            }),
+            None => errors.push(MachineDefinitionError::UnknownEvent {
+                state: path.to_string(),
+                event: event_name.clone(),
+            }),
+        }
+    }
+
+    let entry_actions = lookup_all(
+        &definition.entry_actions,
+        &implementations.actions,
+        |action| MachineDefinitionError::UnknownAction {
+            state: path.to_string(),
+            action,
+        },
+    );
+    let exit_actions = lookup_all(
+        &definition.exit_actions,
+        &implementations.actions,
+        |action| MachineDefinitionError::UnknownAction {
+            state: path.to_string(),
+            action,
+        },
+    );
+
+    StateNode {
+        id: id.to_string(),
+        transitions,
+        entry_actions: entry_actions.unwrap_or_else(|missing| {
+            errors.extend(missing);
+            Vec::new()
+        }),
+        exit_actions: exit_actions.unwrap_or_else(|missing| {
+            errors.extend(missing);
+            Vec::new()
+        }),
+        child_states: build_states(
+            &definition.child_states,
+            path,
+            machine_transitions,
+            implementations,
+            errors,
+        ),
+        // History states use `initial_child` as their default target instead
+        initial_child: definition
+            .initial_child
+            .clone()
+            .filter(|_| history.is_none()),
+        delayed_transitions: Vec::new(),
+        always_transitions: Vec::new(),
+        is_parallel,
+        is_final: definition.state_type == StateType::Final,
+        done_target: None,
+        any_transitions,
+        forbidden_events: Vec::new(),
+        deferred_events,
+        invocations: Vec::new(),
+        tags: definition.tags.clone(),
+        #[cfg(feature = "serde_json")]
+        meta: definition.meta.clone(),
+        history,
+        _phantom: std::marker::PhantomData,
+    }
+}
+
+/// Look up every name, or report every missing one
+fn lookup_all<T: ?Sized>(
+    names: &[String],
+    registry: &HashMap<String, Arc<T>>,
+    missing: impl Fn(String) -> MachineDefinitionError,
+) -> Result<Vec<Arc<T>>, Vec<MachineDefinitionError>> {
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+    for name in names {
+        match registry.get(name) {
+            Some(implementation) => found.push(implementation.clone()),
+            None => errors.push(missing(name.clone())),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(found)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct FormContext {
+        valid: bool,
+        edits: u32,
+        drafts: u32,
+        notified: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum FormEvent {
+        Edit(String),
+        Submit,
+        Approve,
+        Reject,
+        Cancel,
+    }
+
+    fn implementations() -> Implementations<FormContext, FormEvent> {
+        Implementations::new()
+            .event("Edit", FormEvent::Edit(String::new()))
+            .event("Submit", FormEvent::Submit)
+            .event("Approve", FormEvent::Approve)
+            .event("Reject", FormEvent::Reject)
+            .event("Cancel", FormEvent::Cancel)
+            .guard("is_valid", |ctx: &FormContext, _: &FormEvent| ctx.valid)
+            .action("count_edit", |ctx: &mut FormContext, _: &FormEvent| {
+                ctx.edits += 1
+            })
+            .action("save_draft", |ctx: &mut FormContext, _: &FormEvent| {
+                ctx.drafts += 1
+            })
+            .action(
+                "notify_reviewers",
+                |ctx: &mut FormContext, _: &FormEvent| ctx.notified += 1,
+            )
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    const FORM_YAML: &str = r#"
+initial: editing
+states:
+  editing:
+    on:
+      Edit:
+        actions: [count_edit]
+      Submit:
+        - target: review
+          guards: [is_valid]
+          actions: [save_draft]
+  review:
+    tags: [locked]
+    meta:
+      label: Waiting for review
+    entry: [notify_reviewers]
+    initial: pending
+    states:
+      pending:
+        on:
+          Approve: published
+    on:
+      Reject: editing
+      "*": cancelled
+  published:
+    type: final
+  cancelled:
+    type: final
+"#;
+
+    #[cfg(feature = "serde_yaml")]
+    #[test]
+    fn yaml_definitions_run_with_named_implementations() {
+        let machine = Machine::from_yaml(FORM_YAML, &implementations()).unwrap();
+
+        // Payloads are ignored when matching registered events
+        let editing = machine.step(
+            &machine.initial_state(),
+            FormEvent::Edit("title".to_string()),
+        );
+        assert!(editing.state.matches("editing"));
+        assert!(editing.entered.is_empty());
+        assert_eq!(editing.state.context().edits, 1);
+
+        let rejected = machine.step(&editing.state, FormEvent::Submit);
+        assert!(rejected.state.matches("editing"));
+        assert_eq!(rejected.guards[0].guard, "is_valid");
+
+        let valid = machine.initial_with_context(FormContext {
+            valid: true,
+            ..FormContext::default()
+        });
+        let review = machine.step(&valid, FormEvent::Submit);
+        assert_eq!(review.state.value().to_string(), "review.pending");
+        assert_eq!(review.actions, vec!["save_draft", "notify_reviewers"]);
+        assert!(review.state.has_tag("locked"));
+        #[cfg(feature = "serde_json")]
+        assert_eq!(
+            machine.meta("review").unwrap()["label"],
+            "Waiting for review"
+        );
+
+        let published = machine.transition(&review.state, FormEvent::Approve);
+        assert!(machine.is_done(&published));
+
+        let cancelled = machine.transition(&review.state, FormEvent::Cancel);
+        assert!(cancelled.matches("cancelled"));
+    }
+
+    #[cfg(all(feature = "serde_json", feature = "serde_yaml"))]
+    #[test]
+    fn json_and_yaml_describe_the_same_definition() {
+        let yaml: MachineDefinition = serde_yaml::from_str(FORM_YAML).unwrap();
+        let json = serde_json::to_string(&yaml).unwrap();
+        let from_json: MachineDefinition = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json, yaml);
+        assert_eq!(
+            yaml.states["review"].on["Reject"],
+            vec![TransitionDefinition::to("editing")]
+        );
+
+        assert!(Machine::from_json(&json, &implementations()).is_ok());
+        assert!(matches!(
+            Machine::from_json("{\"states\": 1}", &implementations())
+                .err()
+                .as_deref(),
+            Some([MachineDefinitionError::InvalidFormat(_)])
+        ));
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    #[test]
+    fn history_states_fall_back_to_their_default_target() {
+        let yaml = r#"
+initial: paused
+states:
+  paused:
+    on:
+      Edit: wizard.resume
+  wizard:
+    initial: details
+    on:
+      Cancel: paused
+    states:
+      details:
+        on:
+          Submit: review
+      review:
+        on:
+          Reject: details
+      resume:
+        type: history
+        initial: review
+"#;
+        let machine = Machine::from_yaml(yaml, &implementations()).unwrap();
+        let edit = || FormEvent::Edit(String::new());
+
+        let review = machine.transition(&machine.initial_state(), edit());
+        assert_eq!(review.value().to_string(), "wizard.review");
+
+        let details = machine.transition(&review, FormEvent::Reject);
+        let paused = machine.transition(&details, FormEvent::Cancel);
+        let resumed = machine.transition(&paused, edit());
+        assert_eq!(resumed.value().to_string(), "wizard.details");
+    }
+
+    #[test]
+    fn missing_names_are_reported_together() {
+        let mut review = StateDefinition {
+            entry: vec!["notify".to_string()],
+            states: BTreeMap::from([("pending".to_string(), StateDefinition::default())]),
+            ..StateDefinition::default()
+        };
+        review.on.insert(
+            "Publish".to_string(),
+            vec![TransitionDefinition::to("published")],
+        );
+
+        let mut editing = StateDefinition::default();
+        editing.on.insert(
+            "Submit".to_string(),
+            vec![TransitionDefinition {
+                target: Some("reviw".to_string()),
+                guards: vec!["is_valid".to_string(), "is_complete".to_string()],
+                actions: vec!["save".to_string()],
+            }],
+        );
+        editing
+            .on
+            .insert(ANY_EVENT.to_string(), vec![TransitionDefinition::default()]);
+        let definition = MachineDefinition {
+            initial: "editing".to_string(),
+            states: BTreeMap::from([
+                ("editing".to_string(), editing),
+                ("review".to_string(), review),
+            ]),
+        };
+
+        let errors = Machine::from_definition(&(&definition).into(), &implementations())
+            .expect_err("definition should be rejected");
+        assert_eq!(
+            errors,
+            vec![
+                MachineDefinitionError::MissingTarget {
+                    state: "editing".to_string(),
+                },
+                MachineDefinitionError::UnknownGuard {
+                    state: "editing".to_string(),
+                    guard: "is_complete".to_string(),
+                },
+                MachineDefinitionError::UnknownAction {
+                    state: "editing".to_string(),
+                    action: "save".to_string(),
+                },
+                MachineDefinitionError::MissingInitialChild("review".to_string()),
+                MachineDefinitionError::UnknownEvent {
+                    state: "review".to_string(),
+                    event: "Publish".to_string(),
+                },
+                MachineDefinitionError::UnknownAction {
+                    state: "review".to_string(),
+                    action: "notify".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn serialized_machines_are_built_from_their_flat_transitions() {
+        let definition = SerializedMachine::new()
+            .initial_state("editing".to_string())
+            .add_state(
+                SerializedState::new("editing".to_string())
+                    .add_entry_action("count_edit".to_string()),
+            )
+            .add_state(
+                SerializedState::new("review".to_string())
+                    .state_type(StateType::Compound)
+                    .initial_child("pending".to_string())
+                    .add_child(SerializedState::new("pending".to_string()))
+                    .add_tag("locked".to_string()),
+            )
+            .add_transition(
+                SerializedTransition::new("editing".to_string(), "review".to_string())
+                    .event("Submit".to_string()),
+            )
+            .add_transition(
+                SerializedTransition::new("review.pending".to_string(), "editing".to_string())
+                    .event("Reject".to_string())
+                    .add_action("save_draft".to_string()),
+            );
+        let machine = Machine::from_definition(&definition, &implementations()).unwrap();
+
+        let review = machine.transition(&machine.initial_state(), FormEvent::Submit);
+        assert_eq!(review.value().to_string(), "review.pending");
+        assert!(review.has_tag("locked"));
+        let editing = machine.transition(&review, FormEvent::Reject);
+        assert!(editing.matches("editing"));
+        assert_eq!(editing.context().drafts, 1);
+        assert_eq!(editing.context().edits, 1);
+
+        let orphaned = definition.clone().add_transition(SerializedTransition::new(
+            "review.done".to_string(),
+            "editing".to_string(),
+        ));
+        assert_eq!(
+            Machine::from_definition(&orphaned, &implementations()).err(),
+            Some(vec![MachineDefinitionError::UnknownSource(
+                "review.done".to_string()
+            )])
+        );
+
+        let eventless = definition.add_transition(SerializedTransition::new(
+            "editing".to_string(),
+            "review".to_string(),
+        ));
+        assert_eq!(
+            Machine::from_definition(&eventless, &implementations()).err(),
+            Some(vec![MachineDefinitionError::MissingEvent {
+                state: "editing".to_string(),
+            }])
+        );
+    }
+}
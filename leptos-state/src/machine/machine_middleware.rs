@@ -0,0 +1,190 @@
+//! Hooks run around every event a machine handles
+
+use super::*;
+
+/// Machine-level counterpart of the store `Middleware`
+///
+/// Registered with `MachineBuilder::middleware`, so logging, analytics, authorization
+/// or devtools see every event without wrapping each `send` callback. Middleware
+/// runs in registration order and every hook does nothing by default.
+pub trait MachineMiddleware<C: Send + Sync, E>: Send + Sync {
+    /// Inspect `event` before the machine handles it in `state`
+    ///
+    /// Sees events sent to the machine, raised by its actions and produced by
+    /// invoked services. Return the event to handle, possibly rewritten, or `None`
+    /// to drop it. A dropped event leaves the state unchanged and skips the later
+    /// middleware.
+    fn before_event(&self, _state: &MachineStateImpl<C>, event: E) -> Option<E> {
+        Some(event)
+    }
+
+    /// Observe a single transition, from `old` to `new`
+    ///
+    /// Called for each transition a step took, in order, once the whole step has
+    /// succeeded: the transitions for each event, then every delayed, always or done
+    /// transition taken on the way. `event` is `None` for the eventless ones.
+    fn after_transition(
+        &self,
+        _event: Option<&E>,
+        _old: &MachineStateImpl<C>,
+        _new: &MachineStateImpl<C>,
+    ) {
+    }
+
+    /// Observe a failed action, whether or not a transition's error target handled it
+    ///
+    /// `event` is the event the step handled, `None` for a delayed transition.
+    fn on_action_error(&self, _event: Option<&E>, _error: &ActionError) {}
+
+    /// Observe a step rejected because the invariant named `invariant` failed
+    ///
+    /// The machine stays in `state`, the state the step started from. `event` is the
+    /// event the step handled, `None` for a delayed transition or the initial state.
+    fn on_invariant_violated(
+        &self,
+        _event: Option<&E>,
+        _state: &MachineStateImpl<C>,
+        _invariant: &str,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+        Increment,
+    }
+
+    /// Records what it sees; drops `Stop` and turns `Increment` into `Start`
+    #[derive(Clone, Default)]
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MachineMiddleware<TestContext, TestEvent> for Recorder {
+        fn before_event(
+            &self,
+            _state: &MachineStateImpl<TestContext>,
+            event: TestEvent,
+        ) -> Option<TestEvent> {
+            match event {
+                TestEvent::Stop => None,
+                TestEvent::Increment => Some(TestEvent::Start),
+                event => Some(event),
+            }
+        }
+
+        fn after_transition(
+            &self,
+            event: Option<&TestEvent>,
+            old: &MachineStateImpl<TestContext>,
+            new: &MachineStateImpl<TestContext>,
+        ) {
+            let event = event.map_or("eventless".to_string(), |event| format!("{:?}", event));
+            let entry = format!("{}: {} -> {}", event, old.value(), new.value());
+            self.log.lock().unwrap().push(entry);
+        }
+
+        fn on_action_error(&self, event: Option<&TestEvent>, error: &ActionError) {
+            let entry = format!("{:?}: {}", event, error);
+            self.log.lock().unwrap().push(entry);
+        }
+    }
+
+    #[test]
+    fn middleware_rewrites_drops_and_observes_events() {
+        let recorder = Recorder::default();
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .middleware(recorder.clone())
+            .state("idle")
+            .on(TestEvent::Start, "running")
+            .state("running")
+            .on(TestEvent::Stop, "idle")
+            .initial("idle")
+            .build();
+
+        assert!(format!("{:?}", machine).contains("middleware: 1"));
+        let running = machine.transition(&machine.initial_state(), TestEvent::Increment);
+        assert!(running.matches("running"));
+        assert_eq!(machine.transition(&running, TestEvent::Stop), running);
+        assert_eq!(
+            *recorder.log.lock().unwrap(),
+            vec!["Start: idle -> running"]
+        );
+
+        let mut failing = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "running")
+            .try_action_fn(|_: &mut TestContext, _: &TestEvent| Err(ActionError::new("disk full")))
+            .state("running")
+            .initial("idle")
+            .build();
+        failing.middleware.push(Arc::new(recorder.clone()));
+        failing.transition(&failing.initial_state(), TestEvent::Start);
+        assert_eq!(
+            recorder.log.lock().unwrap()[1],
+            "Some(Start): Action fallible failed: disk full"
+        );
+    }
+
+    #[test]
+    fn middleware_sees_every_microstep() {
+        let recorder = Recorder::default();
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .middleware(recorder.clone())
+            .state("idle")
+            .on(TestEvent::Start, "checking")
+            .action_with_context(|_: &mut TestContext, _: &TestEvent, actions| {
+                actions.raise(TestEvent::Increment)
+            })
+            .state("checking")
+            .on(TestEvent::Start, "ready")
+            .state("ready")
+            .always("done")
+            .state("done")
+            .after(std::time::Duration::from_secs(1), "idle")
+            .initial("idle")
+            .build();
+
+        // The raised event is rewritten like any other
+        let done = machine.transition(&machine.initial_state(), TestEvent::Start);
+        assert!(done.matches("done"));
+        let idle = machine.step_delayed(&done, "done", 0).state;
+        assert!(idle.matches("idle"));
+        assert_eq!(
+            *recorder.log.lock().unwrap(),
+            vec![
+                "Start: idle -> checking",
+                "Start: checking -> ready",
+                "eventless: ready -> done",
+                "eventless: done -> idle",
+            ]
+        );
+
+        // Nothing is reported for a step that gets rejected
+        recorder.log.lock().unwrap().clear();
+        let looping = MachineBuilder::<TestContext, TestEvent>::new()
+            .middleware(recorder.clone())
+            .state("ping")
+            .always("pong")
+            .state("pong")
+            .always("ping")
+            .state("idle")
+            .on(TestEvent::Start, "ping")
+            .initial("idle")
+            .build();
+        assert!(looping
+            .try_transition(&looping.initial_state(), TestEvent::Start)
+            .is_err());
+        assert!(recorder.log.lock().unwrap().is_empty());
+    }
+}
@@ -1,11 +1,22 @@
 use super::*;
 use crate::machine::states::StateValue;
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Concrete implementation of machine state
+///
+/// Build one with `new` rather than a struct literal; the machine fills in tags,
+/// history and deferred events.
 #[derive(Debug, Clone, PartialEq)]
 pub struct MachineStateImpl<C: Send + Sync> {
     pub value: StateValue,
     pub context: C,
+    /// Tags of every active state, filled in by the machine
+    pub tags: BTreeSet<String>,
+    /// Child configuration of each state with a history state, by dotted path,
+    /// recorded when the state was last exited
+    pub history: BTreeMap<String, StateValue>,
+    /// Events held by states that defer them, oldest first
+    pub deferred: Vec<AnyEvent>,
 }
 
 impl<C: Send + Sync + 'static> MachineState for MachineStateImpl<C> {
@@ -23,12 +34,15 @@ impl<C: Send + Sync + 'static> MachineState for MachineStateImpl<C> {
         self.value.matches(pattern)
     }
 
+    fn has_tag(&self, tag: &str) -> bool {
+        MachineStateImpl::has_tag(self, tag)
+    }
+
+    /// Whether `target` names a state at all
+    ///
+    /// The state does not know the machine's transitions or guards; use
+    /// `Machine::can_transition_to` to check whether an enabled transition leads there.
     fn can_transition_to(&self, target: &str) -> bool {
-        // Check if the target state exists in the machine
-        // This is a simplified implementation - in a full implementation,
-        // you would need access to the machine definition to check transitions
-        // For now, we'll assume any state can transition to any other state
-        // In a real implementation, this would check the machine's transition table
         !target.is_empty()
     }
 }
@@ -36,7 +50,13 @@ impl<C: Send + Sync + 'static> MachineState for MachineStateImpl<C> {
 impl<C: Send + Sync> MachineStateImpl<C> {
     /// Create a new machine state with the given value and context
     pub fn new(value: StateValue, context: C) -> Self {
-        Self { value, context }
+        Self {
+            value,
+            context,
+            tags: BTreeSet::new(),
+            history: BTreeMap::new(),
+            deferred: Vec::new(),
+        }
     }
 
     /// Create a new machine state with the given value and default context
@@ -47,6 +67,9 @@ impl<C: Send + Sync> MachineStateImpl<C> {
         Self {
             value,
             context: C::default(),
+            tags: BTreeSet::new(),
+            history: BTreeMap::new(),
+            deferred: Vec::new(),
         }
     }
 
@@ -55,8 +78,34 @@ impl<C: Send + Sync> MachineStateImpl<C> {
         Self {
             value: StateValue::Simple("idle".to_string()),
             context,
+            tags: BTreeSet::new(),
+            history: BTreeMap::new(),
+            deferred: Vec::new(),
         }
     }
+
+    /// Check if any active state is tagged with `tag`
+    ///
+    /// Tags group states by what they mean to the UI (e.g. `"loading"`), so
+    /// components keep working when states are renamed or split.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// Tags of every active state
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    /// Child configuration last recorded for each state with a history state
+    pub fn history(&self) -> &BTreeMap<String, StateValue> {
+        &self.history
+    }
+
+    /// Events held by states that defer them, oldest first
+    pub fn deferred(&self) -> &[AnyEvent] {
+        &self.deferred
+    }
 }
 
 impl<C: Send + Sync> Default for MachineStateImpl<C>
@@ -67,6 +116,9 @@ where
         Self {
             value: StateValue::Simple("idle".to_string()),
             context: C::default(),
+            tags: BTreeSet::new(),
+            history: BTreeMap::new(),
+            deferred: Vec::new(),
         }
     }
 }
@@ -185,5 +237,39 @@ mod tests {
         let initial_state = machine.initial_state();
         assert!(initial_state.matches("idle"));
         assert!(initial_state.can_transition_to("running"));
+        assert!(!machine.can_transition_to(&initial_state, "idle"));
+        assert!(machine.can_transition_to(&initial_state, "running"));
+    }
+
+    #[test]
+    fn tags_cover_every_active_state() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "fetching")
+            .state("fetching")
+            .tag("busy")
+            .initial_child("request")
+            .child_state("request")
+            .tag("loading")
+            .on(TestEvent::Increment, "retry")
+            .parent()
+            .child_state("retry")
+            .parent()
+            .on(TestEvent::Stop, "idle")
+            .initial("idle")
+            .build();
+
+        let idle = machine.initial_state();
+        assert!(!idle.has_tag("busy"));
+
+        let request = machine.transition(&idle, TestEvent::Start);
+        assert!(request.has_tag("busy"));
+        assert!(request.has_tag("loading"));
+
+        let retry = machine.transition(&request, TestEvent::Increment);
+        assert!(retry.has_tag("busy"));
+        assert!(!retry.has_tag("loading"));
+        assert!(!machine.transition(&retry, TestEvent::Stop).has_tag("busy"));
+        assert_eq!(machine.tags("fetching.request"), ["loading"]);
     }
 }
@@ -0,0 +1,364 @@
+//! Structural checks of machine definitions
+
+use super::*;
+use crate::machine::core_types::join_path;
+use std::collections::{HashSet, VecDeque};
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + Send + Sync + 'static,
+    > Machine<C, E, C>
+{
+    /// Check the definition for problems that make the machine misbehave
+    ///
+    /// Reports an empty or unknown initial state, transitions to unknown states, and
+    /// compound states whose initial child is unknown or missing. Unknown targets of machine-level wildcard transitions
+    /// are reported with an empty `state`. Duplicate states can only be detected while
+    /// building, see `MachineBuilder::try_build`.
+    pub fn definition_errors(&self) -> Vec<MachineDefinitionError> {
+        let mut errors = Vec::new();
+
+        if self.initial.is_empty() {
+            errors.push(MachineDefinitionError::EmptyInitial);
+        } else if self.state_at(&self.initial).is_none() {
+            errors.push(MachineDefinitionError::UnknownInitial(self.initial.clone()));
+        }
+
+        for (path, node) in self.state_paths() {
+            match &node.initial_child {
+                Some(child) if !node.child_states.contains_key(child) => {
+                    errors.push(MachineDefinitionError::UnknownInitialChild {
+                        state: path.clone(),
+                        child: child.clone(),
+                    });
+                }
+                // Parallel states enter every region instead
+                None if !node.is_parallel && !node.child_states.is_empty() => {
+                    errors.push(MachineDefinitionError::MissingInitialChild(path.clone()));
+                }
+                _ => {}
+            }
+
+            for (target, internal) in Self::targets_of(node) {
+                if self.resolve_target(&path, target, internal).is_none() {
+                    errors.push(MachineDefinitionError::UnknownTarget {
+                        state: path.clone(),
+                        target: target.to_string(),
+                    });
+                }
+            }
+        }
+
+        for any in &self.any_transitions {
+            if self.resolve_target("", &any.target, false).is_none() {
+                errors.push(MachineDefinitionError::UnknownTarget {
+                    state: String::new(),
+                    target: any.target.clone(),
+                });
+            }
+        }
+
+        errors
+    }
+
+    /// Check the definition for states that are never entered and transitions that
+    /// are never taken
+    pub fn definition_warnings(&self) -> Vec<MachineDefinitionWarning> {
+        let mut warnings = Vec::new();
+        let reachable = self.reachable_paths();
+
+        for (path, node) in self.state_paths() {
+            if !reachable.contains(&path) {
+                warnings.push(MachineDefinitionWarning::UnreachableState(path.clone()));
+            }
+
+            for (index, transition) in node.transitions.iter().enumerate() {
+                let shadowed = node.transitions[..index].iter().any(|earlier| {
+                    earlier.guards.is_empty()
+                        && earlier.matcher.matches(&earlier.event, &transition.event)
+                });
+
+                if shadowed {
+                    warnings.push(MachineDefinitionWarning::ShadowedTransition {
+                        state: path.clone(),
+                        index,
+                    });
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /// Every state with its dotted path, parents before their children, sorted by path
+    fn state_paths(&self) -> Vec<(String, &StateNode<C, E, C>)> {
+        let mut paths = Vec::new();
+        let mut pending: Vec<(String, &StateNode<C, E, C>)> = self
+            .states
+            .iter()
+            .map(|(id, node)| (id.clone(), node))
+            .collect();
+
+        while let Some((path, node)) = pending.pop() {
+            pending.extend(
+                node.child_states
+                    .iter()
+                    .map(|(id, child)| (join_path(&path, id), child)),
+            );
+            paths.push((path, node));
+        }
+
+        paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+        paths
+    }
+
+    /// Every target a state can leave for, with whether the transition is internal
+    fn targets_of(node: &StateNode<C, E, C>) -> impl Iterator<Item = (&str, bool)> {
+        let transitions = node
+            .transitions
+            .iter()
+            .map(|transition| (transition.target.as_str(), transition.internal));
+        let errors = node
+            .transitions
+            .iter()
+            .filter_map(|transition| transition.on_error.as_ref())
+            .map(|on_error| (on_error.target.as_str(), false));
+        let delayed = node
+            .delayed_transitions
+            .iter()
+            .map(|delayed| (delayed.target.as_str(), false));
+        let always = node
+            .always_transitions
+            .iter()
+            .map(|always| (always.target.as_str(), false));
+        let any = node
+            .any_transitions
+            .iter()
+            .map(|any| (any.target.as_str(), false));
+        let done = node
+            .done_target
+            .iter()
+            .map(|target| (target.as_str(), false));
+        let invoked = node
+            .invocations
+            .iter()
+            .flat_map(|invocation| {
+                invocation
+                    .done_target
+                    .iter()
+                    .chain(&invocation.error_target)
+            })
+            .map(|target| (target.as_str(), false));
+        let history = node
+            .history
+            .iter()
+            .flat_map(|history| &history.default_target)
+            .map(|target| (target.as_str(), false));
+
+        transitions
+            .chain(errors)
+            .chain(delayed)
+            .chain(always)
+            .chain(any)
+            .chain(done)
+            .chain(invoked)
+            .chain(history)
+    }
+
+    /// Get the dotted path a transition from `source` to `target` enters, if it exists
+    fn resolve_target(&self, source: &str, target: &str, internal: bool) -> Option<String> {
+        if internal {
+            if let Some(path) = self.internal_target(source, target) {
+                return Some(path);
+            }
+        }
+
+        let path = self.target_path(source, target);
+        self.state_at(&path).is_some().then_some(path)
+    }
+
+    /// Dotted paths of every state some sequence of transitions can activate
+    fn reachable_paths(&self) -> HashSet<String> {
+        let mut reachable = HashSet::new();
+        let mut pending = VecDeque::new();
+        self.enter_path(&self.initial, &mut reachable, &mut pending);
+        // Machine-level wildcards can be taken from any state
+        for any in &self.any_transitions {
+            if let Some(target) = self.resolve_target("", &any.target, false) {
+                self.enter_path(&target, &mut reachable, &mut pending);
+            }
+        }
+
+        while let Some(path) = pending.pop_front() {
+            let Some(node) = self.state_at(&path) else {
+                continue;
+            };
+
+            for (target, internal) in Self::targets_of(node) {
+                if let Some(target) = self.resolve_target(&path, target, internal) {
+                    self.enter_path(&target, &mut reachable, &mut pending);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// Mark the state at `path`, its ancestors and the children entering it activates
+    fn enter_path(
+        &self,
+        path: &str,
+        reachable: &mut HashSet<String>,
+        pending: &mut VecDeque<String>,
+    ) {
+        let Some(node) = self.state_at(path) else {
+            return;
+        };
+
+        let mut ancestor = String::new();
+        for segment in path.split('.') {
+            ancestor = join_path(&ancestor, segment);
+            if reachable.insert(ancestor.clone()) {
+                pending.push_back(ancestor.clone());
+            }
+        }
+
+        if node.is_parallel {
+            for region in node.child_states.keys() {
+                self.enter_path(&join_path(path, region), reachable, pending);
+            }
+        } else if let Some(child) = &node.initial_child {
+            self.enter_path(&join_path(path, child), reachable, pending);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+    }
+
+    #[test]
+    fn try_build_reports_every_structural_error() {
+        let errors = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "runing")
+            .state("checkout")
+            .initial_child("shiping")
+            .child_state("shipping")
+            .on(TestEvent::Start, "checkout.payment")
+            .parent()
+            .state("done")
+            .state("done")
+            .try_build()
+            .expect_err("definition should be rejected");
+
+        assert_eq!(
+            errors,
+            vec![
+                MachineDefinitionError::DuplicateState("done".to_string()),
+                MachineDefinitionError::EmptyInitial,
+                MachineDefinitionError::UnknownInitialChild {
+                    state: "checkout".to_string(),
+                    child: "shiping".to_string(),
+                },
+                MachineDefinitionError::UnknownTarget {
+                    state: "checkout.shipping".to_string(),
+                    target: "checkout.payment".to_string(),
+                },
+                MachineDefinitionError::UnknownTarget {
+                    state: "idle".to_string(),
+                    target: "runing".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn compound_states_need_an_initial_child() {
+        let errors = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("checkout")
+            .child_state("shipping")
+            .parent()
+            .child_state("payment")
+            .parent()
+            .state("editor")
+            .parallel()
+            .region("text")
+            .parent()
+            .region("toolbar")
+            .parent()
+            .initial("checkout")
+            .try_build()
+            .expect_err("definition should be rejected");
+
+        assert_eq!(
+            errors,
+            vec![MachineDefinitionError::MissingInitialChild(
+                "checkout".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn try_build_accepts_dotted_and_sibling_targets() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "checkout")
+            .on(TestEvent::Stop, "checkout.payment")
+            .state("checkout")
+            .initial_child("shipping")
+            .child_state("shipping")
+            .on(TestEvent::Start, "payment")
+            .parent()
+            .child_state("payment")
+            .on(TestEvent::Stop, "idle")
+            .parent()
+            .initial("idle")
+            .try_build();
+
+        let Ok((_, warnings)) = machine else {
+            panic!("definition should be valid");
+        };
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn try_build_warns_about_unreachable_states_and_shadowed_transitions() {
+        let Ok((machine, warnings)) = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "running")
+            .on(TestEvent::Start, "paused")
+            .state("running")
+            .state("paused")
+            .state("orphan")
+            .on(TestEvent::Stop, "idle")
+            .initial("idle")
+            .try_build()
+        else {
+            panic!("definition should be valid");
+        };
+
+        assert_eq!(
+            warnings,
+            vec![
+                MachineDefinitionWarning::ShadowedTransition {
+                    state: "idle".to_string(),
+                    index: 1,
+                },
+                MachineDefinitionWarning::UnreachableState("orphan".to_string()),
+            ]
+        );
+        assert_eq!(machine.definition_warnings(), warnings);
+    }
+}
@@ -4,10 +4,14 @@
 
 pub mod action_builder;
 pub mod action_composite;
+pub mod action_context;
 pub mod action_control;
 pub mod action_core;
 pub mod action_executor;
 pub mod actions;
+pub mod actor;
+pub mod always_transition_builder;
+pub mod any_transition_builder;
 pub mod builder;
 pub mod cache_system;
 pub mod child_state_builder;
@@ -20,6 +24,7 @@ pub mod codegen_ext;
 pub mod codegen_types;
 pub mod core;
 pub mod core_types;
+pub mod delayed_transition_builder;
 pub mod doc_builder;
 pub mod doc_config;
 pub mod doc_data;
@@ -47,10 +52,14 @@ pub mod integration_core;
 pub mod integration_events;
 pub mod integration_ext;
 pub mod integration_metrics;
+pub mod invoke_builder;
 pub mod lazy_evaluation;
 pub mod machine;
 pub mod machine_builder;
+pub mod machine_definition;
+pub mod machine_middleware;
 pub mod machine_state_impl;
+pub mod machine_validation;
 pub mod optimized_machine;
 pub mod performance;
 pub mod performance_builder;
@@ -76,6 +85,9 @@ pub mod persistence_metadata;
 pub mod persistence_serialization;
 pub mod persistence_storage;
 pub mod property_testing;
+pub mod scheduler;
+pub mod service;
+pub mod service_plugins;
 pub mod state_builder;
 pub mod states;
 pub mod test_builder;
@@ -85,8 +97,10 @@ pub mod test_macros;
 pub mod test_runner;
 pub mod test_types;
 pub mod testing;
+pub mod timed_machine;
 pub mod traits;
 pub mod transition_builder;
+pub mod transition_outcome;
 pub mod types;
 pub mod types_basic;
 pub mod types_config;
@@ -103,18 +117,38 @@ pub mod visualization_ext;
 pub mod visualization_monitor;
 
 // Re-export core types from new modular structure
+pub use action_context::{ActionContext, ActionRuntime, AnyEvent, ContextAction, SentEvent};
+pub use actor::{
+    Actor, ActorDone, ActorRef, ActorStep, ActorSystem, MachineActor, SpawnedActor,
+    MAX_ACTOR_MESSAGES, PARENT_ACTOR,
+};
+pub use always_transition_builder::AlwaysTransitionBuilder;
+pub use any_transition_builder::AnyTransitionBuilder;
 pub use builder::{create_machine_builder, MachineBuilderImpl};
 pub use child_state_builder::ChildStateBuilder;
 pub use child_transition_builder::ChildTransitionBuilder;
 pub use core_actions::Action;
-pub use core_errors::{MachineError, MachineResult};
+pub use core_errors::{
+    ActionError, MachineDefinitionError, MachineDefinitionWarning, MachineError, MachineResult,
+};
 pub use core_guards::Guard;
-pub use core_types::{Machine, StateNode, Transition};
-pub use machine_builder::MachineBuilder;
+pub use core_types::{
+    AlwaysTransition, AnyTransition, DelayedTransition, ErrorTransition, EventMatcher, Invariant,
+    Invocation, InvokeEvent, InvokeFuture, InvokeService, Machine, MachineSignal, MatchedEvent,
+    StateNode, Transition, TransitionSemantics, MAX_EVENTLESS_STEPS, MAX_RAISED_EVENTS,
+};
+pub use delayed_transition_builder::DelayedTransitionBuilder;
+pub use invoke_builder::InvokeBuilder;
+pub use machine_builder::{MachineBuildResult, MachineBuilder};
+pub use machine_definition::{
+    Implementations, MachineDefinition, StateDefinition, TransitionDefinition, ANY_EVENT,
+};
+pub use machine_middleware::MachineMiddleware;
 pub use machine_state_impl::MachineStateImpl;
 pub use state_builder::StateBuilder;
-pub use traits::{MachineState, StateMachine};
+pub use traits::{MachineRuntime, MachineState, StateMachine};
 pub use transition_builder::TransitionBuilder;
+pub use transition_outcome::{TransitionGuardEvaluation, TransitionOutcome};
 pub use types::{
     CompleteMachineConfig, ContextValue, EventRoutingConfig, HistoryEntry, IntegrationConfig,
     PerformanceConfig, StateValidationConfig,
@@ -133,9 +167,12 @@ pub use action_composite::{
     CompositeAction, CompositeLogic, ConditionalAction, ParallelAction, SequentialAction,
 };
 pub use action_control::{
-    CircuitBreakerAction, MetricsAction, RetryAction, RetryBackoff, TimeoutAction, TimerAction,
+    CircuitBreakerAction, CircuitStatus, MetricsAction, RetryAction, RetryBackoff, TimeoutAction,
+    TimerAction,
+};
+pub use action_core::{
+    AssignAction, FallibleAction, FunctionAction, LogAction, LogLevel, PureAction,
 };
-pub use action_core::{AssignAction, FunctionAction, LogAction, LogLevel, PureAction};
 pub use action_executor::{
     ActionExecutionStats, ActionScheduler, BatchActionExecutor, EnhancedActionExecutor,
     ErrorHandlingStrategy, ExecutionResult, PrioritizedAction,
@@ -263,6 +300,18 @@ pub use persistence_storage::{
     FileSystemStorage, LocalStorage, MachineStorage, MemoryStorage, StorageFactory, StorageInfo,
 };
 pub use property_testing::{Property, PropertyResult, PropertyTestResult, PropertyTestRunner};
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+pub use scheduler::BrowserScheduler;
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+pub use scheduler::TokioScheduler;
+pub use scheduler::{
+    default_scheduler, ManualScheduler, MaybeSend, Scheduler, Task, TaskId, TimerCallback, TimerId,
+};
+pub use service::{MachinePlugin, MachineService, ServiceStatus, SubscriptionId};
+pub use service_plugins::{
+    HistoryPlugin, IntegrationPlugin, PersistencePlugin, ProfilingPlugin, ProfilingStats,
+    VisualizationPlugin,
+};
 pub use test_builder::{MachineTestingExt, TestBuilder, TestSuiteResult};
 pub use test_cases::{TestCase as TestCaseTypes, TestCaseExecutor, TestCaseStep};
 pub use test_data_generation::{
@@ -275,6 +324,7 @@ pub use test_types::{
     TestCoverage, TestResult as TestResultTypes, TestStep,
 };
 pub use testing::{MachineTestRunner, TestCase, TestConfig, TestResult};
+pub use timed_machine::TimedMachine;
 pub use visualization_config::{
     ExportFormat, LayoutDirection, RenderingOptions, VisualizationConfig, VisualizationTheme,
 };
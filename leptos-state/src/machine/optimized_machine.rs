@@ -61,10 +61,7 @@ impl<C: Send + Sync + Clone + 'static, E: Clone + Send + Sync + Hash + Eq + 'sta
         let transition_start = std::time::Instant::now();
         // This would need to be implemented properly
         // For now, return a placeholder
-        let result = MachineStateImpl {
-            value: current.value.clone(),
-            context: current.context.clone(),
-        };
+        let result = current.clone();
         let transition_duration = transition_start.elapsed();
 
         // Cache the result
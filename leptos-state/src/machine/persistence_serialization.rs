@@ -24,6 +24,7 @@ pub struct SerializedMachine<C, E, S> {
     pub metadata: MachineMetadata,
     /// Serialization timestamp
     pub timestamp: u64,
+    _phantom: std::marker::PhantomData<fn() -> (E, S)>,
 }
 
 impl<C, E, S> SerializedMachine<C, E, S> {
@@ -42,6 +43,7 @@ impl<C, E, S> SerializedMachine<C, E, S> {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
+            _phantom: std::marker::PhantomData,
         }
     }
 
@@ -184,6 +186,15 @@ pub struct SerializedState<C> {
     pub exit_actions: Vec<String>,
     /// State metadata
     pub metadata: std::collections::HashMap<String, String>,
+    /// Tags reported while the state is active
+    pub tags: Vec<String>,
+    /// Free-form metadata, available from `Machine::meta`
+    #[cfg(feature = "serde_json")]
+    pub meta: Option<serde_json::Value>,
+    /// Names of the events held while the state is active
+    pub deferred_events: Vec<String>,
+    /// Whether a history state restores the whole configuration below its parent
+    pub deep_history: bool,
 }
 
 impl<C> SerializedState<C> {
@@ -198,6 +209,11 @@ impl<C> SerializedState<C> {
             entry_actions: Vec::new(),
             exit_actions: Vec::new(),
             metadata: std::collections::HashMap::new(),
+            tags: Vec::new(),
+            #[cfg(feature = "serde_json")]
+            meta: None,
+            deferred_events: Vec::new(),
+            deep_history: false,
         }
     }
 
@@ -243,6 +259,31 @@ impl<C> SerializedState<C> {
         self
     }
 
+    /// Add a tag
+    pub fn add_tag(mut self, tag: String) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    /// Set free-form metadata
+    #[cfg(feature = "serde_json")]
+    pub fn meta(mut self, meta: serde_json::Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Add a deferred event
+    pub fn add_deferred_event(mut self, event: String) -> Self {
+        self.deferred_events.push(event);
+        self
+    }
+
+    /// Make a history state restore the whole configuration below its parent
+    pub fn deep_history(mut self) -> Self {
+        self.deep_history = true;
+        self
+    }
+
     /// Validate the state
     pub fn validate(&self) -> Result<(), PersistenceError> {
         if self.id.is_empty() {
@@ -296,9 +337,15 @@ impl<C> SerializedState<C> {
 }
 
 /// State types
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum StateType {
     /// Atomic state (no substates)
+    #[default]
     Atomic,
     /// Compound state (has substates)
     Compound,
@@ -325,9 +372,9 @@ impl StateType {
 /// Serialized transition data
 #[derive(Debug, Clone)]
 pub struct SerializedTransition<E> {
-    /// From state ID
+    /// From state ID, a dotted path for nested states
     pub from_state: String,
-    /// To state ID
+    /// To state ID, resolved like builder targets; empty stays in the from state
     pub to_state: String,
     /// Event that triggers the transition
     pub event: Option<String>,
@@ -337,6 +384,7 @@ pub struct SerializedTransition<E> {
     pub actions: Vec<String>,
     /// Transition metadata
     pub metadata: std::collections::HashMap<String, String>,
+    _phantom: std::marker::PhantomData<fn() -> E>,
 }
 
 impl<E> SerializedTransition<E> {
@@ -349,6 +397,7 @@ impl<E> SerializedTransition<E> {
             guards: Vec::new(),
             actions: Vec::new(),
             metadata: std::collections::HashMap::new(),
+            _phantom: std::marker::PhantomData,
         }
     }
 
@@ -581,6 +630,7 @@ mod serde_impl {
                 current_state: data.current_state,
                 metadata: data.metadata,
                 timestamp: data.timestamp,
+                _phantom: std::marker::PhantomData,
             })
         }
     }
@@ -140,10 +140,10 @@ impl<
             (
                 std::mem::zeroed(), // This is unsafe and should be replaced
                 std::mem::zeroed(), // This is unsafe and should be replaced
-                MachineStateImpl {
-                    value: crate::machine::states::StateValue::Simple("test".to_string()),
-                    context: std::mem::zeroed(), // This is unsafe and should be replaced
-                },
+                MachineStateImpl::new(
+                    crate::machine::states::StateValue::Simple("test".to_string()),
+                    std::mem::zeroed(), // This is unsafe and should be replaced
+                ),
             )
         }
     }
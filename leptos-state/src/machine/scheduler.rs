@@ -0,0 +1,483 @@
+//! Timer and task scheduling for delayed transitions and invoked services
+//!
+//! Delayed (`after`) transitions and invoked services run through a [`Scheduler`]
+//! so the same machine can be driven by tokio on the server, `setTimeout` and
+//! `spawn_local` in the browser and a [`ManualScheduler`] virtual clock in tests.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Identifier of a scheduled timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(pub u64);
+
+/// Callback run when a timer fires
+pub type TimerCallback = Box<dyn FnOnce() + Send + 'static>;
+
+/// Identifier of a spawned task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskId(pub u64);
+
+/// Future run in the background by a scheduler
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+pub type Task = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Future run in the background by a scheduler
+///
+/// Browser futures (`JsFuture`, `fetch`) are not `Send`; they all run on the page's
+/// single thread through `spawn_local`.
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+pub type Task = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+/// `Send`, except in the browser where tasks and invoked services need not be
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+pub trait MaybeSend: Send {}
+
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// `Send`, except in the browser where tasks and invoked services need not be
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+pub trait MaybeSend {}
+
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// Abstraction over the timer and task facilities of the host environment
+pub trait Scheduler: Send + Sync {
+    /// Run `callback` once `delay` has elapsed
+    fn schedule(&self, delay: Duration, callback: TimerCallback) -> TimerId;
+
+    /// Cancel a pending timer; cancelling a fired or unknown timer is a no-op
+    fn cancel(&self, id: TimerId);
+
+    /// Run `task` in the background
+    fn spawn(&self, task: Task) -> TaskId;
+
+    /// Stop a running task; aborting a finished or unknown task is a no-op
+    fn abort(&self, id: TaskId);
+}
+
+/// Get the scheduler matching the current target
+///
+/// Uses `setTimeout` in the browser and tokio everywhere else.
+pub fn default_scheduler() -> Arc<dyn Scheduler> {
+    #[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+    {
+        Arc::new(BrowserScheduler::new())
+    }
+    #[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+    {
+        Arc::new(TokioScheduler::new())
+    }
+}
+
+/// Scheduler backed by tokio tasks
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+#[derive(Default)]
+pub struct TokioScheduler {
+    next_id: AtomicU64,
+    tasks: Arc<Mutex<HashMap<TimerId, tokio::task::JoinHandle<()>>>>,
+    spawned: Arc<Mutex<HashMap<TaskId, tokio::task::JoinHandle<()>>>>,
+}
+
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+impl TokioScheduler {
+    /// Create a new tokio scheduler
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+impl Scheduler for TokioScheduler {
+    fn schedule(&self, delay: Duration, callback: TimerCallback) -> TimerId {
+        let id = TimerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("No tokio runtime available, timer {:?} will never fire", id);
+            return id;
+        };
+
+        // Hold the lock until the handle is stored, so a timer that fires at once
+        // cannot try to remove itself before it is inserted
+        let mut tasks = self.tasks.lock().unwrap();
+        let shared = self.tasks.clone();
+        let handle = runtime.spawn(async move {
+            tokio::time::sleep(delay).await;
+            shared.lock().unwrap().remove(&id);
+            callback();
+        });
+        tasks.insert(id, handle);
+
+        id
+    }
+
+    fn cancel(&self, id: TimerId) {
+        if let Some(handle) = self.tasks.lock().unwrap().remove(&id) {
+            handle.abort();
+        }
+    }
+
+    fn spawn(&self, task: Task) -> TaskId {
+        let id = TaskId(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("No tokio runtime available, task {:?} will never run", id);
+            return id;
+        };
+
+        // As in `schedule`, store the handle before the task can finish and remove it
+        let mut spawned = self.spawned.lock().unwrap();
+        let shared = self.spawned.clone();
+        let handle = runtime.spawn(async move {
+            task.await;
+            shared.lock().unwrap().remove(&id);
+        });
+        spawned.insert(id, handle);
+
+        id
+    }
+
+    fn abort(&self, id: TaskId) {
+        if let Some(handle) = self.spawned.lock().unwrap().remove(&id) {
+            handle.abort();
+        }
+    }
+}
+
+/// Scheduler backed by the browser's `setTimeout`
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+#[derive(Default)]
+pub struct BrowserScheduler {
+    next_id: AtomicU64,
+    handles: Arc<Mutex<HashMap<TimerId, leptos::prelude::TimeoutHandle>>>,
+    spawned: Arc<Mutex<HashMap<TaskId, futures::future::AbortHandle>>>,
+}
+
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+impl BrowserScheduler {
+    /// Create a new browser scheduler
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+impl Scheduler for BrowserScheduler {
+    fn schedule(&self, delay: Duration, callback: TimerCallback) -> TimerId {
+        let id = TimerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        let handles = self.handles.clone();
+        let result = leptos::prelude::set_timeout_with_handle(
+            move || {
+                handles.lock().unwrap().remove(&id);
+                callback();
+            },
+            delay,
+        );
+
+        match result {
+            Ok(handle) => {
+                self.handles.lock().unwrap().insert(id, handle);
+            }
+            Err(err) => {
+                tracing::warn!("Failed to schedule timer {:?}: {:?}", id, err);
+            }
+        }
+
+        id
+    }
+
+    fn cancel(&self, id: TimerId) {
+        if let Some(handle) = self.handles.lock().unwrap().remove(&id) {
+            handle.clear();
+        }
+    }
+
+    fn spawn(&self, task: Task) -> TaskId {
+        let id = TaskId(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        let (task, handle) = futures::future::abortable(task);
+        self.spawned.lock().unwrap().insert(id, handle);
+
+        let spawned = self.spawned.clone();
+        leptos::task::spawn_local(async move {
+            let _ = task.await;
+            spawned.lock().unwrap().remove(&id);
+        });
+
+        id
+    }
+
+    fn abort(&self, id: TaskId) {
+        if let Some(handle) = self.spawned.lock().unwrap().remove(&id) {
+            handle.abort();
+        }
+    }
+}
+
+/// Virtual clock scheduler for tests
+///
+/// Timers only fire when [`ManualScheduler::advance`] moves the clock past their
+/// due time, and spawned tasks only run when [`ManualScheduler::run_tasks`] is
+/// called. Clones share the same clock.
+#[derive(Clone, Default)]
+pub struct ManualScheduler {
+    inner: Arc<Mutex<ManualClock>>,
+}
+
+#[derive(Default)]
+struct ManualClock {
+    now: Duration,
+    next_id: u64,
+    pending: Vec<(Duration, TimerId, TimerCallback)>,
+    tasks: Vec<(TaskId, Task)>,
+}
+
+// SAFETY: browser tasks are not `Send`, but without the `atomics` target feature
+// wasm has a single thread, so the clock is never touched from another one
+#[cfg(all(
+    target_arch = "wasm32",
+    not(feature = "ssr"),
+    not(target_feature = "atomics")
+))]
+unsafe impl Send for ManualClock {}
+
+impl ManualScheduler {
+    /// Create a new virtual clock starting at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current virtual time
+    pub fn now(&self) -> Duration {
+        self.inner.lock().unwrap().now
+    }
+
+    /// Number of timers that have not fired or been cancelled yet
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().pending.len()
+    }
+
+    /// Move the clock forward, firing due timers in order
+    ///
+    /// Timers scheduled by a firing callback also fire if they fall due within
+    /// the advanced window.
+    pub fn advance(&self, by: Duration) {
+        let until = self.now() + by;
+
+        loop {
+            let next = {
+                let mut clock = self.inner.lock().unwrap();
+                let due = clock
+                    .pending
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (at, _, _))| *at <= until)
+                    .min_by_key(|(_, (at, id, _))| (*at, id.0))
+                    .map(|(index, _)| index);
+
+                due.map(|index| {
+                    let (at, _, callback) = clock.pending.remove(index);
+                    clock.now = at;
+                    callback
+                })
+            };
+
+            // Run the callback without holding the lock so it can schedule new timers
+            match next {
+                Some(callback) => callback(),
+                None => break,
+            }
+        }
+
+        self.inner.lock().unwrap().now = until;
+    }
+
+    /// Number of spawned tasks that have not finished or been aborted yet
+    pub fn pending_tasks(&self) -> usize {
+        self.inner.lock().unwrap().tasks.len()
+    }
+
+    /// Poll the spawned tasks until none of them can make progress
+    ///
+    /// Tasks are polled with a no-op waker, so a task waiting on something outside
+    /// the scheduler only resumes on the next call.
+    pub fn run_tasks(&self) {
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+
+        loop {
+            let ids: Vec<TaskId> = {
+                let clock = self.inner.lock().unwrap();
+                clock.tasks.iter().map(|(id, _)| *id).collect()
+            };
+
+            let mut progressed = false;
+            for id in ids {
+                // Tasks finished earlier in this round may have aborted this one
+                let task = {
+                    let mut clock = self.inner.lock().unwrap();
+                    let index = clock.tasks.iter().position(|(pending, _)| *pending == id);
+                    index.map(|index| clock.tasks.remove(index))
+                };
+                let Some((id, mut task)) = task else {
+                    continue;
+                };
+
+                // Poll without holding the lock so the task can spawn and abort tasks
+                if task.as_mut().poll(&mut cx).is_ready() {
+                    progressed = true;
+                } else {
+                    self.inner.lock().unwrap().tasks.push((id, task));
+                }
+            }
+
+            if !progressed {
+                break;
+            }
+        }
+    }
+}
+
+impl Scheduler for ManualScheduler {
+    fn schedule(&self, delay: Duration, callback: TimerCallback) -> TimerId {
+        let mut clock = self.inner.lock().unwrap();
+        let id = TimerId(clock.next_id);
+        clock.next_id += 1;
+        let due = clock.now + delay;
+        clock.pending.push((due, id, callback));
+        id
+    }
+
+    fn cancel(&self, id: TimerId) {
+        self.inner
+            .lock()
+            .unwrap()
+            .pending
+            .retain(|(_, pending, _)| *pending != id);
+    }
+
+    fn spawn(&self, task: Task) -> TaskId {
+        let mut clock = self.inner.lock().unwrap();
+        let id = TaskId(clock.next_id);
+        clock.next_id += 1;
+        clock.tasks.push((id, task));
+        id
+    }
+
+    fn abort(&self, id: TaskId) {
+        self.inner
+            .lock()
+            .unwrap()
+            .tasks
+            .retain(|(pending, _)| *pending != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_scheduler_fires_due_timers_in_order() {
+        let scheduler = ManualScheduler::new();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+
+        for (name, ms) in [("late", 30), ("early", 10), ("middle", 20)] {
+            let fired = fired.clone();
+            scheduler.schedule(
+                Duration::from_millis(ms),
+                Box::new(move || fired.lock().unwrap().push(name)),
+            );
+        }
+
+        scheduler.advance(Duration::from_millis(20));
+        assert_eq!(*fired.lock().unwrap(), vec!["early", "middle"]);
+        assert_eq!(scheduler.pending(), 1);
+
+        scheduler.advance(Duration::from_millis(10));
+        assert_eq!(*fired.lock().unwrap(), vec!["early", "middle", "late"]);
+        assert_eq!(scheduler.now(), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn manual_scheduler_cancel_prevents_firing() {
+        let scheduler = ManualScheduler::new();
+        let fired = Arc::new(Mutex::new(false));
+
+        let flag = fired.clone();
+        let id = scheduler.schedule(
+            Duration::from_secs(1),
+            Box::new(move || *flag.lock().unwrap() = true),
+        );
+        scheduler.cancel(id);
+        scheduler.advance(Duration::from_secs(2));
+
+        assert!(!*fired.lock().unwrap());
+        assert_eq!(scheduler.pending(), 0);
+    }
+
+    #[test]
+    fn manual_scheduler_runs_tasks_until_they_stall() {
+        let scheduler = ManualScheduler::new();
+        let (sender, receiver) = futures::channel::oneshot::channel::<i32>();
+        let received = Arc::new(Mutex::new(None));
+
+        let slot = received.clone();
+        scheduler.spawn(Box::pin(async move {
+            *slot.lock().unwrap() = receiver.await.ok();
+        }));
+
+        scheduler.run_tasks();
+        assert_eq!(scheduler.pending_tasks(), 1);
+
+        sender.send(7).unwrap();
+        scheduler.run_tasks();
+        assert_eq!(*received.lock().unwrap(), Some(7));
+        assert_eq!(scheduler.pending_tasks(), 0);
+    }
+
+    #[test]
+    fn manual_scheduler_abort_drops_the_task() {
+        let scheduler = ManualScheduler::new();
+        let ran = Arc::new(Mutex::new(false));
+
+        let flag = ran.clone();
+        let id = scheduler.spawn(Box::pin(async move {
+            *flag.lock().unwrap() = true;
+        }));
+        scheduler.abort(id);
+        scheduler.run_tasks();
+
+        assert!(!*ran.lock().unwrap());
+        assert_eq!(scheduler.pending_tasks(), 0);
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", not(feature = "ssr"))))]
+    #[test]
+    fn tokio_scheduler_forgets_tasks_that_finish_at_once() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_time()
+            .build()
+            .unwrap();
+        let scheduler = TokioScheduler::new();
+
+        runtime.block_on(async {
+            for _ in 0..100 {
+                scheduler.spawn(Box::pin(async {}));
+                scheduler.schedule(Duration::ZERO, Box::new(|| {}));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        });
+
+        assert!(scheduler.spawned.lock().unwrap().is_empty());
+        assert!(scheduler.tasks.lock().unwrap().is_empty());
+    }
+}
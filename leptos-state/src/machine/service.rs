@@ -0,0 +1,689 @@
+//! Machine interpreter that owns the current state and processes queued events
+//!
+//! A [`MachineService`] runs a single machine instance, with its delayed
+//! transitions, invoked services and spawned actors. Cross-cutting behavior such
+//! as persistence, history, visualization and profiling attaches to it as
+//! [`MachinePlugin`]s instead of wrapping the machine.
+
+use super::*;
+use crate::machine::scheduler::Scheduler;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Behavior attached to a [`MachineService`]
+///
+/// Hooks run in the order the plugins were added, on the thread processing the
+/// event. Events a plugin sends from a hook are queued behind the current one.
+/// Every hook has an empty default implementation.
+pub trait MachinePlugin<C: Send + Sync, E>: Send {
+    /// Name of the plugin, for diagnostics
+    fn name(&self) -> &str;
+
+    /// Called when the service starts, with the state it starts in
+    fn on_start(&mut self, _state: &MachineStateImpl<C>) {}
+
+    /// Called before `event` is processed in `state`
+    fn before_transition(&mut self, _state: &MachineStateImpl<C>, _event: &E) {}
+
+    /// Called after every step, with the event that caused it
+    ///
+    /// An event the machine rejects (a failed action without an error target, a
+    /// violated invariant) gets an unchanged outcome, so every `before_transition`
+    /// is followed by a call. Steps taken by timers and invoked services follow the
+    /// events sent to the service. `event` is `None` for a delayed transition out
+    /// of the state the service started in.
+    fn after_transition(&mut self, _event: Option<&E>, _outcome: &TransitionOutcome<C>) {}
+
+    /// Called when the service stops, with the state it stopped in
+    fn on_stop(&mut self, _state: &MachineStateImpl<C>) {}
+}
+
+/// Lifecycle of a [`MachineService`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ServiceStatus {
+    /// Events are queued until the service starts
+    #[default]
+    NotStarted,
+    Running,
+    /// Events are dropped
+    Stopped,
+}
+
+/// Identifier of a state subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(pub u64);
+
+/// Callback notified with the state after every processed event
+type Subscriber<C> = Arc<dyn Fn(&MachineStateImpl<C>) + Send + Sync>;
+
+/// Id the children of a service are spawned under in its actor system
+const SERVICE_ACTOR: &str = "#service";
+
+/// Entry of the service queue
+enum Work<C: Send + Sync, E> {
+    /// Event sent to the service
+    Event(E),
+    /// Step the machine took, waiting for its effects and hooks to run
+    Stepped(Option<E>, Box<TransitionOutcome<C>>),
+}
+
+/// Interpreter running a machine instance
+///
+/// Events are processed one at a time in the order they were sent. Subscribers
+/// may send events while being notified; those are queued and processed before
+/// the outer `send` returns. Clones share the same instance.
+///
+/// Once started, the service runs the machine on a [`TimedMachine`]: delayed
+/// transitions and invoked services step it in the background. Actors its
+/// actions spawn run in an [`ActorSystem`] owned by the service, and the events
+/// they send back are queued like any other.
+pub struct MachineService<
+    C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+    E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    inner: Arc<ServiceInner<C, E>>,
+}
+
+struct ServiceInner<
+    C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+    E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    machine: Machine<C, E, C>,
+    scheduler: Mutex<Arc<dyn Scheduler>>,
+    /// Runs the machine while the service is running
+    runtime: Mutex<Option<Arc<TimedMachine<C, E>>>>,
+    state: Mutex<MachineStateImpl<C>>,
+    status: Mutex<ServiceStatus>,
+    queue: Mutex<VecDeque<Work<C, E>>>,
+    actors: Mutex<ActorSystem>,
+    /// Set while an event loop drains the queue
+    processing: AtomicBool,
+    plugins: Mutex<Vec<Box<dyn MachinePlugin<C, E>>>>,
+    subscribers: Mutex<Vec<(SubscriptionId, Subscriber<C>)>>,
+    next_subscription: AtomicU64,
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > MachineService<C, E>
+{
+    /// Create a service for `machine` in its initial state with a default context
+    pub fn new(machine: Machine<C, E, C>) -> Self {
+        let initial = machine.initial_state();
+        Self::with_state(machine, initial)
+    }
+
+    /// Create a service for `machine` in its initial state with the given context
+    pub fn with_context(machine: Machine<C, E, C>, context: C) -> Self {
+        let initial = machine.initial_with_context(context);
+        Self::with_state(machine, initial)
+    }
+
+    /// Create a service resuming `machine` from a previously saved state
+    pub fn with_state(machine: Machine<C, E, C>, state: MachineStateImpl<C>) -> Self {
+        Self {
+            inner: Arc::new(ServiceInner {
+                machine,
+                scheduler: Mutex::new(default_scheduler()),
+                runtime: Mutex::new(None),
+                state: Mutex::new(state),
+                status: Mutex::new(ServiceStatus::NotStarted),
+                queue: Mutex::new(VecDeque::new()),
+                actors: Mutex::new(ActorSystem::new()),
+                processing: AtomicBool::new(false),
+                plugins: Mutex::new(Vec::new()),
+                subscribers: Mutex::new(Vec::new()),
+                next_subscription: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Run timers and invoked services on `scheduler` instead of the default one
+    ///
+    /// Takes effect the next time the service starts.
+    pub fn scheduler(self, scheduler: Arc<dyn Scheduler>) -> Self {
+        *self.inner.scheduler.lock().unwrap() = scheduler;
+        self
+    }
+
+    /// Attach a plugin
+    pub fn plugin<P: MachinePlugin<C, E> + 'static>(self, plugin: P) -> Self {
+        self.inner.plugins.lock().unwrap().push(Box::new(plugin));
+        self
+    }
+
+    /// Names of the attached plugins, in the order their hooks run
+    pub fn plugin_names(&self) -> Vec<String> {
+        let plugins = self.inner.plugins.lock().unwrap();
+        plugins
+            .iter()
+            .map(|plugin| plugin.name().to_string())
+            .collect()
+    }
+
+    /// Start processing events, including those sent before the start
+    ///
+    /// Plugins start first, so the events they send are queued as well.
+    pub fn start(&self) {
+        if self.status() == ServiceStatus::Running {
+            return;
+        }
+
+        let state = self.state();
+        self.each_plugin(|plugin| plugin.on_start(&state));
+
+        let scheduler = self.inner.scheduler.lock().unwrap().clone();
+        let runtime =
+            TimedMachine::with_state(self.inner.machine.clone(), scheduler, state.clone());
+        let weak = Arc::downgrade(&self.inner);
+        runtime.observe(move |event, outcome| {
+            // The service was dropped while the machine was still running
+            let Some(inner) = weak.upgrade() else {
+                return;
+            };
+            // The step already happened, so its hooks run before anything queued
+            inner
+                .queue
+                .lock()
+                .unwrap()
+                .push_front(Work::Stepped(event.cloned(), Box::new(outcome.clone())));
+            MachineService { inner }.process();
+        });
+        *self.inner.runtime.lock().unwrap() = Some(Arc::new(runtime));
+        *self.inner.status.lock().unwrap() = ServiceStatus::Running;
+        self.notify(&state);
+
+        self.process();
+    }
+
+    /// Stop processing events; queued events are dropped
+    ///
+    /// Pending timers, running services and spawned actors are stopped as well.
+    pub fn stop(&self) {
+        {
+            let mut status = self.inner.status.lock().unwrap();
+            if *status != ServiceStatus::Running {
+                *status = ServiceStatus::Stopped;
+                return;
+            }
+            *status = ServiceStatus::Stopped;
+        }
+
+        if let Some(runtime) = self.inner.runtime.lock().unwrap().take() {
+            runtime.stop();
+        }
+        self.inner.queue.lock().unwrap().clear();
+        let mut actors = self.inner.actors.lock().unwrap();
+        let children: Vec<String> = actors
+            .children(SERVICE_ACTOR)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        for child in children {
+            actors.stop(&child);
+        }
+        drop(actors);
+
+        let state = self.state();
+        self.each_plugin(|plugin| plugin.on_stop(&state));
+    }
+
+    /// Queue `event` and process the queue unless the service is not running yet
+    pub fn send(&self, event: E) {
+        if self.status() == ServiceStatus::Stopped {
+            tracing::warn!("Dropping event {:?} sent to a stopped service", event);
+            return;
+        }
+
+        self.inner
+            .queue
+            .lock()
+            .unwrap()
+            .push_back(Work::Event(event));
+        self.process();
+    }
+
+    /// Call `callback` with the state after every processed event
+    pub fn subscribe<F>(&self, callback: F) -> SubscriptionId
+    where
+        F: Fn(&MachineStateImpl<C>) + Send + Sync + 'static,
+    {
+        let id = SubscriptionId(self.inner.next_subscription.fetch_add(1, Ordering::Relaxed));
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .push((id, Arc::new(callback)));
+        id
+    }
+
+    /// Remove a subscription; unknown ids are ignored
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|(subscription, _)| *subscription != id);
+    }
+
+    /// Get a snapshot of the current state
+    pub fn state(&self) -> MachineStateImpl<C> {
+        self.inner.state.lock().unwrap().clone()
+    }
+
+    /// Get a snapshot of the state of an actor the machine spawned
+    pub fn actor_state<C2: Clone + Send + Sync + 'static, E2>(
+        &self,
+        actor: &ActorRef<C2, E2>,
+    ) -> Option<MachineStateImpl<C2>> {
+        self.inner.actors.lock().unwrap().state(actor)
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        *self.inner.status.lock().unwrap()
+    }
+
+    /// Get the underlying machine definition
+    pub fn machine(&self) -> &Machine<C, E, C> {
+        &self.inner.machine
+    }
+
+    /// Process queued events until the queue is empty
+    ///
+    /// Returns right away when another call is already draining the queue; that
+    /// call picks up the events queued meanwhile.
+    fn process(&self) {
+        if self.status() != ServiceStatus::Running {
+            return;
+        }
+
+        while !self.inner.processing.swap(true, Ordering::AcqRel) {
+            loop {
+                let Some(work) = self.inner.queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                match work {
+                    Work::Event(event) => self.process_event(event),
+                    Work::Stepped(event, outcome) => self.finish_step(event, *outcome),
+                }
+            }
+            self.inner.processing.store(false, Ordering::Release);
+
+            // Events sent between draining the queue and clearing the flag
+            if self.inner.queue.lock().unwrap().is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Hand `event` to the machine; the runtime queues the step it takes
+    fn process_event(&self, event: E) {
+        let runtime = self.inner.runtime.lock().unwrap().clone();
+        let Some(runtime) = runtime else {
+            return;
+        };
+
+        let state = self.state();
+        self.each_plugin(|plugin| plugin.before_transition(&state, &event));
+        runtime.send(event);
+    }
+
+    /// Record a step, run the actors it spawned and the events it sent, then run
+    /// the hooks and notify the subscribers
+    fn finish_step(&self, event: Option<E>, outcome: TransitionOutcome<C>) {
+        if self.status() != ServiceStatus::Running {
+            return;
+        }
+        *self.inner.state.lock().unwrap() = outcome.state.clone();
+
+        let relayed = self.inner.actors.lock().unwrap().relay(
+            SERVICE_ACTOR,
+            outcome.spawned.clone(),
+            outcome.sent.clone(),
+        );
+        let replies = relayed.unwrap_or_else(|err| {
+            tracing::error!("Actor messages dropped: {}", err);
+            Vec::new()
+        });
+        for reply in replies {
+            match reply.downcast_ref::<E>() {
+                Some(reply) => self
+                    .inner
+                    .queue
+                    .lock()
+                    .unwrap()
+                    .push_back(Work::Event(reply.clone())),
+                None => tracing::warn!(
+                    "Ignoring {} event, the service handles {}",
+                    reply.type_name(),
+                    std::any::type_name::<E>()
+                ),
+            }
+        }
+
+        self.each_plugin(|plugin| plugin.after_transition(event.as_ref(), &outcome));
+        self.notify(&outcome.state);
+    }
+
+    /// Run a hook on every plugin without holding the plugins lock, so hooks can
+    /// use the service
+    fn each_plugin(&self, mut hook: impl FnMut(&mut dyn MachinePlugin<C, E>)) {
+        let mut plugins = std::mem::take(&mut *self.inner.plugins.lock().unwrap());
+        for plugin in plugins.iter_mut() {
+            hook(plugin.as_mut());
+        }
+
+        // Keep the plugins attached while the hooks ran after the earlier ones
+        let mut attached = self.inner.plugins.lock().unwrap();
+        plugins.append(&mut attached);
+        *attached = plugins;
+    }
+
+    /// Notify the subscribers without holding any lock, so they can send events
+    fn notify(&self, state: &MachineStateImpl<C>) {
+        let subscribers: Vec<Subscriber<C>> = self
+            .inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, subscriber)| subscriber.clone())
+            .collect();
+
+        for subscriber in subscribers {
+            subscriber(state);
+        }
+    }
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > Clone for MachineService<C, E>
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::scheduler::ManualScheduler;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        toggles: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Toggle,
+    }
+
+    fn toggle_machine() -> Machine<TestContext, TestEvent, TestContext> {
+        MachineBuilder::<TestContext, TestEvent>::new()
+            .state("off")
+            .on(TestEvent::Toggle, "on")
+            .state("on")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.toggles += 1)
+            .on(TestEvent::Toggle, "off")
+            .initial("off")
+            .build()
+    }
+
+    /// Records every hook call
+    struct RecordingPlugin(Arc<Mutex<Vec<String>>>);
+
+    impl MachinePlugin<TestContext, TestEvent> for RecordingPlugin {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn on_start(&mut self, state: &MachineStateImpl<TestContext>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("start {}", state.value()));
+        }
+
+        fn before_transition(&mut self, state: &MachineStateImpl<TestContext>, _: &TestEvent) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("before {}", state.value()));
+        }
+
+        fn after_transition(
+            &mut self,
+            _: Option<&TestEvent>,
+            outcome: &TransitionOutcome<TestContext>,
+        ) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("after {}", outcome.target));
+        }
+
+        fn on_stop(&mut self, state: &MachineStateImpl<TestContext>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("stop {}", state.value()));
+        }
+    }
+
+    #[test]
+    fn events_sent_before_start_are_queued() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let service = MachineService::new(toggle_machine()).plugin(RecordingPlugin(calls.clone()));
+
+        service.send(TestEvent::Toggle);
+        assert!(service.state().matches("off"));
+
+        service.start();
+        assert!(service.state().matches("on"));
+
+        service.stop();
+        service.send(TestEvent::Toggle);
+        assert!(service.state().matches("on"));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["start off", "before off", "after on", "stop on"]
+        );
+    }
+
+    #[test]
+    fn subscribers_can_send_events() {
+        let service = MachineService::new(toggle_machine());
+        service.start();
+
+        // Toggle straight back off whenever the machine turns on
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (sender, log) = (service.clone(), seen.clone());
+        let subscription = service.subscribe(move |state| {
+            log.lock().unwrap().push(state.value().to_string());
+            if state.matches("on") {
+                sender.send(TestEvent::Toggle);
+            }
+        });
+
+        service.send(TestEvent::Toggle);
+        assert!(service.state().matches("off"));
+        assert_eq!(service.state().context().toggles, 1);
+        assert_eq!(*seen.lock().unwrap(), vec!["on", "off"]);
+
+        service.unsubscribe(subscription);
+        service.send(TestEvent::Toggle);
+        assert!(service.state().matches("on"));
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    /// Turns the machine back off from its hooks
+    struct SwitchOffPlugin(MachineService<TestContext, TestEvent>);
+
+    impl MachinePlugin<TestContext, TestEvent> for SwitchOffPlugin {
+        fn name(&self) -> &str {
+            "switch-off"
+        }
+
+        fn on_start(&mut self, _: &MachineStateImpl<TestContext>) {
+            self.0.send(TestEvent::Toggle);
+        }
+
+        fn after_transition(
+            &mut self,
+            _: Option<&TestEvent>,
+            outcome: &TransitionOutcome<TestContext>,
+        ) {
+            if outcome.state.matches("on") && outcome.state.context().toggles < 2 {
+                self.0.send(TestEvent::Toggle);
+            }
+        }
+    }
+
+    #[test]
+    fn plugins_can_send_events_from_their_hooks() {
+        let service = MachineService::new(toggle_machine());
+        let service = service.clone().plugin(SwitchOffPlugin(service));
+
+        // The plugin turns the machine on when starting and back off right away
+        service.start();
+        assert!(service.state().matches("off"));
+        assert_eq!(service.state().context().toggles, 1);
+
+        service.send(TestEvent::Toggle);
+        assert!(service.state().matches("on"));
+        assert_eq!(service.state().context().toggles, 2);
+        service.stop();
+    }
+
+    /// Records every transition the machine takes
+    struct TransitionLog(Arc<Mutex<Vec<String>>>);
+
+    impl MachineMiddleware<TestContext, TestEvent> for TransitionLog {
+        fn after_transition(
+            &self,
+            event: Option<&TestEvent>,
+            old: &MachineStateImpl<TestContext>,
+            new: &MachineStateImpl<TestContext>,
+        ) {
+            let event = event.map_or("eventless".to_string(), |event| format!("{:?}", event));
+            let entry = format!("{}: {} -> {}", event, old.value(), new.value());
+            self.0.lock().unwrap().push(entry);
+        }
+    }
+
+    #[test]
+    fn delayed_transitions_and_services_run_once_started() {
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .middleware(TransitionLog(transitions.clone()))
+            .state("off")
+            .after(std::time::Duration::from_secs(1), "on")
+            .state("on")
+            .invoke(|_: &TestContext| async { Ok::<_, ()>(TestEvent::Toggle) })
+            .on_done("off")
+            .initial("off")
+            .build();
+        let scheduler = ManualScheduler::new();
+        let service = MachineService::new(machine).scheduler(Arc::new(scheduler.clone()));
+
+        scheduler.advance(std::time::Duration::from_secs(1));
+        assert!(service.state().matches("off"));
+
+        service.start();
+        scheduler.advance(std::time::Duration::from_secs(1));
+        assert!(service.state().matches("on"));
+
+        scheduler.run_tasks();
+        assert!(service.state().matches("off"));
+
+        service.stop();
+        scheduler.advance(std::time::Duration::from_secs(1));
+        assert!(service.state().matches("off"));
+        assert_eq!(scheduler.pending(), 0);
+        assert_eq!(
+            *transitions.lock().unwrap(),
+            vec!["eventless: off -> on", "Toggle: on -> off"]
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct ChildContext;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum ChildEvent {
+        Finish,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct ParentContext {
+        children: Vec<ActorRef<ChildContext, ChildEvent>>,
+        finished: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum ParentEvent {
+        Spawn,
+        FinishAll,
+        Finished(ActorDone),
+    }
+
+    impl From<ActorDone> for ParentEvent {
+        fn from(done: ActorDone) -> Self {
+            ParentEvent::Finished(done)
+        }
+    }
+
+    #[test]
+    fn spawned_actors_run_and_reply_to_the_service() {
+        let child = MachineBuilder::<ChildContext, ChildEvent>::new()
+            .state("working")
+            .on(ChildEvent::Finish, "finished")
+            .state("finished")
+            .final_state()
+            .initial("working")
+            .build();
+        let parent = MachineBuilder::<ParentContext, ParentEvent>::new()
+            .state("running")
+            .on_event(ParentEvent::Spawn)
+            .action_with_context(move |ctx: &mut ParentContext, _, effects| {
+                ctx.children.push(effects.spawn("child", child.clone()));
+            })
+            .on_event(ParentEvent::FinishAll)
+            .action_with_context(|ctx: &mut ParentContext, _, effects| {
+                for child in &ctx.children {
+                    effects.send(child, ChildEvent::Finish);
+                }
+            })
+            .on_event(ParentEvent::Finished(ActorDone {
+                id: String::new(),
+                state: String::new(),
+            }))
+            .match_variant()
+            .action(FunctionAction::new(
+                |ctx: &mut ParentContext, event: &ParentEvent| {
+                    if let ParentEvent::Finished(done) = event {
+                        ctx.finished.push(done.id.clone());
+                    }
+                },
+            ))
+            .initial("running")
+            .build();
+
+        let service = MachineService::new(parent);
+        service.start();
+        service.send(ParentEvent::Spawn);
+        let child = service.state().context().children[0].clone();
+        assert!(service.actor_state(&child).unwrap().matches("working"));
+
+        service.send(ParentEvent::FinishAll);
+        assert_eq!(service.state().context().finished, vec!["child"]);
+        assert!(service.actor_state(&child).is_none());
+    }
+}
@@ -0,0 +1,513 @@
+//! Built-in plugins for [`MachineService`]
+//!
+//! Plugins keep their data behind a shared handle, so keep a clone of the plugin
+//! to read it after attaching the original to a service.
+
+use super::*;
+use crate::machine::scheduler::Scheduler;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Records the states a service went through
+pub struct HistoryPlugin<C: Send + Sync> {
+    states: Arc<Mutex<VecDeque<MachineStateImpl<C>>>>,
+    limit: usize,
+}
+
+impl<C: Clone + Send + Sync> HistoryPlugin<C> {
+    /// Keep at most `limit` states, dropping the oldest first
+    pub fn new(limit: usize) -> Self {
+        Self {
+            states: Arc::new(Mutex::new(VecDeque::new())),
+            limit,
+        }
+    }
+
+    /// Recorded states, oldest first
+    pub fn states(&self) -> Vec<MachineStateImpl<C>> {
+        self.states.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Forget the recorded states
+    pub fn clear(&self) {
+        self.states.lock().unwrap().clear();
+    }
+
+    fn record(&self, state: &MachineStateImpl<C>) {
+        let mut states = self.states.lock().unwrap();
+        states.push_back(state.clone());
+        while states.len() > self.limit {
+            states.pop_front();
+        }
+    }
+}
+
+impl<C: Send + Sync> Clone for HistoryPlugin<C> {
+    fn clone(&self) -> Self {
+        Self {
+            states: self.states.clone(),
+            limit: self.limit,
+        }
+    }
+}
+
+impl<C: Clone + Send + Sync, E> MachinePlugin<C, E> for HistoryPlugin<C> {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn on_start(&mut self, state: &MachineStateImpl<C>) {
+        self.record(state);
+    }
+
+    fn after_transition(&mut self, _event: Option<&E>, outcome: &TransitionOutcome<C>) {
+        if outcome.changed {
+            self.record(&outcome.state);
+        }
+    }
+}
+
+/// Timing statistics collected by a [`ProfilingPlugin`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfilingStats {
+    /// Events sent to the service, rejected ones included
+    ///
+    /// Steps taken by timers and invoked services are not counted.
+    pub events: usize,
+    /// Events that took a transition
+    pub transitions: usize,
+    /// Time spent processing events
+    pub total: Duration,
+    /// Longest time spent on a single event
+    pub slowest: Duration,
+}
+
+impl ProfilingStats {
+    /// Average time spent per event
+    pub fn average(&self) -> Duration {
+        match self.events {
+            0 => Duration::ZERO,
+            events => self.total / events as u32,
+        }
+    }
+}
+
+/// Measures how long a service takes to process the events sent to it
+#[derive(Clone, Default)]
+pub struct ProfilingPlugin {
+    stats: Arc<Mutex<ProfilingStats>>,
+    started: Option<Instant>,
+}
+
+impl ProfilingPlugin {
+    /// Start with empty statistics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Statistics collected so far, shared by every clone of the plugin
+    pub fn stats(&self) -> ProfilingStats {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+impl<C: Send + Sync, E> MachinePlugin<C, E> for ProfilingPlugin {
+    fn name(&self) -> &str {
+        "profiling"
+    }
+
+    fn before_transition(&mut self, _state: &MachineStateImpl<C>, _event: &E) {
+        self.started = Some(Instant::now());
+    }
+
+    fn after_transition(&mut self, _event: Option<&E>, outcome: &TransitionOutcome<C>) {
+        // Steps taken by timers and invoked services are not timed
+        let Some(started) = self.started.take() else {
+            return;
+        };
+        let elapsed = started.elapsed();
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.events += 1;
+        if outcome.changed {
+            stats.transitions += 1;
+        }
+        stats.total += elapsed;
+        stats.slowest = stats.slowest.max(elapsed);
+    }
+}
+
+/// Function a [`PersistencePlugin`] saves states with
+type SaveFn<C> = Box<dyn FnMut(&MachineStateImpl<C>) + Send>;
+
+/// Hands every state the service settles in to a save function
+///
+/// Pair it with [`MachineService::with_state`] to resume from the saved state.
+pub struct PersistencePlugin<C: Send + Sync> {
+    save: SaveFn<C>,
+}
+
+impl<C: Send + Sync> PersistencePlugin<C> {
+    /// Call `save` whenever a transition changes the state and once more on stop
+    pub fn new<F>(save: F) -> Self
+    where
+        F: FnMut(&MachineStateImpl<C>) + Send + 'static,
+    {
+        Self {
+            save: Box::new(save),
+        }
+    }
+}
+
+impl<C: Send + Sync, E> MachinePlugin<C, E> for PersistencePlugin<C> {
+    fn name(&self) -> &str {
+        "persistence"
+    }
+
+    fn after_transition(&mut self, _event: Option<&E>, outcome: &TransitionOutcome<C>) {
+        if outcome.changed {
+            (self.save)(&outcome.state);
+        }
+    }
+
+    fn on_stop(&mut self, state: &MachineStateImpl<C>) {
+        (self.save)(state);
+    }
+}
+
+/// Feeds the steps of a service to a [`MachineVisualizer`] and a [`StateMonitor`]
+pub struct VisualizationPlugin<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    visualizer: Arc<Mutex<MachineVisualizer<C, E>>>,
+    monitor: Arc<Mutex<StateMonitor<C, E>>>,
+    started: Option<Instant>,
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > VisualizationPlugin<C, E>
+{
+    /// Visualize `machine` with the default configuration
+    pub fn new(machine: &Machine<C, E, C>) -> Self {
+        Self::from_parts(machine.visualizer(), machine.monitor())
+    }
+
+    /// Visualize `machine` with a custom configuration
+    pub fn with_config(machine: &Machine<C, E, C>, config: VisualizationConfig) -> Self {
+        Self::from_parts(machine.visualizer_with_config(config), machine.monitor())
+    }
+
+    fn from_parts(visualizer: MachineVisualizer<C, E>, monitor: StateMonitor<C, E>) -> Self {
+        Self {
+            visualizer: Arc::new(Mutex::new(visualizer)),
+            monitor: Arc::new(Mutex::new(monitor)),
+            started: None,
+        }
+    }
+
+    /// Get the visualizer the transitions are recorded in
+    pub fn visualizer(&self) -> MutexGuard<'_, MachineVisualizer<C, E>> {
+        self.visualizer.lock().unwrap()
+    }
+
+    /// Get the monitor notified of state changes
+    pub fn monitor(&self) -> MutexGuard<'_, StateMonitor<C, E>> {
+        self.monitor.lock().unwrap()
+    }
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > Clone for VisualizationPlugin<C, E>
+{
+    fn clone(&self) -> Self {
+        Self {
+            visualizer: self.visualizer.clone(),
+            monitor: self.monitor.clone(),
+            started: None,
+        }
+    }
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > MachinePlugin<C, E> for VisualizationPlugin<C, E>
+{
+    fn name(&self) -> &str {
+        "visualization"
+    }
+
+    fn before_transition(&mut self, _state: &MachineStateImpl<C>, _event: &E) {
+        self.started = Some(Instant::now());
+    }
+
+    fn after_transition(&mut self, event: Option<&E>, outcome: &TransitionOutcome<C>) {
+        let from = outcome.source.to_string();
+        let mut visualizer = self.visualizer.lock().unwrap();
+        let mut monitor = self.monitor.lock().unwrap();
+
+        for error in &outcome.errors {
+            let error =
+                ErrorEvent::new(ErrorEventType::ActionError, error.to_string(), from.clone());
+            monitor.notify_error(&error);
+            visualizer.record_error(error);
+        }
+
+        if outcome.changed {
+            let to = outcome.target.to_string();
+            visualizer.record_transition(TransitionEvent::success(
+                from.clone(),
+                to.clone(),
+                event.cloned(),
+                Some(outcome.state.context().clone()),
+            ));
+
+            let mut change = StateChangeEvent::new(from, to, StateChangeType::Transition)
+                .with_context(outcome.state.context().clone());
+            if let Some(event) = event {
+                change = change.with_event(event.clone());
+            }
+            monitor.notify_state_change(&change);
+        }
+
+        // Steps taken by timers and invoked services are not timed
+        if let Some(started) = self.started.take() {
+            let performance =
+                PerformanceEvent::new(PerformanceEventType::Transition, started.elapsed());
+            monitor.notify_performance(&performance);
+            visualizer.record_performance(performance);
+        }
+    }
+}
+
+/// Forwards the transitions of a service to the adapters of an [`IntegrationManager`]
+///
+/// Events are sent from a task on the scheduler, so slow adapters never hold up
+/// the service.
+pub struct IntegrationPlugin<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    /// Source the integration events are tagged with
+    source: String,
+    manager: Arc<IntegrationManager<C, E>>,
+    scheduler: Arc<dyn Scheduler>,
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > IntegrationPlugin<C, E>
+{
+    /// Forward to `manager` from tasks spawned on `scheduler`, tagged with `source`
+    pub fn new(
+        source: impl Into<String>,
+        manager: Arc<IntegrationManager<C, E>>,
+        scheduler: Arc<dyn Scheduler>,
+    ) -> Self {
+        Self {
+            source: source.into(),
+            manager,
+            scheduler,
+        }
+    }
+
+    /// Integration event describing `outcome`
+    fn event(&self, event: Option<&E>, outcome: &TransitionOutcome<C>) -> IntegrationEvent {
+        IntegrationEvent::new(
+            "transition".to_string(),
+            self.source.clone(),
+            serde_json::json!({
+                "from": outcome.source.to_string(),
+                "to": outcome.target.to_string(),
+                "event": event.map(|event| format!("{:?}", event)),
+            }),
+        )
+    }
+}
+
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > MachinePlugin<C, E> for IntegrationPlugin<C, E>
+{
+    fn name(&self) -> &str {
+        "integration"
+    }
+
+    fn after_transition(&mut self, event: Option<&E>, outcome: &TransitionOutcome<C>) {
+        if !outcome.changed {
+            return;
+        }
+
+        let event = self.event(event, outcome);
+        let manager = self.manager.clone();
+        self.scheduler.spawn(Box::pin(async move {
+            if let Err(err) = manager.send_event(event).await {
+                tracing::warn!("Integration event not sent: {}", err.message);
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::scheduler::ManualScheduler;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        visits: usize,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Next,
+        Ignored,
+    }
+
+    fn steps_machine() -> Machine<TestContext, TestEvent, TestContext> {
+        MachineBuilder::<TestContext, TestEvent>::new()
+            .state("a")
+            .on(TestEvent::Next, "b")
+            .state("b")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.visits += 1)
+            .on(TestEvent::Next, "c")
+            .state("c")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.visits += 1)
+            .initial("a")
+            .build()
+    }
+
+    #[test]
+    fn plugins_combine_on_one_service() {
+        let history = HistoryPlugin::new(2);
+        let profiling = ProfilingPlugin::new();
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let store = saved.clone();
+        let service = MachineService::new(steps_machine())
+            .plugin(history.clone())
+            .plugin(profiling.clone())
+            .plugin(PersistencePlugin::new(
+                move |state: &MachineStateImpl<TestContext>| {
+                    store.lock().unwrap().push(state.value().to_string())
+                },
+            ));
+        assert_eq!(
+            service.plugin_names(),
+            vec!["history", "profiling", "persistence"]
+        );
+
+        service.start();
+        service.send(TestEvent::Next);
+        service.send(TestEvent::Ignored);
+        service.send(TestEvent::Next);
+        service.stop();
+
+        let states: Vec<String> = history
+            .states()
+            .iter()
+            .map(|state| state.value().to_string())
+            .collect();
+        assert_eq!(states, vec!["b", "c"]);
+        assert_eq!(history.states()[1].context().visits, 2);
+        assert_eq!(profiling.stats().events, 3);
+        assert_eq!(profiling.stats().transitions, 2);
+        assert_eq!(*saved.lock().unwrap(), vec!["b", "c", "c"]);
+    }
+
+    #[test]
+    fn visualization_plugin_records_the_transitions_of_the_service() {
+        let machine = steps_machine();
+        let visualization = VisualizationPlugin::new(&machine);
+        let service = MachineService::new(machine).plugin(visualization.clone());
+
+        service.start();
+        service.send(TestEvent::Next);
+        service.send(TestEvent::Ignored);
+        service.send(TestEvent::Next);
+
+        let visualizer = visualization.visualizer();
+        let transitions: Vec<(&str, &str)> = visualizer
+            .event_history
+            .iter()
+            .map(|event| (event.from_state.as_str(), event.to_state.as_str()))
+            .collect();
+        assert_eq!(transitions, vec![("a", "b"), ("b", "c")]);
+        assert_eq!(
+            visualizer.event_history[1].context.as_ref().unwrap().visits,
+            2
+        );
+        assert_eq!(visualizer.performance_metrics.len(), 3);
+        assert!(visualizer.export_diagram(ExportFormat::Dot).is_ok());
+        drop(visualizer);
+
+        let monitor = visualization.monitor();
+        assert_eq!(monitor.get_stats().total_state_changes, 2);
+        assert_eq!(monitor.get_stats().total_performance_events, 3);
+    }
+
+    #[test]
+    fn plugins_see_the_steps_taken_by_timers() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("a")
+            .on(TestEvent::Next, "b")
+            .state("b")
+            .after(Duration::from_secs(1), "c")
+            .state("c")
+            .initial("a")
+            .build();
+        let scheduler = ManualScheduler::new();
+        let history = HistoryPlugin::new(10);
+        let profiling = ProfilingPlugin::new();
+        let service = MachineService::new(machine)
+            .scheduler(Arc::new(scheduler.clone()))
+            .plugin(history.clone())
+            .plugin(profiling.clone());
+
+        service.start();
+        service.send(TestEvent::Next);
+        scheduler.advance(Duration::from_secs(1));
+        assert!(service.state().matches("c"));
+
+        let states: Vec<String> = history
+            .states()
+            .iter()
+            .map(|state| state.value().to_string())
+            .collect();
+        assert_eq!(states, vec!["a", "b", "c"]);
+        assert_eq!(profiling.stats().events, 1);
+    }
+
+    #[test]
+    fn profiling_counts_rejected_events_and_not_the_timers_after_them() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("a")
+            .on(TestEvent::Next, "b")
+            .try_action_fn(|_: &mut TestContext, _: &TestEvent| Err(ActionError::new("disk full")))
+            .after(Duration::from_secs(1), "b")
+            .state("b")
+            .initial("a")
+            .build();
+        let scheduler = ManualScheduler::new();
+        let profiling = ProfilingPlugin::new();
+        let service = MachineService::new(machine)
+            .scheduler(Arc::new(scheduler.clone()))
+            .plugin(profiling.clone());
+
+        service.start();
+        service.send(TestEvent::Next);
+        assert!(service.state().matches("a"));
+        scheduler.advance(Duration::from_secs(1));
+        assert!(service.state().matches("b"));
+
+        assert_eq!(profiling.stats().events, 1);
+        assert_eq!(profiling.stats().transitions, 0);
+    }
+}
@@ -1,18 +1,43 @@
 use super::*;
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// State builder for fluent API
-pub struct StateBuilder<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> {
+pub struct StateBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
     pub machine_builder: MachineBuilder<C, E>,
     pub current_state: String,
     pub transitions: Vec<Transition<C, E>>,
-    pub entry_actions: Vec<Box<dyn Action<C, E>>>,
-    pub exit_actions: Vec<Box<dyn Action<C, E>>>,
+    pub entry_actions: Vec<Arc<dyn Action<C, E>>>,
+    pub exit_actions: Vec<Arc<dyn Action<C, E>>>,
     pub child_states: HashMap<String, StateNode<C, E, C>>,
     pub initial_child: Option<String>,
+    pub delayed_transitions: Vec<DelayedTransition<C>>,
+    pub always_transitions: Vec<AlwaysTransition<C>>,
+    pub is_parallel: bool,
+    pub is_final: bool,
+    pub done_target: Option<String>,
+    pub any_transitions: Vec<AnyTransition<C, E>>,
+    pub forbidden_events: Vec<MatchedEvent<E>>,
+    pub deferred_events: Vec<MatchedEvent<E>>,
+    pub invocations: Vec<Invocation<C, E>>,
+    pub tags: Vec<String>,
+    #[cfg(feature = "serde_json")]
+    pub meta: Option<serde_json::Value>,
+    pub history: Option<HistoryState>,
+    /// The state containing this one, if this state is a region or a nested child
+    pub parent_state: Option<Box<StateBuilder<C, E>>>,
 }
 
-impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBuilder<C, E> {
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > StateBuilder<C, E>
+{
     pub fn new(machine_builder: MachineBuilder<C, E>, state_id: String) -> Self {
         Self {
             machine_builder,
@@ -22,20 +47,117 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
             exit_actions: Vec::new(),
             child_states: HashMap::new(),
             initial_child: None,
+            delayed_transitions: Vec::new(),
+            always_transitions: Vec::new(),
+            is_parallel: false,
+            is_final: false,
+            done_target: None,
+            any_transitions: Vec::new(),
+            forbidden_events: Vec::new(),
+            deferred_events: Vec::new(),
+            invocations: Vec::new(),
+            tags: Vec::new(),
+            #[cfg(feature = "serde_json")]
+            meta: None,
+            history: None,
+            parent_state: None,
         }
     }
 
+    /// Transition to `target` on `event`; see `on_event` for a transition without one
     pub fn on(self, event: E, target: &str) -> TransitionBuilder<C, E> {
         TransitionBuilder::new(self, event, target.to_string())
     }
 
+    /// Handle `event` without leaving this state
+    ///
+    /// The targetless form of `on`: the transition only runs its actions, so entry
+    /// and exit actions are not re-triggered. `Machine::can` and `enabled_events`
+    /// count it as handling the event, but it leads to no state, so
+    /// `can_transition_to` and diagrams leave it out.
+    pub fn on_event(self, event: E) -> TransitionBuilder<C, E> {
+        TransitionBuilder::new(self, event, String::new()).internal()
+    }
+
+    /// Transition to `target` on any event this state has no transition for
+    ///
+    /// Specific transitions of this state and its active children take precedence;
+    /// the wildcard is tried before the event bubbles up to the parent state.
+    pub fn on_any(self, target: &str) -> AnyTransitionBuilder<C, E, Self> {
+        AnyTransitionBuilder::new(self, target.to_string())
+    }
+
+    /// Swallow `event` in this state instead of letting it bubble up to the parent
+    ///
+    /// Transitions of this state and its children still handle the event.
+    pub fn forbid(self, event: E) -> Self {
+        self.forbid_matching(event, EventMatcher::Exact)
+    }
+
+    /// Forbid every event `matcher` matches against `event`, see `forbid`
+    ///
+    /// With `EventMatcher::Variant`, forbidding `Submit(..)` swallows every `Submit`
+    /// whatever its payload, the way a transition matching variants would take it.
+    pub fn forbid_matching(mut self, event: E, matcher: EventMatcher<E>) -> Self {
+        self.forbidden_events.push(MatchedEvent { event, matcher });
+        self
+    }
+
+    /// Hold `event` while this state is active instead of dropping it
+    ///
+    /// Transitions of this state and its children still handle the event. Otherwise
+    /// it is replayed once the machine is in a state that does not defer it.
+    pub fn defer(self, event: E) -> Self {
+        self.defer_matching(event, EventMatcher::Exact)
+    }
+
+    /// Defer every event `matcher` matches against `event`, see `defer`
+    pub fn defer_matching(mut self, event: E, matcher: EventMatcher<E>) -> Self {
+        self.deferred_events.push(MatchedEvent { event, matcher });
+        self
+    }
+
+    /// Transition to `target` once this state has been active for `delay`
+    ///
+    /// The timer starts when the state is entered and is cancelled when it is exited.
+    /// Exit and entry actions get `MachineSignal::DelayElapsed` as their event, and
+    /// only run if the machine was built with `MachineBuilder::signal_event`.
+    pub fn after(self, delay: Duration, target: &str) -> DelayedTransitionBuilder<C, E> {
+        DelayedTransitionBuilder::new(self, delay, target.to_string())
+    }
+
+    /// Invoke an async service while this state is active
+    ///
+    /// The service is started with the context when the state is entered and
+    /// cancelled when it is exited. It must own whatever it needs from the context,
+    /// so clone values out before the `async move` block.
+    pub fn invoke<F, Fut, T, X>(self, service: F) -> InvokeBuilder<C, E, T, X>
+    where
+        F: Fn(&C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, X>> + MaybeSend + 'static,
+        T: 'static,
+        X: 'static,
+    {
+        InvokeBuilder::new(self, service)
+    }
+
+    /// Transition to `target` as soon as the state is entered and the guards pass
+    ///
+    /// Always transitions are re-checked after every transition until the machine is
+    /// stable, so a routing state never becomes visible to subscribers. Taken while
+    /// settling the initial state, exit and entry actions get `MachineSignal::Started`
+    /// if the machine was built with `MachineBuilder::signal_event`, and do not run otherwise.
+    pub fn always(self, target: &str) -> AlwaysTransitionBuilder<C, E> {
+        AlwaysTransitionBuilder::new(self, target.to_string())
+    }
+
     pub fn on_entry<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.entry_actions.push(Box::new(action));
+        self.entry_actions.push(Arc::new(action));
         self
     }
 
     pub fn on_exit<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.exit_actions.push(Box::new(action));
+        self.exit_actions.push(Arc::new(action));
         self
     }
 
@@ -45,7 +167,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
@@ -55,29 +177,31 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::FunctionAction::new(func)));
+            .push(Arc::new(actions::FunctionAction::new(func)));
         self
     }
 
-    /// Add a log entry action
-    pub fn on_entry_log(mut self, message: impl Into<String>) -> Self
+    /// Add a function-based entry action that can raise follow-up events or send
+    /// events to other machines
+    pub fn on_entry_with_context<F>(mut self, func: F) -> Self
     where
-        C: std::fmt::Debug,
-        E: std::fmt::Debug,
+        F: Fn(&mut C, &E, &mut ActionContext<E>) + Clone + Send + Sync + 'static,
     {
+        self.entry_actions.push(Arc::new(ContextAction::new(func)));
+        self
+    }
+
+    /// Add a log entry action
+    pub fn on_entry_log(mut self, message: impl Into<String>) -> Self {
         self.entry_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message.into())));
         self
     }
 
     /// Add a log exit action
-    pub fn on_exit_log(mut self, message: impl Into<String>) -> Self
-    where
-        C: std::fmt::Debug,
-        E: std::fmt::Debug,
-    {
+    pub fn on_exit_log(mut self, message: impl Into<String>) -> Self {
         self.exit_actions
-            .push(Box::new(actions::LogAction::new(message)));
+            .push(Arc::new(actions::LogAction::new(message.into())));
         self
     }
 
@@ -87,7 +211,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.entry_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
@@ -97,7 +221,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         F: Fn() + Clone + Send + Sync + 'static,
     {
         self.exit_actions
-            .push(Box::new(actions::PureAction::new(func)));
+            .push(Arc::new(actions::PureAction::new(func)));
         self
     }
 
@@ -112,57 +236,516 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> StateBu
         self
     }
 
-    pub fn state(mut self, id: &str) -> StateBuilder<C, E> {
-        // Finish current state
-        let state_node = StateNode {
-            id: self.current_state.clone(),
-            transitions: self.transitions,
-            entry_actions: self.entry_actions,
-            exit_actions: self.exit_actions,
-            child_states: self.child_states,
-            initial_child: self.initial_child,
-            _phantom: std::marker::PhantomData,
-        };
+    /// Make this a parallel state whose regions are all active at once
+    ///
+    /// Declare the regions with [`StateBuilder::region`]; entering the state enters
+    /// the initial child of every region.
+    pub fn parallel(mut self) -> Self {
+        self.is_parallel = true;
+        self
+    }
 
-        self.machine_builder
-            .states
-            .insert(self.current_state, state_node);
+    /// Start a region of this parallel state
+    ///
+    /// Called on a region, finishes it and starts the next region of the same
+    /// parallel state. Use [`StateBuilder::parent`] to return to the parallel state.
+    pub fn region(mut self, id: &str) -> StateBuilder<C, E> {
+        if !self.is_parallel && self.parent_state.is_some() {
+            return self.parent().region(id);
+        }
 
-        // Start new state
-        StateBuilder::new(self.machine_builder, id.to_string())
+        self.is_parallel = true;
+        // The region carries the machine builder until it is finished
+        let machine_builder = std::mem::take(&mut self.machine_builder);
+        let mut region = StateBuilder::new(machine_builder, id.to_string());
+        region.parent_state = Some(Box::new(self));
+        region
     }
 
-    pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
-        // Finish current state
-        let state_node = StateNode {
-            id: self.current_state.clone(),
-            transitions: self.transitions,
-            entry_actions: self.entry_actions,
-            exit_actions: self.exit_actions,
-            child_states: self.child_states,
-            initial_child: self.initial_child,
-            _phantom: std::marker::PhantomData,
+    /// Finish this region or nested child state and return to the state containing it
+    ///
+    /// Top-level states have no parent and are returned unchanged.
+    pub fn parent(mut self) -> StateBuilder<C, E> {
+        let Some(parent) = self.parent_state.take() else {
+            return self;
         };
 
-        let mut builder = self.machine_builder;
-        builder.states.insert(self.current_state, state_node);
-        builder.initial(state_id)
+        let (region, machine_builder) = self.into_node();
+        let mut parent = *parent;
+        parent.machine_builder = machine_builder;
+        parent.insert_child(region);
+        parent
+    }
+
+    /// Add a finished child state, recording it if the id is already taken
+    pub(crate) fn insert_child(&mut self, child: StateNode<C, E, C>) {
+        let path = format!("{}.{}", self.path(), child.id);
+        if self.child_states.insert(child.id.clone(), child).is_some() {
+            self.machine_builder.duplicate_states.push(path);
+        }
+    }
+
+    /// Get the dotted path of the state being built
+    fn path(&self) -> String {
+        match &self.parent_state {
+            Some(parent) => format!("{}.{}", parent.path(), self.current_state),
+            None => self.current_state.clone(),
+        }
+    }
+
+    /// Mark this state as a final state
+    ///
+    /// A machine whose top-level state is final is done.
+    pub fn final_state(mut self) -> Self {
+        self.is_final = true;
+        self
+    }
+
+    /// Tag this state, so `has_tag` reports it while the state (or a child) is active
+    ///
+    /// Several states can share a tag, e.g. every state that shows a spinner.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Attach metadata to this state, available from `Machine::meta`
+    #[cfg(feature = "serde_json")]
+    pub fn meta(mut self, meta: serde_json::Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Make this state a history pseudo-state of its parent
+    ///
+    /// Transitions targeting it re-enter the parent's children as they were when the
+    /// parent was last exited: only the direct child with shallow history, the
+    /// whole configuration below the parent with deep history. Declare history
+    /// states among the children of compound states.
+    pub fn history(mut self, history_type: HistoryType) -> Self {
+        self.history = Some(HistoryState::new(self.current_state.clone(), history_type));
+        self
+    }
+
+    /// Set the sibling entered through this history state before the parent has been
+    /// exited, instead of the parent's initial child
+    pub fn history_default(mut self, target: &str) -> Self {
+        let id = self.current_state.clone();
+        self.history
+            .get_or_insert_with(|| HistoryState::shallow(id))
+            .default_target = Some(target.to_string());
+        self
+    }
+
+    /// Transition to `target` once this state's children complete
+    ///
+    /// A compound state completes when it enters a final child, a parallel state
+    /// when every region has.
+    pub fn on_done(mut self, target: &str) -> Self {
+        self.done_target = Some(target.to_string());
+        self
+    }
+
+    pub fn state(self, id: &str) -> StateBuilder<C, E> {
+        // Finish current state and start a new one
+        StateBuilder::new(self.finish(), id.to_string())
+    }
+
+    pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
+        self.finish().initial(state_id)
     }
 
     pub fn build(self) -> Machine<C, E, C> {
-        // Finish current state
+        self.finish().build()
+    }
+
+    /// Finish the current state and build the machine, rejecting invalid definitions
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        self.finish().try_build()
+    }
+
+    /// Finish the current state and hand it to the machine builder
+    pub(crate) fn finish(self) -> MachineBuilder<C, E> {
+        if self.parent_state.is_some() {
+            return self.parent().finish();
+        }
+
+        let (state_node, mut builder) = self.into_node();
+        let id = state_node.id.clone();
+        if builder.states.insert(id.clone(), state_node).is_some() {
+            builder.duplicate_states.push(id);
+        }
+        builder
+    }
+
+    /// Split the builder into the finished state node and the machine builder
+    fn into_node(self) -> (StateNode<C, E, C>, MachineBuilder<C, E>) {
         let state_node = StateNode {
-            id: self.current_state.clone(),
+            id: self.current_state,
             transitions: self.transitions,
             entry_actions: self.entry_actions,
             exit_actions: self.exit_actions,
             child_states: self.child_states,
             initial_child: self.initial_child,
+            delayed_transitions: self.delayed_transitions,
+            always_transitions: self.always_transitions,
+            is_parallel: self.is_parallel,
+            is_final: self.is_final,
+            done_target: self.done_target,
+            any_transitions: self.any_transitions,
+            forbidden_events: self.forbidden_events,
+            deferred_events: self.deferred_events,
+            invocations: self.invocations,
+            tags: self.tags,
+            #[cfg(feature = "serde_json")]
+            meta: self.meta,
+            history: self.history,
             _phantom: std::marker::PhantomData,
         };
 
-        let mut builder = self.machine_builder;
-        builder.states.insert(self.current_state, state_node);
-        builder.build()
+        (state_node, self.machine_builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+        Increment,
+        Signal(MachineSignal),
+    }
+
+    impl From<MachineSignal> for TestEvent {
+        fn from(signal: MachineSignal) -> Self {
+            TestEvent::Signal(signal)
+        }
+    }
+
+    #[test]
+    fn initial_final_child_runs_actions_of_done_transition() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .signal_event(TestEvent::from)
+            .state("wizard")
+            .on_exit_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 1)
+            .initial_child("skipped")
+            .child_state("skipped")
+            .final_state()
+            .parent()
+            .on_done("complete")
+            .state("complete")
+            .on_entry_fn(|ctx: &mut TestContext, event: &TestEvent| {
+                assert_eq!(*event, TestEvent::Signal(MachineSignal::Started));
+                ctx.count += 10;
+            })
+            .initial("wizard")
+            .build();
+
+        let complete = machine.initial_state();
+        assert!(complete.matches("complete"));
+        assert_eq!(complete.context().count, 11);
+    }
+
+    #[test]
+    fn final_child_fires_on_done() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("wizard")
+            .initial_child("details")
+            .child_state("details")
+            .on(TestEvent::Increment, "confirm")
+            .parent()
+            .child_state("confirm")
+            .final_state()
+            .parent()
+            .on_done("complete")
+            .state("complete")
+            .final_state()
+            .initial("wizard")
+            .build();
+
+        let details = machine.initial_state();
+        assert!(details.matches("wizard.details"));
+        assert!(!machine.is_done(&details));
+
+        let complete = machine.transition(&details, TestEvent::Increment);
+        assert!(complete.matches("complete"));
+        assert!(machine.is_done(&complete));
+    }
+
+    #[test]
+    fn parallel_state_is_done_when_every_region_is_final() {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        enum UploadEvent {
+            Uploaded,
+            Scanned,
+        }
+
+        let machine = MachineBuilder::<TestContext, UploadEvent>::new()
+            .state("processing")
+            .parallel()
+            .on_done("complete")
+            .region("upload")
+            .initial_child("pending")
+            .child_state("pending")
+            .on(UploadEvent::Uploaded, "done")
+            .parent()
+            .child_state("done")
+            .final_state()
+            .parent()
+            .region("scan")
+            .initial_child("pending")
+            .child_state("pending")
+            .on(UploadEvent::Scanned, "done")
+            .parent()
+            .child_state("done")
+            .final_state()
+            .parent()
+            .state("complete")
+            .final_state()
+            .initial("processing")
+            .build();
+
+        let uploaded = machine.transition(&machine.initial_state(), UploadEvent::Uploaded);
+        assert!(uploaded.matches("processing.upload.done"));
+        assert!(uploaded.matches("processing.scan.pending"));
+        assert!(!machine.is_done(&uploaded));
+
+        let complete = machine.transition(&uploaded, UploadEvent::Scanned);
+        assert!(complete.matches("complete"));
+        assert!(machine.is_done(&complete));
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum EditorEvent {
+        Open,
+        Close,
+        ToggleBold,
+        ToggleItalic,
+    }
+
+    fn editor_machine() -> Machine<TestContext, EditorEvent, TestContext> {
+        MachineBuilder::<TestContext, EditorEvent>::new()
+            .state("closed")
+            .on(EditorEvent::Open, "editor")
+            .state("editor")
+            .parallel()
+            .on(EditorEvent::Close, "closed")
+            .region("bold")
+            .initial_child("off")
+            .on_entry_fn(|ctx: &mut TestContext, _: &EditorEvent| ctx.count += 1)
+            .on_exit_fn(|ctx: &mut TestContext, _: &EditorEvent| ctx.count += 10)
+            .child_state("off")
+            .on(EditorEvent::ToggleBold, "on")
+            .parent()
+            .child_state("on")
+            .on(EditorEvent::ToggleBold, "off")
+            .parent()
+            .region("italic")
+            .initial_child("off")
+            .on_entry_fn(|ctx: &mut TestContext, _: &EditorEvent| ctx.count += 1)
+            .on_exit_fn(|ctx: &mut TestContext, _: &EditorEvent| ctx.count += 10)
+            .child_state("off")
+            .on(EditorEvent::ToggleItalic, "on")
+            .parent()
+            .child_state("on")
+            .on(EditorEvent::ToggleItalic, "off")
+            .parent()
+            .initial("closed")
+            .build()
+    }
+
+    #[test]
+    fn parallel_state_enters_every_region() {
+        let machine = editor_machine();
+
+        let editor = machine.transition(&machine.initial_state(), EditorEvent::Open);
+        assert!(editor.matches("editor.bold.off"));
+        assert!(editor.matches("editor.italic.off"));
+
+        let bold = machine.transition(&editor, EditorEvent::ToggleBold);
+        assert!(bold.matches("editor.bold.on"));
+        assert!(bold.matches("editor.italic.off"));
+
+        let both = machine.transition(&bold, EditorEvent::ToggleItalic);
+        assert!(both.matches("editor.bold.on"));
+        assert!(both.matches("editor.italic.on"));
+    }
+
+    #[test]
+    fn parallel_regions_run_their_own_entry_and_exit_actions() {
+        let machine = editor_machine();
+
+        let editor = machine.transition(&machine.initial_state(), EditorEvent::Open);
+        assert_eq!(editor.context().count, 2);
+
+        let closed = machine.transition(&editor, EditorEvent::Close);
+        assert!(closed.matches("closed"));
+        assert_eq!(closed.context().count, 22);
+    }
+
+    #[test]
+    fn step_reports_nested_paths() {
+        let machine = editor_machine();
+
+        let outcome = machine.step(&machine.initial_state(), EditorEvent::Open);
+        assert_eq!(
+            outcome.entered,
+            vec![
+                "editor",
+                "editor.bold",
+                "editor.bold.off",
+                "editor.italic",
+                "editor.italic.off",
+            ]
+        );
+
+        let toggled = machine.step(&outcome.state, EditorEvent::ToggleBold);
+        assert_eq!(toggled.exited, vec!["editor.bold.off"]);
+        assert_eq!(toggled.entered, vec!["editor.bold.on"]);
+    }
+
+    #[test]
+    fn forbidden_events_do_not_bubble_to_the_parent() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("form")
+            .initial_child("editing")
+            .child_state("editing")
+            .on(TestEvent::Increment, "review")
+            .forbid(TestEvent::Stop)
+            .parent()
+            .child_state("review")
+            .parent()
+            .on(TestEvent::Stop, "closed")
+            .on_any("closed")
+            .state("closed")
+            .initial("form")
+            .build();
+
+        let editing = machine.initial_state();
+        let stopped = machine.transition(&editing, TestEvent::Stop);
+        assert!(stopped.matches("form.editing"));
+        // Only the forbidden event is held back, others still reach the wildcard
+        let started = machine.transition(&editing, TestEvent::Start);
+        assert!(started.matches("closed"));
+
+        let review = machine.transition(&editing, TestEvent::Increment);
+        let closed = machine.transition(&review, TestEvent::Stop);
+        assert!(closed.matches("closed"));
+    }
+
+    #[test]
+    fn deferred_events_are_replayed_once_accepted() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("initializing")
+            .on(TestEvent::Start, "uploading")
+            .defer(TestEvent::Stop)
+            .state("uploading")
+            .on(TestEvent::Stop, "cancelled")
+            .state("cancelled")
+            .initial("initializing")
+            .build();
+
+        let initializing = machine.initial_state();
+        let outcome = machine.step(&initializing, TestEvent::Stop);
+        assert!(outcome.state.matches("initializing"));
+        assert_eq!(outcome.deferred, vec!["initializing".to_string()]);
+        assert_eq!(outcome.state.deferred().len(), 1);
+        assert!(!machine.can(&outcome.state, &TestEvent::Stop));
+
+        // Leaving the deferring state replays the held cancellation
+        let cancelled = machine.transition(&outcome.state, TestEvent::Start);
+        assert!(cancelled.matches("cancelled"));
+        assert!(cancelled.deferred().is_empty());
+    }
+
+    #[test]
+    fn deferred_events_compare_by_value() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("initializing")
+            .defer(TestEvent::Stop)
+            .defer(TestEvent::Increment)
+            .on(TestEvent::Start, "uploading")
+            .state("uploading")
+            .initial("initializing")
+            .build();
+
+        let initializing = machine.initial_state();
+        let held = machine.transition(&initializing, TestEvent::Stop);
+        assert_eq!(held, machine.transition(&initializing, TestEvent::Stop));
+        assert_ne!(
+            held,
+            machine.transition(&initializing, TestEvent::Increment)
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct UploadContext {
+        attempts: i32,
+        error: Option<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum UploadEvent {
+        Upload,
+        Failed(String),
+    }
+
+    #[test]
+    fn forbidden_and_deferred_events_can_match_by_variant() {
+        let failed = || UploadEvent::Failed(String::new());
+        let machine = MachineBuilder::<UploadContext, UploadEvent>::new()
+            .state("idle")
+            .defer_matching(failed(), EventMatcher::Variant)
+            .on(UploadEvent::Upload, "uploading")
+            .state("uploading")
+            .initial_child("sending")
+            .child_state("sending")
+            .forbid_matching(failed(), EventMatcher::Variant)
+            .parent()
+            .on(failed(), "failed")
+            .match_variant()
+            .state("failed")
+            .initial("idle")
+            .build();
+
+        // The held failure is replayed in `uploading`, where `sending` swallows it
+        let idle = machine.initial_state();
+        let held = machine.transition(&idle, UploadEvent::Failed("timeout".to_string()));
+        assert!(held.matches("idle"));
+        let sending = machine.transition(&held, UploadEvent::Upload);
+        assert!(sending.matches("uploading.sending"));
+        assert!(sending.deferred().is_empty());
+        assert!(machine.enabled_events(&sending).is_empty());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn meta_is_looked_up_by_path() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "loading")
+            .state("loading")
+            .meta(serde_json::json!({ "description": "Loading results" }))
+            .initial_child("request")
+            .child_state("request")
+            .meta(serde_json::json!({ "retries": 3 }))
+            .parent()
+            .initial("idle")
+            .build();
+
+        assert_eq!(
+            machine.meta("loading").unwrap()["description"],
+            "Loading results"
+        );
+        assert_eq!(machine.meta("loading.request").unwrap()["retries"], 3);
+        assert!(machine.meta("idle").is_none());
     }
 }
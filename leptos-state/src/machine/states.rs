@@ -47,11 +47,10 @@ impl StateValue {
                     return true;
                 }
 
-                // Check for exact compound match (e.g., "power.on")
-                if pattern.contains('.') {
-                    let parts: Vec<&str> = pattern.split('.').collect();
-                    if parts.len() == 2 && parts[0] == parent {
-                        return child.matches(parts[1]);
+                // Check for a dotted match from this state down (e.g., "editor.bold.on")
+                if let Some((head, rest)) = pattern.split_once('.') {
+                    if head == parent {
+                        return child.matches(rest);
                     }
                 }
 
@@ -109,6 +108,71 @@ impl StateValue {
         }
     }
 
+    /// Get the dotted path of every active state, parents first
+    ///
+    /// `editor.[bold.on, italic.off]` yields `["editor", "editor.bold", "editor.bold.on",
+    /// "editor.italic", "editor.italic.off"]`, so states with the same id in different
+    /// regions stay distinct.
+    pub fn active_paths(&self) -> Vec<String> {
+        match self {
+            StateValue::Simple(name) => vec![name.clone()],
+            StateValue::Compound { parent, child } => {
+                let mut paths = vec![parent.clone()];
+                paths.extend(
+                    child
+                        .active_paths()
+                        .into_iter()
+                        .map(|path| format!("{}.{}", parent, path)),
+                );
+                paths
+            }
+            StateValue::Parallel(states) => states.iter().flat_map(|s| s.active_paths()).collect(),
+        }
+    }
+
+    /// Find the part of this value rooted at the active state with the dotted `path`
+    pub fn find(&self, path: &str) -> Option<&StateValue> {
+        match self {
+            StateValue::Simple(name) => (name == path).then_some(self),
+            StateValue::Compound { parent, child } => {
+                if parent == path {
+                    return Some(self);
+                }
+                path.strip_prefix(parent.as_str())
+                    .and_then(|rest| rest.strip_prefix('.'))
+                    .and_then(|rest| child.find(rest))
+            }
+            StateValue::Parallel(states) => states.iter().find_map(|s| s.find(path)),
+        }
+    }
+
+    /// Replace the part of this value rooted at the active state with the dotted `path`
+    pub fn replace(&self, path: &str, value: StateValue) -> StateValue {
+        match self {
+            StateValue::Simple(name) if name == path => value,
+            StateValue::Compound { parent, .. } if parent == path => value,
+            StateValue::Compound { parent, child } => {
+                match path
+                    .strip_prefix(parent.as_str())
+                    .and_then(|rest| rest.strip_prefix('.'))
+                {
+                    Some(rest) => StateValue::Compound {
+                        parent: parent.clone(),
+                        child: Box::new(child.replace(rest, value)),
+                    },
+                    None => self.clone(),
+                }
+            }
+            StateValue::Parallel(states) => StateValue::Parallel(
+                states
+                    .iter()
+                    .map(|state| state.replace(path, value.clone()))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
     /// Convert to a dot-notation string
     pub fn to_dot_notation(&self) -> String {
         match self {
@@ -258,6 +322,50 @@ mod tests {
         assert_eq!(parallel.leaf_states(), vec!["heating", "cooling.active"]);
     }
 
+    #[test]
+    fn active_paths_include_parents() {
+        let compound = StateValue::compound("power", StateValue::simple("on"));
+        assert_eq!(compound.active_paths(), vec!["power", "power.on"]);
+
+        let parallel = StateValue::parallel(vec![
+            StateValue::simple("heating"),
+            StateValue::compound("cooling", StateValue::simple("active")),
+        ]);
+        assert_eq!(
+            parallel.active_paths(),
+            vec!["heating", "cooling", "cooling.active"]
+        );
+    }
+
+    #[test]
+    fn dotted_patterns_match_across_regions() {
+        let editor = StateValue::compound(
+            "editor",
+            StateValue::parallel(vec![
+                StateValue::compound("bold", StateValue::simple("on")),
+                StateValue::compound("italic", StateValue::simple("off")),
+            ]),
+        );
+
+        assert!(editor.matches("editor.bold.on"));
+        assert!(editor.matches("editor.italic.off"));
+        assert!(editor.matches("bold.on"));
+        assert!(!editor.matches("editor.bold.off"));
+
+        let bold = editor.find("editor.bold").unwrap();
+        assert_eq!(
+            bold,
+            &StateValue::compound("bold", StateValue::simple("on"))
+        );
+
+        let replaced = editor.replace(
+            "editor.bold",
+            StateValue::compound("bold", StateValue::simple("off")),
+        );
+        assert!(replaced.matches("editor.bold.off"));
+        assert!(replaced.matches("editor.italic.off"));
+    }
+
     #[test]
     fn state_display_formatting() {
         let simple = StateValue::simple("idle");
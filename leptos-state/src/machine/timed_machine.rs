@@ -0,0 +1,899 @@
+//! Machine runtime that drives delayed (`after`) transitions and invoked services
+
+use super::*;
+use crate::machine::action_context::Delivery;
+use crate::machine::scheduler::{Scheduler, TaskId, TimerId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Callback notified with the state after every change
+type Subscriber<C> = Arc<dyn Fn(&MachineStateImpl<C>) + Send + Sync>;
+
+/// Callback notified with every step and the event that caused it, if any
+type Observer<C, E> = Arc<dyn Fn(Option<&E>, &TransitionOutcome<C>) + Send + Sync>;
+
+/// Machine wrapper that owns the current state and runs delayed transitions and
+/// invoked services
+///
+/// Timers and services of a state start when it is entered and are cancelled when
+/// it is exited. An event rejected by an action asking for a retry is sent again
+/// once its delay has passed, as the next attempt. Attempts and circuit breakers
+/// are kept per `TimedMachine`, so machines sharing a definition never see each other's.
+pub struct TimedMachine<
+    C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+    E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    inner: Arc<TimedMachineInner<C, E>>,
+}
+
+struct TimedMachineInner<
+    C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+    E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
+    machine: Machine<C, E, C>,
+    scheduler: Arc<dyn Scheduler>,
+    state: Mutex<MachineStateImpl<C>>,
+    /// Pending timers, by the path of the state that owns them
+    timers: Mutex<HashMap<String, Vec<TimerId>>>,
+    /// Running services, by the path of the state that invoked them
+    services: Mutex<HashMap<String, Vec<(u64, TaskId)>>>,
+    /// Source of the tokens telling a running service apart from a cancelled one
+    next_service: AtomicU64,
+    /// Events waiting to be sent again after a failed action, by token
+    retries: Mutex<HashMap<u64, TimerId>>,
+    /// Source of the tokens telling a pending retry apart from a cancelled one
+    next_retry: AtomicU64,
+    /// Action state of this runtime, such as its circuit breakers
+    runtime: ActionRuntime,
+    subscribers: Mutex<Vec<(SubscriptionId, Subscriber<C>)>>,
+    next_subscription: AtomicU64,
+    observer: Mutex<Option<Observer<C, E>>>,
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > TimedMachine<C, E>
+{
+    /// Start the machine in its initial state with a default context
+    pub fn new(machine: Machine<C, E, C>, scheduler: Arc<dyn Scheduler>) -> Self {
+        let initial = machine.initial_state();
+        Self::start(machine, scheduler, initial)
+    }
+
+    /// Start the machine in its initial state with the given context
+    pub fn with_context(
+        machine: Machine<C, E, C>,
+        scheduler: Arc<dyn Scheduler>,
+        context: C,
+    ) -> Self {
+        let initial = machine.initial_with_context(context);
+        Self::start(machine, scheduler, initial)
+    }
+
+    /// Start the machine in a previously saved state
+    pub fn with_state(
+        machine: Machine<C, E, C>,
+        scheduler: Arc<dyn Scheduler>,
+        state: MachineStateImpl<C>,
+    ) -> Self {
+        Self::start(machine, scheduler, state)
+    }
+
+    fn start(
+        machine: Machine<C, E, C>,
+        scheduler: Arc<dyn Scheduler>,
+        initial: MachineStateImpl<C>,
+    ) -> Self {
+        let entered = initial.value().active_paths();
+        let inner = Arc::new(TimedMachineInner {
+            machine,
+            scheduler,
+            state: Mutex::new(initial.clone()),
+            timers: Mutex::new(HashMap::new()),
+            services: Mutex::new(HashMap::new()),
+            next_service: AtomicU64::new(0),
+            retries: Mutex::new(HashMap::new()),
+            next_retry: AtomicU64::new(0),
+            runtime: ActionRuntime::new(),
+            subscribers: Mutex::new(Vec::new()),
+            next_subscription: AtomicU64::new(0),
+            observer: Mutex::new(None),
+        });
+
+        TimedMachineInner::sync_timers(&inner, &[], &entered, &initial);
+
+        Self { inner }
+    }
+
+    /// Get a snapshot of the current state
+    pub fn state(&self) -> MachineStateImpl<C> {
+        self.inner.state.lock().unwrap().clone()
+    }
+
+    /// Get the underlying machine definition
+    pub fn machine(&self) -> &Machine<C, E, C> {
+        &self.inner.machine
+    }
+
+    /// Send an event, starting and cancelling timers and services for the states
+    /// entered and exited
+    pub fn send(&self, event: E) -> MachineStateImpl<C> {
+        TimedMachineInner::send(&self.inner, event, 1)
+    }
+
+    /// Call `callback` with the state after every change, including those made by
+    /// timers and services
+    pub fn subscribe<F>(&self, callback: F) -> SubscriptionId
+    where
+        F: Fn(&MachineStateImpl<C>) + Send + Sync + 'static,
+    {
+        let id = SubscriptionId(self.inner.next_subscription.fetch_add(1, Ordering::Relaxed));
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .push((id, Arc::new(callback)));
+        id
+    }
+
+    /// Remove a subscription; unknown ids are ignored
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|(subscription, _)| *subscription != id);
+    }
+
+    /// Call `observer` with every step before the subscribers are notified
+    ///
+    /// Rejected events reach the observer as well, with an unchanged outcome; a
+    /// retried event does so on every failed attempt. Replaces the previous observer.
+    pub(crate) fn observe<F>(&self, observer: F)
+    where
+        F: Fn(Option<&E>, &TransitionOutcome<C>) + Send + Sync + 'static,
+    {
+        *self.inner.observer.lock().unwrap() = Some(Arc::new(observer));
+    }
+
+    /// Cancel every pending timer and running service
+    pub fn stop(&self) {
+        let mut timers = self.inner.timers.lock().unwrap();
+        for (_, ids) in timers.drain() {
+            for id in ids {
+                self.inner.scheduler.cancel(id);
+            }
+        }
+
+        let mut services = self.inner.services.lock().unwrap();
+        for (_, running) in services.drain() {
+            for (_, id) in running {
+                self.inner.scheduler.abort(id);
+            }
+        }
+
+        let mut retries = self.inner.retries.lock().unwrap();
+        for (_, id) in retries.drain() {
+            self.inner.scheduler.cancel(id);
+        }
+    }
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > TimedMachineInner<C, E>
+{
+    /// Deliver `event` for the `attempt`th time, counting from 1
+    fn send(inner: &Arc<Self>, event: E, attempt: u32) -> MachineStateImpl<C> {
+        let stepped = {
+            let mut state = inner.state.lock().unwrap();
+            let delivery = Delivery {
+                attempt,
+                ..inner.delivery()
+            };
+            let stepped = inner.machine.try_step_in(delivery, &state, event.clone());
+            if let Ok(outcome) = &stepped {
+                *state = outcome.state.clone();
+            }
+            stepped
+        };
+
+        let outcome = match stepped {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                match &err {
+                    MachineError::ActionFailed(ActionError {
+                        retry_after: Some(delay),
+                        ..
+                    }) => Self::retry(inner, *delay, event.clone(), attempt + 1),
+                    err => tracing::error!("Transition rejected: {}", err),
+                }
+                let state = inner.state.lock().unwrap().clone();
+                inner.reject(&event, &TransitionOutcome::rejected(&state, &err));
+                return state;
+            }
+        };
+
+        Self::sync_timers(inner, &outcome.exited, &outcome.entered, &outcome.state);
+        inner.notify(Some(&event), &outcome);
+
+        outcome.state
+    }
+
+    /// Tell the observer and the subscribers about `outcome` without holding any
+    /// lock, so they can send events
+    fn notify(&self, event: Option<&E>, outcome: &TransitionOutcome<C>) {
+        let observer = self.observer.lock().unwrap().clone();
+        if let Some(observer) = observer {
+            observer(event, outcome);
+        }
+
+        let subscribers: Vec<Subscriber<C>> = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, subscriber)| subscriber.clone())
+            .collect();
+
+        for subscriber in subscribers {
+            subscriber(&outcome.state);
+        }
+    }
+
+    /// Tell the observer that `event` was rejected, leaving the state as in `outcome`
+    ///
+    /// The subscribers are not notified, since nothing changed.
+    fn reject(&self, event: &E, outcome: &TransitionOutcome<C>) {
+        let observer = self.observer.lock().unwrap().clone();
+        if let Some(observer) = observer {
+            observer(Some(event), outcome);
+        }
+    }
+
+    /// How events, timers and service results reach the actions
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            retries: true,
+            runtime: Some(self.runtime.clone()),
+            ..Delivery::default()
+        }
+    }
+
+    /// Send `event` again as the `attempt`th attempt once `delay` has passed
+    fn retry(inner: &Arc<Self>, delay: std::time::Duration, event: E, attempt: u32) {
+        // Held while scheduling so the retry cannot run before it is recorded
+        let mut retries = inner.retries.lock().unwrap();
+        let token = inner.next_retry.fetch_add(1, Ordering::Relaxed);
+        let weak = Arc::downgrade(inner);
+        let id = inner.scheduler.schedule(
+            delay,
+            Box::new(move || {
+                // The machine was dropped or stopped before the retry was due
+                let Some(inner) = weak.upgrade() else {
+                    return;
+                };
+                if inner.retries.lock().unwrap().remove(&token).is_some() {
+                    Self::send(&inner, event, attempt);
+                }
+            }),
+        );
+        retries.insert(token, id);
+    }
+
+    /// Cancel the timers and services of exited states and start those of entered states
+    ///
+    /// States entered and left again within the same step get no timers or services.
+    fn sync_timers(
+        inner: &Arc<Self>,
+        exited: &[String],
+        entered: &[String],
+        current: &MachineStateImpl<C>,
+    ) {
+        Self::sync_services(inner, exited, entered, current);
+
+        let mut timers = inner.timers.lock().unwrap();
+
+        for path in exited {
+            for id in timers.remove(path).unwrap_or_default() {
+                inner.scheduler.cancel(id);
+            }
+        }
+
+        for path in entered {
+            if timers.contains_key(path) || current.value().find(path).is_none() {
+                continue;
+            }
+
+            let ids: Vec<TimerId> = inner
+                .machine
+                .delayed_transitions(path)
+                .iter()
+                .enumerate()
+                .map(|(index, delayed)| {
+                    let weak = Arc::downgrade(inner);
+                    let source = path.clone();
+                    inner.scheduler.schedule(
+                        delayed.delay,
+                        Box::new(move || Self::fire(weak, source, index)),
+                    )
+                })
+                .collect();
+
+            if !ids.is_empty() {
+                timers.insert(path.clone(), ids);
+            }
+        }
+    }
+
+    /// Abort the services of exited states and start the services of entered states
+    fn sync_services(
+        inner: &Arc<Self>,
+        exited: &[String],
+        entered: &[String],
+        current: &MachineStateImpl<C>,
+    ) {
+        let mut services = inner.services.lock().unwrap();
+
+        for path in exited {
+            for (_, id) in services.remove(path).unwrap_or_default() {
+                inner.scheduler.abort(id);
+            }
+        }
+
+        for path in entered {
+            if services.contains_key(path) || current.value().find(path).is_none() {
+                continue;
+            }
+
+            let running: Vec<(u64, TaskId)> = inner
+                .machine
+                .invocations(path)
+                .iter()
+                .enumerate()
+                .map(|(index, invocation)| {
+                    let token = inner.next_service.fetch_add(1, Ordering::Relaxed);
+                    let service = (invocation.service)(current.context());
+                    let weak = Arc::downgrade(inner);
+                    let source = path.clone();
+                    let id = inner.scheduler.spawn(Box::pin(async move {
+                        if let Some(result) = service.await {
+                            Self::resolve(weak, source, index, token, result);
+                        }
+                    }));
+                    (token, id)
+                })
+                .collect();
+
+            if !running.is_empty() {
+                services.insert(path.clone(), running);
+            }
+        }
+    }
+
+    fn resolve(weak: Weak<Self>, source: String, index: usize, token: u64, result: InvokeEvent<E>) {
+        // The machine was dropped before the service finished
+        let Some(inner) = weak.upgrade() else {
+            return;
+        };
+
+        let (InvokeEvent::Done(event) | InvokeEvent::Error(event)) = &result;
+        let event = event.clone();
+
+        let outcome = {
+            let mut state = inner.state.lock().unwrap();
+
+            // The service was cancelled while finishing; its state may have been re-entered since
+            let running = inner
+                .services
+                .lock()
+                .unwrap()
+                .get(&source)
+                .is_some_and(|running| running.iter().any(|(running, _)| *running == token));
+            if !running {
+                return;
+            }
+
+            let stepped =
+                inner
+                    .machine
+                    .step_invoked_in(inner.delivery(), &state, &source, index, result);
+            match stepped {
+                Ok(outcome) => {
+                    *state = outcome.state.clone();
+                    outcome
+                }
+                Err(err) => {
+                    tracing::error!("Invoked service result rejected: {}", err);
+                    return;
+                }
+            }
+        };
+
+        Self::sync_timers(&inner, &outcome.exited, &outcome.entered, &outcome.state);
+        inner.notify(Some(&event), &outcome);
+    }
+
+    fn fire(weak: Weak<Self>, source: String, index: usize) {
+        // The machine was dropped before the timer fired
+        let Some(inner) = weak.upgrade() else {
+            return;
+        };
+
+        let outcome = {
+            let mut state = inner.state.lock().unwrap();
+            let outcome = inner
+                .machine
+                .step_delayed_in(inner.delivery(), &state, &source, index);
+            *state = outcome.state.clone();
+            outcome
+        };
+
+        Self::sync_timers(&inner, &outcome.exited, &outcome.entered, &outcome.state);
+        inner.notify(None, &outcome);
+    }
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > MachineRuntime<MachineStateImpl<C>, E> for TimedMachine<C, E>
+{
+    fn state(&self) -> MachineStateImpl<C> {
+        TimedMachine::state(self)
+    }
+
+    fn send(&self, event: E) {
+        TimedMachine::send(self, event);
+    }
+
+    fn subscribe(&self, callback: Box<dyn Fn(&MachineStateImpl<C>) + Send + Sync>) {
+        TimedMachine::subscribe(self, callback);
+    }
+
+    fn stop(&self) {
+        TimedMachine::stop(self);
+    }
+}
+
+impl<
+        C: Send + Sync + Clone + std::fmt::Debug + Default + 'static,
+        E: Send + Sync + Clone + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > Drop for TimedMachine<C, E>
+{
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::scheduler::ManualScheduler;
+    use std::time::Duration;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        timeouts: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Load,
+        Loaded,
+        Signal(MachineSignal),
+    }
+
+    impl From<MachineSignal> for TestEvent {
+        fn from(signal: MachineSignal) -> Self {
+            TestEvent::Signal(signal)
+        }
+    }
+
+    fn loading_machine() -> Machine<TestContext, TestEvent, TestContext> {
+        MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Load, "loading")
+            .state("loading")
+            .on(TestEvent::Loaded, "done")
+            .after(Duration::from_secs(10), "timeout")
+            .action_fn(|ctx: &mut TestContext| ctx.timeouts += 1)
+            .state("done")
+            .state("timeout")
+            .on(TestEvent::Load, "loading")
+            .initial("idle")
+            .build()
+    }
+
+    #[test]
+    fn delayed_transition_fires_after_delay() {
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(loading_machine(), Arc::new(scheduler.clone()));
+
+        machine.send(TestEvent::Load);
+        scheduler.advance(Duration::from_secs(9));
+        assert!(machine.state().matches("loading"));
+
+        scheduler.advance(Duration::from_secs(1));
+        assert!(machine.state().matches("timeout"));
+        assert_eq!(machine.state().context().timeouts, 1);
+    }
+
+    #[test]
+    fn delayed_transition_runs_exit_and_entry_actions() {
+        let entered_with = Arc::new(Mutex::new(Vec::new()));
+        let seen = entered_with.clone();
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .signal_event(TestEvent::from)
+            .state("loading")
+            .on_exit_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.timeouts += 10)
+            .after(Duration::from_secs(10), "timeout")
+            .state("timeout")
+            .on_entry_fn(move |ctx: &mut TestContext, event: &TestEvent| {
+                ctx.timeouts += 1;
+                seen.lock().unwrap().push(event.clone());
+            })
+            .on(TestEvent::Load, "loading")
+            .initial("loading")
+            .build();
+
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(machine, Arc::new(scheduler.clone()));
+
+        // Active since start, so there is no event that entered the source
+        scheduler.advance(Duration::from_secs(10));
+        assert!(machine.state().matches("timeout"));
+        assert_eq!(machine.state().context().timeouts, 11);
+
+        // Entered by an event, which is not handed to the actions again
+        machine.send(TestEvent::Load);
+        scheduler.advance(Duration::from_secs(10));
+        assert_eq!(machine.state().context().timeouts, 22);
+
+        let elapsed = TestEvent::Signal(MachineSignal::DelayElapsed {
+            source: "loading".to_string(),
+            delay: Duration::from_secs(10),
+        });
+        assert_eq!(
+            *entered_with.lock().unwrap(),
+            vec![elapsed.clone(), elapsed]
+        );
+    }
+
+    #[test]
+    fn delayed_transition_cancelled_on_exit() {
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(loading_machine(), Arc::new(scheduler.clone()));
+
+        machine.send(TestEvent::Load);
+        machine.send(TestEvent::Loaded);
+        assert_eq!(scheduler.pending(), 0);
+
+        scheduler.advance(Duration::from_secs(20));
+        assert!(machine.state().matches("done"));
+        assert_eq!(machine.state().context().timeouts, 0);
+    }
+
+    #[test]
+    fn delayed_transition_respects_guards() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("waiting")
+            .after(Duration::from_millis(100), "expired")
+            .guard_fn(|ctx: &TestContext| ctx.timeouts > 0)
+            .state("expired")
+            .initial("waiting")
+            .build();
+
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(machine, Arc::new(scheduler.clone()));
+
+        scheduler.advance(Duration::from_secs(1));
+        assert!(machine.state().matches("waiting"));
+    }
+
+    #[test]
+    fn self_transition_restarts_timers() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("active")
+            .on(TestEvent::Load, "active")
+            .after(Duration::from_secs(10), "idle")
+            .state("idle")
+            .initial("active")
+            .build();
+
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(machine, Arc::new(scheduler.clone()));
+
+        scheduler.advance(Duration::from_secs(6));
+        machine.send(TestEvent::Load);
+        assert_eq!(scheduler.pending(), 1);
+
+        scheduler.advance(Duration::from_secs(6));
+        assert!(machine.state().matches("active"));
+
+        scheduler.advance(Duration::from_secs(4));
+        assert!(machine.state().matches("idle"));
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum SaveEvent {
+        Save,
+        Failed(String),
+    }
+
+    impl From<ActionError> for SaveEvent {
+        fn from(error: ActionError) -> Self {
+            SaveEvent::Failed(error.message)
+        }
+    }
+
+    /// Saves with an action failing `failures` times, retried at most `max_attempts` times
+    fn save_machine(
+        failures: usize,
+        max_attempts: usize,
+    ) -> Machine<TestContext, SaveEvent, TestContext> {
+        let calls = Arc::new(AtomicU64::new(0));
+        let save = FallibleAction::new(move |_: &mut TestContext, _: &SaveEvent| {
+            if calls.fetch_add(1, Ordering::SeqCst) < failures as u64 {
+                Err(ActionError::new("offline"))
+            } else {
+                Ok(())
+            }
+        });
+
+        MachineBuilder::<TestContext, SaveEvent>::new()
+            .state("idle")
+            .on(SaveEvent::Save, "saved")
+            .action(
+                RetryAction::new(Box::new(save), max_attempts)
+                    .with_delay(Duration::from_secs(1))
+                    .with_backoff(RetryBackoff::Exponential),
+            )
+            .on_error("failed")
+            .state("saved")
+            .state("failed")
+            .initial("idle")
+            .build()
+    }
+
+    #[test]
+    fn failed_action_is_retried_on_the_scheduler() {
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(save_machine(2, 3), Arc::new(scheduler.clone()));
+
+        machine.send(SaveEvent::Save);
+        assert!(machine.state().matches("idle"));
+        assert_eq!(scheduler.pending(), 1);
+
+        // The second attempt fails too and backs off for two seconds
+        scheduler.advance(Duration::from_secs(1));
+        assert!(machine.state().matches("idle"));
+        scheduler.advance(Duration::from_secs(1));
+        assert!(machine.state().matches("idle"));
+
+        scheduler.advance(Duration::from_secs(1));
+        assert!(machine.state().matches("saved"));
+        assert_eq!(scheduler.pending(), 0);
+    }
+
+    #[test]
+    fn last_failed_retry_takes_the_error_target() {
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(save_machine(5, 2), Arc::new(scheduler.clone()));
+
+        machine.send(SaveEvent::Save);
+        scheduler.advance(Duration::from_secs(1));
+        assert!(machine.state().matches("failed"));
+
+        // Stopping cancels a pending retry
+        let machine = TimedMachine::new(save_machine(5, 2), Arc::new(scheduler.clone()));
+        machine.send(SaveEvent::Save);
+        machine.stop();
+        assert_eq!(scheduler.pending(), 0);
+    }
+
+    #[test]
+    fn machines_sharing_a_definition_count_their_own_attempts() {
+        let scheduler = ManualScheduler::new();
+        let definition = save_machine(usize::MAX, 2);
+        let first = TimedMachine::new(definition.clone(), Arc::new(scheduler.clone()));
+        let second = TimedMachine::new(definition, Arc::new(scheduler.clone()));
+
+        // The second machine's first send is not the first machine's second attempt
+        first.send(SaveEvent::Save);
+        second.send(SaveEvent::Save);
+        assert!(second.state().matches("idle"));
+        assert_eq!(scheduler.pending(), 2);
+
+        scheduler.advance(Duration::from_secs(1));
+        assert!(first.state().matches("failed"));
+        assert!(second.state().matches("failed"));
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct FetchContext {
+        name: Option<String>,
+        error: Option<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum FetchEvent {
+        Fetch,
+        Cancel,
+        Fetched(String),
+        Failed(String),
+    }
+
+    struct FetchError(String);
+
+    impl From<String> for FetchEvent {
+        fn from(name: String) -> Self {
+            FetchEvent::Fetched(name)
+        }
+    }
+
+    impl From<FetchError> for FetchEvent {
+        fn from(error: FetchError) -> Self {
+            FetchEvent::Failed(error.0)
+        }
+    }
+
+    type Response = futures::channel::oneshot::Receiver<Result<String, FetchError>>;
+
+    /// Machine whose `loading` state waits for the responses handed to `respond`
+    fn fetch_machine(
+        responses: Arc<Mutex<Vec<Response>>>,
+    ) -> Machine<FetchContext, FetchEvent, FetchContext> {
+        MachineBuilder::<FetchContext, FetchEvent>::new()
+            .state("idle")
+            .on(FetchEvent::Fetch, "loading")
+            .state("loading")
+            .on(FetchEvent::Cancel, "idle")
+            .invoke(move |_: &FetchContext| {
+                let response = responses.lock().unwrap().remove(0);
+                async move {
+                    response
+                        .await
+                        .unwrap_or_else(|_| Err(FetchError("cancelled".to_string())))
+                }
+            })
+            .on_done("loaded")
+            .on_error("failed")
+            .state("loaded")
+            .on_entry_fn(|ctx: &mut FetchContext, event: &FetchEvent| {
+                if let FetchEvent::Fetched(name) = event {
+                    ctx.name = Some(name.clone());
+                }
+            })
+            .state("failed")
+            .on_entry_fn(|ctx: &mut FetchContext, event: &FetchEvent| {
+                if let FetchEvent::Failed(error) = event {
+                    ctx.error = Some(error.clone());
+                }
+            })
+            .initial("idle")
+            .build()
+    }
+
+    fn respond(
+        responses: &Arc<Mutex<Vec<Response>>>,
+    ) -> futures::channel::oneshot::Sender<Result<String, FetchError>> {
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        responses.lock().unwrap().push(receiver);
+        sender
+    }
+
+    #[test]
+    fn invoked_service_result_takes_the_done_transition() {
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(
+            fetch_machine(responses.clone()),
+            Arc::new(scheduler.clone()),
+        );
+
+        let response = respond(&responses);
+        machine.send(FetchEvent::Fetch);
+        scheduler.run_tasks();
+        assert!(machine.state().matches("loading"));
+
+        response.send(Ok("Ada".to_string())).ok();
+        scheduler.run_tasks();
+        assert!(machine.state().matches("loaded"));
+        assert_eq!(machine.state().context().name.as_deref(), Some("Ada"));
+        assert_eq!(scheduler.pending_tasks(), 0);
+    }
+
+    #[test]
+    fn invoked_service_failure_takes_the_error_transition() {
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(
+            fetch_machine(responses.clone()),
+            Arc::new(scheduler.clone()),
+        );
+
+        let response = respond(&responses);
+        machine.send(FetchEvent::Fetch);
+        response.send(Err(FetchError("not found".to_string()))).ok();
+        scheduler.run_tasks();
+
+        assert!(machine.state().matches("failed"));
+        assert_eq!(
+            machine.state().context().error.as_deref(),
+            Some("not found")
+        );
+    }
+
+    #[test]
+    fn invoked_service_cancelled_on_exit() {
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(
+            fetch_machine(responses.clone()),
+            Arc::new(scheduler.clone()),
+        );
+
+        let stale = respond(&responses);
+        machine.send(FetchEvent::Fetch);
+        machine.send(FetchEvent::Cancel);
+        assert_eq!(scheduler.pending_tasks(), 0);
+        assert!(stale.is_canceled());
+
+        // Re-entering starts a fresh service
+        let response = respond(&responses);
+        machine.send(FetchEvent::Fetch);
+        response.send(Ok("Grace".to_string())).ok();
+        scheduler.run_tasks();
+        assert_eq!(machine.state().context().name.as_deref(), Some("Grace"));
+    }
+
+    #[test]
+    fn subscribers_see_the_results_of_invoked_services() {
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(
+            fetch_machine(responses.clone()),
+            Arc::new(scheduler.clone()),
+        );
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let subscription =
+            machine.subscribe(move |state| log.lock().unwrap().push(state.value().to_string()));
+
+        let response = respond(&responses);
+        machine.send(FetchEvent::Fetch);
+        response.send(Ok("Ada".to_string())).ok();
+        scheduler.run_tasks();
+        assert_eq!(*seen.lock().unwrap(), vec!["loading", "loaded"]);
+
+        machine.unsubscribe(subscription);
+        machine.send(FetchEvent::Fetch);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(all(target_arch = "wasm32", not(feature = "ssr")))]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    fn invoked_services_need_not_be_send_in_the_browser() {
+        let machine = MachineBuilder::<FetchContext, FetchEvent>::new()
+            .state("loading")
+            .invoke(|_: &FetchContext| {
+                // Stands in for a `JsFuture`, which is not `Send` either
+                let name = std::rc::Rc::new("Ada".to_string());
+                async move { Ok::<_, FetchError>(name.to_string()) }
+            })
+            .on_done("loaded")
+            .state("loaded")
+            .initial("loading")
+            .build();
+
+        let scheduler = ManualScheduler::new();
+        let machine = TimedMachine::new(machine, Arc::new(scheduler.clone()));
+        scheduler.run_tasks();
+        assert!(machine.state().matches("loaded"));
+    }
+}
@@ -8,6 +8,48 @@ pub trait StateMachine: Sized + 'static {
 
     fn initial() -> Self::State;
     fn transition(state: &Self::State, event: Self::Event) -> Self::State;
+
+    /// Whether the machine has reached a final state
+    fn is_done(_state: &Self::State) -> bool {
+        false
+    }
+
+    /// Whether sending `event` in `state` would take a transition
+    ///
+    /// Must not run any action. Implementations backed by a
+    /// [`Machine`](super::Machine) forward to `Machine::can`, which only checks guards.
+    fn can(state: &Self::State, event: &Self::Event) -> bool;
+
+    /// Events `state` can currently take a transition for
+    ///
+    /// Implementations backed by a [`Machine`](super::Machine) forward to
+    /// `Machine::enabled_events`.
+    fn enabled_events(state: &Self::State) -> Vec<Self::Event>;
+
+    /// Start a runtime for the delayed transitions and invoked services of this machine
+    ///
+    /// `use_machine` sends events through the runtime when there is one, and calls
+    /// `transition` itself otherwise. Implementations backed by a
+    /// [`Machine`](super::Machine) should return a [`TimedMachine`](super::TimedMachine).
+    fn runtime() -> Option<Box<dyn MachineRuntime<Self::State, Self::Event>>> {
+        None
+    }
+}
+
+/// Runtime owning a machine's current state, as returned by [`StateMachine::runtime`]
+pub trait MachineRuntime<S, Ev>: Send + Sync {
+    /// Get a snapshot of the current state
+    fn state(&self) -> S;
+
+    /// Send an event
+    fn send(&self, event: Ev);
+
+    /// Call `callback` with the state after every change, including those made by
+    /// timers and services
+    fn subscribe(&self, callback: Box<dyn Fn(&S) + Send + Sync>);
+
+    /// Cancel every pending timer and running service
+    fn stop(&self);
 }
 
 /// Trait for machine states
@@ -18,4 +60,9 @@ pub trait MachineState {
     fn context(&self) -> &Self::Context;
     fn matches(&self, pattern: &str) -> bool;
     fn can_transition_to(&self, target: &str) -> bool;
+
+    /// Check if any active state is tagged with `tag`
+    fn has_tag(&self, _tag: &str) -> bool {
+        false
+    }
 }
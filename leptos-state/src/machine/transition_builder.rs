@@ -1,27 +1,41 @@
 use super::*;
+use std::sync::Arc;
 
 /// Transition builder for fluent API
-pub struct TransitionBuilder<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> {
+pub struct TransitionBuilder<
+    C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+    E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+> {
     state_builder: StateBuilder<C, E>,
     event: E,
+    matcher: EventMatcher<E>,
     target: String,
-    guards: Vec<Box<dyn Guard<C, E>>>,
-    actions: Vec<Box<dyn Action<C, E>>>,
+    internal: bool,
+    guards: Vec<Arc<dyn Guard<C, E>>>,
+    actions: Vec<Arc<dyn Action<C, E>>>,
+    on_error: Option<ErrorTransition<E>>,
 }
 
-impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> TransitionBuilder<C, E> {
+impl<
+        C: Clone + Send + Sync + std::fmt::Debug + Default + 'static,
+        E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static,
+    > TransitionBuilder<C, E>
+{
     pub fn new(state_builder: StateBuilder<C, E>, event: E, target: String) -> Self {
         Self {
             state_builder,
             event,
+            matcher: EventMatcher::Exact,
             target,
+            internal: false,
             guards: Vec::new(),
             actions: Vec::new(),
+            on_error: None,
         }
     }
 
     pub fn guard<G: Guard<C, E> + 'static>(mut self, guard: G) -> Self {
-        self.guards.push(Box::new(guard));
+        self.guards.push(Arc::new(guard));
         self
     }
 
@@ -30,7 +44,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
     where
         F: Fn(&C, &E) -> bool + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FunctionGuard::new(func)));
+        self.guards.push(Arc::new(guards::FunctionGuard::new(func)));
         self
     }
 
@@ -40,7 +54,7 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
         F: Fn(&C) -> T + Clone + Send + Sync + 'static,
         T: PartialEq + Clone + Send + Sync + 'static,
     {
-        self.guards.push(Box::new(guards::FieldEqualityGuard::new(
+        self.guards.push(Arc::new(guards::FieldEqualityGuard::new(
             field_extractor,
             expected_value,
         )));
@@ -54,67 +68,156 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
         T: PartialOrd + Send + Sync + 'static,
     {
         self.guards
-            .push(Box::new(guards::RangeGuard::new(field_extractor, min, max)));
+            .push(Arc::new(guards::RangeGuard::new(field_extractor, min, max)));
         self
     }
 
     /// Add a time limit guard
     pub fn guard_time_limit(mut self, duration: std::time::Duration) -> Self {
-        self.guards.push(Box::new(guards::TimeGuard::new(duration)));
+        self.guards.push(Arc::new(guards::TimeGuard::new(duration)));
         self
     }
 
     /// Add a counter guard
     pub fn guard_max_transitions(mut self, max_count: usize) -> Self {
         self.guards
-            .push(Box::new(guards::CounterGuard::new(max_count)));
+            .push(Arc::new(guards::CounterGuard::new(max_count)));
+        self
+    }
+
+    /// Trigger on any event of the same enum variant, whatever its payload
+    ///
+    /// `on(Submit(FormData::default()), "sending").match_variant()` takes every `Submit`.
+    pub fn match_variant(mut self) -> Self {
+        self.matcher = EventMatcher::Variant;
+        self
+    }
+
+    /// Trigger on any event reporting the same `Event::event_type()`
+    pub fn match_event_type(mut self) -> Self
+    where
+        E: Event,
+    {
+        self.matcher = EventMatcher::EventType(E::event_type);
+        self
+    }
+
+    /// Do not exit and re-enter the source state when taking this transition
+    ///
+    /// Targeting the source itself only runs the transition actions; targeting one
+    /// of its descendants only exits and enters the states below the source.
+    pub fn internal(mut self) -> Self {
+        self.internal = true;
         self
     }
 
     pub fn action<A: Action<C, E> + 'static>(mut self, action: A) -> Self {
-        self.actions.push(Box::new(action));
+        self.actions.push(Arc::new(action));
+        self
+    }
+
+    /// Add a function-based action that can fail
+    pub fn try_action_fn<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C, &E) -> Result<(), ActionError> + Clone + Send + Sync + 'static,
+    {
+        self.actions
+            .push(Arc::new(actions::FallibleAction::new(func)));
+        self
+    }
+
+    /// Go to `target` instead when an action fails while taking this transition
+    ///
+    /// Everything the failed attempt changed is rolled back, including the context.
+    /// The error target's entry actions receive the failure as an `E`.
+    pub fn on_error(mut self, target: &str) -> Self
+    where
+        E: From<ActionError>,
+    {
+        self.on_error = Some(ErrorTransition {
+            target: target.to_string(),
+            event: E::from,
+        });
+        self
+    }
+
+    /// Add a function-based action that can raise follow-up events or send events
+    /// to other machines
+    pub fn action_with_context<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&mut C, &E, &mut ActionContext<E>) + Clone + Send + Sync + 'static,
+    {
+        self.actions.push(Arc::new(ContextAction::new(func)));
         self
     }
 
     pub fn on(self, event: E, target: &str) -> TransitionBuilder<C, E> {
-        let transition = Transition {
-            event: self.event,
-            target: self.target,
-            guards: self.guards,
-            actions: self.actions,
-        };
+        TransitionBuilder::new(self.finish(), event, target.to_string())
+    }
 
-        let mut state_builder = self.state_builder;
-        state_builder.transitions.push(transition);
+    /// Finish the current transition and add a targetless transition to the current state
+    pub fn on_event(self, event: E) -> TransitionBuilder<C, E> {
+        self.finish().on_event(event)
+    }
+
+    /// Finish the current transition and add a delayed transition to the current state
+    pub fn after(self, delay: std::time::Duration, target: &str) -> DelayedTransitionBuilder<C, E> {
+        self.finish().after(delay, target)
+    }
+
+    /// Finish the current transition and add an always transition to the current state
+    pub fn always(self, target: &str) -> AlwaysTransitionBuilder<C, E> {
+        self.finish().always(target)
+    }
+
+    /// Finish the current transition and invoke a service while the current state is active
+    pub fn invoke<F, Fut, T, X>(self, service: F) -> InvokeBuilder<C, E, T, X>
+    where
+        F: Fn(&C) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<T, X>> + MaybeSend + 'static,
+        T: 'static,
+        X: 'static,
+    {
+        self.finish().invoke(service)
+    }
+
+    /// Finish the current transition and add a wildcard transition to the current state
+    pub fn on_any(self, target: &str) -> AnyTransitionBuilder<C, E, StateBuilder<C, E>> {
+        self.finish().on_any(target)
+    }
+
+    /// Finish the current transition and forbid `event` from bubbling up from the current state
+    pub fn forbid(self, event: E) -> StateBuilder<C, E> {
+        self.finish().forbid(event)
+    }
+
+    /// Finish the current transition and defer `event` in the current state
+    pub fn defer(self, event: E) -> StateBuilder<C, E> {
+        self.finish().defer(event)
+    }
 
-        TransitionBuilder::new(state_builder, event, target.to_string())
+    /// Finish the current transition and defer every event `matcher` matches
+    pub fn defer_matching(self, event: E, matcher: EventMatcher<E>) -> StateBuilder<C, E> {
+        self.finish().defer_matching(event, matcher)
     }
 
     pub fn state(self, id: &str) -> StateBuilder<C, E> {
-        let transition = Transition {
-            event: self.event,
-            target: self.target,
-            guards: self.guards,
-            actions: self.actions,
-        };
+        self.finish().state(id)
+    }
 
-        let mut state_builder = self.state_builder;
-        state_builder.transitions.push(transition);
-        state_builder.state(id)
+    /// Finish the current transition and start the next region of the parallel state
+    pub fn region(self, id: &str) -> StateBuilder<C, E> {
+        self.finish().region(id)
+    }
+
+    /// Finish the current transition and return to the parallel state of this region
+    pub fn parent(self) -> StateBuilder<C, E> {
+        self.finish().parent()
     }
 
     /// Finish the current transition and set the initial state on the underlying builder
     pub fn initial(self, state_id: &str) -> MachineBuilder<C, E> {
-        let transition = Transition {
-            event: self.event,
-            target: self.target,
-            guards: self.guards,
-            actions: self.actions,
-        };
-
-        let mut state_builder = self.state_builder;
-        state_builder.transitions.push(transition);
-        state_builder.initial(state_id)
+        self.finish().initial(state_id)
     }
 
     /// Finish the current transition and add an exit function to the current state
@@ -122,28 +225,272 @@ impl<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> Transit
     where
         F: Fn(&mut C, &E) + Clone + Send + Sync + 'static,
     {
+        self.finish().on_exit_fn(func)
+    }
+
+    pub fn build(self) -> Machine<C, E, C> {
+        self.finish().build()
+    }
+
+    /// Finish the current state and build the machine, rejecting invalid definitions
+    pub fn try_build(self) -> MachineBuildResult<C, E> {
+        self.finish().try_build()
+    }
+
+    /// Finish the current transition and add it to the state
+    pub(crate) fn finish(self) -> StateBuilder<C, E> {
         let transition = Transition {
             event: self.event,
+            matcher: self.matcher,
             target: self.target,
+            internal: self.internal,
             guards: self.guards,
             actions: self.actions,
+            on_error: self.on_error,
         };
 
         let mut state_builder = self.state_builder;
         state_builder.transitions.push(transition);
-        state_builder.on_exit_fn(func)
+        state_builder
     }
+}
 
-    pub fn build(self) -> Machine<C, E, C> {
-        let transition = Transition {
-            event: self.event,
-            target: self.target,
-            guards: self.guards,
-            actions: self.actions,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Increment,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum FormEvent {
+        Edit(String),
+        Submit(String),
+    }
+
+    impl crate::machine::events::Event for FormEvent {
+        fn event_type(&self) -> &str {
+            match self {
+                FormEvent::Edit(_) => "edit",
+                FormEvent::Submit(_) => "submit",
+            }
+        }
+    }
+
+    #[test]
+    fn variant_transitions_ignore_payload() {
+        let machine = MachineBuilder::<TestContext, FormEvent>::new()
+            .state("editing")
+            .on(FormEvent::Submit(String::new()), "sent")
+            .match_variant()
+            .action(FunctionAction::new(
+                |ctx: &mut TestContext, event: &FormEvent| {
+                    if let FormEvent::Submit(message) = event {
+                        ctx.count = message.len() as i32;
+                    }
+                },
+            ))
+            .on(FormEvent::Edit("reset".to_string()), "reset")
+            .state("sent")
+            .state("reset")
+            .initial("editing")
+            .build();
+
+        let editing = machine.initial_state();
+
+        // Exact transitions still compare the payload
+        let edited = machine.transition(&editing, FormEvent::Edit("other".to_string()));
+        assert!(edited.matches("editing"));
+
+        let sent = machine.transition(&editing, FormEvent::Submit("hello".to_string()));
+        assert!(sent.matches("sent"));
+        assert_eq!(sent.context().count, 5);
+    }
+
+    #[test]
+    fn event_type_transitions_ignore_payload() {
+        let machine = MachineBuilder::<TestContext, FormEvent>::new()
+            .state("editing")
+            .on(FormEvent::Submit(String::new()), "sent")
+            .match_event_type()
+            .state("sent")
+            .initial("editing")
+            .build();
+
+        let sent = machine.transition(
+            &machine.initial_state(),
+            FormEvent::Submit("hello".to_string()),
+        );
+        assert!(sent.matches("sent"));
+    }
+
+    #[test]
+    fn targetless_transitions_update_context_in_place() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("counting")
+            .on_entry_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 100)
+            .on_event(TestEvent::Increment)
+            .action(FunctionAction::new(
+                |ctx: &mut TestContext, _: &TestEvent| ctx.count += 1,
+            ))
+            .on(TestEvent::Start, "counting")
+            .initial("counting")
+            .build();
+
+        let incremented = machine.step(&machine.initial_state(), TestEvent::Increment);
+        assert!(incremented.changed);
+        assert_eq!(incremented.state.context().count, 1);
+        assert!(incremented.exited.is_empty());
+        assert!(incremented.entered.is_empty());
+
+        // An external self-transition re-runs the entry actions
+        let restarted = machine.step(&incremented.state, TestEvent::Start);
+        assert_eq!(restarted.state.context().count, 101);
+        assert_eq!(restarted.exited, vec!["counting"]);
+    }
+
+    #[test]
+    fn targetless_transitions_handle_events_without_a_target_state() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("editing")
+            .on_event(TestEvent::Increment)
+            .initial("editing")
+            .build();
+
+        let editing = machine.initial_state();
+        assert!(machine.states["editing"].transitions[0].is_targetless());
+        assert!(machine.can(&editing, &TestEvent::Increment));
+        assert_eq!(machine.enabled_events(&editing), vec![TestEvent::Increment]);
+        assert!(!machine.can_transition_to(&editing, "editing"));
+        assert!(machine.definition_errors().is_empty());
+    }
+
+    #[test]
+    fn internal_transitions_do_not_exit_their_source() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("wizard")
+            .initial_child("details")
+            .on_exit_fn(|ctx: &mut TestContext, _: &TestEvent| ctx.count += 10)
+            .child_state("details")
+            .parent()
+            .child_state("confirm")
+            .parent()
+            .on(TestEvent::Start, "confirm")
+            .internal()
+            .initial("wizard")
+            .build();
+
+        let outcome = machine.step(&machine.initial_state(), TestEvent::Start);
+        assert!(outcome.state.matches("wizard.confirm"));
+        assert_eq!(outcome.state.context().count, 0);
+        assert_eq!(outcome.exited, vec!["wizard.details"]);
+        assert_eq!(outcome.entered, vec!["wizard.confirm"]);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct UploadContext {
+        attempts: i32,
+        error: Option<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum UploadEvent {
+        Upload,
+        Failed(String),
+    }
+
+    impl From<ActionError> for UploadEvent {
+        fn from(error: ActionError) -> Self {
+            UploadEvent::Failed(error.message)
+        }
+    }
+
+    fn upload_machine(on_error: bool) -> Machine<UploadContext, UploadEvent, UploadContext> {
+        let upload = MachineBuilder::<UploadContext, UploadEvent>::new()
+            .state("idle")
+            .on(UploadEvent::Upload, "done")
+            .action(FunctionAction::new(
+                |ctx: &mut UploadContext, _: &UploadEvent| ctx.attempts += 1,
+            ))
+            .try_action_fn(|_: &mut UploadContext, _: &UploadEvent| {
+                Err(ActionError::new("disk full"))
+            });
+
+        let upload = if on_error {
+            upload.on_error("failed")
+        } else {
+            upload
         };
 
-        let mut state_builder = self.state_builder;
-        state_builder.transitions.push(transition);
-        state_builder.build()
+        upload
+            .state("done")
+            .state("failed")
+            .on_entry_fn(|ctx: &mut UploadContext, event: &UploadEvent| {
+                if let UploadEvent::Failed(message) = event {
+                    ctx.error = Some(message.clone());
+                }
+            })
+            .initial("idle")
+            .build()
+    }
+
+    #[test]
+    fn failed_actions_roll_back_and_enter_the_error_target() {
+        let machine = upload_machine(true);
+
+        let outcome = machine.step(&machine.initial_state(), UploadEvent::Upload);
+        assert!(outcome.state.matches("failed"));
+        assert_eq!(outcome.state.context().attempts, 0);
+        assert_eq!(outcome.state.context().error.as_deref(), Some("disk full"));
+        assert_eq!(outcome.exited, vec!["idle"]);
+        assert_eq!(outcome.entered, vec!["failed"]);
+        assert_eq!(outcome.actions, vec!["function"]);
+        assert_eq!(outcome.errors.len(), 1);
+        assert_eq!(outcome.errors[0].action, "fallible");
+    }
+
+    #[test]
+    fn failed_actions_without_an_error_target_reject_the_event() {
+        let machine = upload_machine(false);
+        let idle = machine.initial_state();
+
+        let Err(MachineError::ActionFailed(error)) =
+            machine.try_transition(&idle, UploadEvent::Upload)
+        else {
+            panic!("expected the action failure to reject the event");
+        };
+        assert_eq!(error.to_string(), "Action fallible failed: disk full");
+        assert_eq!(machine.transition(&idle, UploadEvent::Upload), idle);
+    }
+
+    #[test]
+    fn retried_actions_fail_at_once_without_a_retrying_runtime() {
+        let machine = MachineBuilder::<UploadContext, UploadEvent>::new()
+            .state("idle")
+            .on(UploadEvent::Upload, "done")
+            .action(RetryAction::new(
+                Box::new(FallibleAction::new(
+                    |_: &mut UploadContext, _: &UploadEvent| Err(ActionError::new("disk full")),
+                )),
+                3,
+            ))
+            .on_error("failed")
+            .state("done")
+            .state("failed")
+            .initial("idle")
+            .build();
+
+        let outcome = machine.step(&machine.initial_state(), UploadEvent::Upload);
+        assert!(outcome.state.matches("failed"));
+        assert_eq!(outcome.errors.len(), 1);
+        assert_eq!(outcome.errors[0].retry_after, None);
     }
 }
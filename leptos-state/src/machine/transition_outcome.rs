@@ -0,0 +1,148 @@
+//! Record of everything a single machine step did
+
+use super::*;
+use crate::machine::action_context::Delivery;
+use crate::machine::states::StateValue;
+
+/// A guard checked while looking for a transition
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionGuardEvaluation {
+    /// Dotted path of the state declaring the guarded transition
+    pub state: String,
+    /// Name of the guard
+    pub guard: String,
+    /// Whether the guard passed
+    pub passed: bool,
+}
+
+/// Everything a call to `Machine::step` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionOutcome<C: Send + Sync> {
+    /// Whether a transition was taken (self-transitions included)
+    pub changed: bool,
+    /// State value before the event
+    pub source: StateValue,
+    /// State value once the event and any eventless transitions were processed
+    pub target: StateValue,
+    /// The resulting state
+    pub state: MachineStateImpl<C>,
+    /// Dotted paths of the states exited, in the order their exit actions ran
+    pub exited: Vec<String>,
+    /// Dotted paths of the states entered, in the order their entry actions ran
+    pub entered: Vec<String>,
+    /// Names of the actions executed, in order
+    pub actions: Vec<String>,
+    /// Guards evaluated, in order
+    pub guards: Vec<TransitionGuardEvaluation>,
+    /// Events raised by actions, in the order they were raised and processed
+    pub raised: Vec<AnyEvent>,
+    /// Dotted paths of the states that held an event because they defer it
+    pub deferred: Vec<String>,
+    /// Events actions sent to other machines, left for the runtime to deliver
+    pub sent: Vec<SentEvent>,
+    /// Child actors spawned by actions, left for the actor system to start
+    pub spawned: Vec<SpawnedActor>,
+    /// Action failures that sent a transition to its error target instead
+    pub errors: Vec<ActionError>,
+    /// Name of the invariant that rejected the step, leaving the state unchanged
+    pub violated_invariant: Option<String>,
+    /// How the runtime delivered the event, handed to every action of the step
+    pub(crate) delivery: Delivery,
+    /// State each transition led to, with its event (`None` if eventless), kept
+    /// for the middleware while the machine has some
+    pub(crate) microsteps: Vec<(Option<AnyEvent>, MachineStateImpl<C>)>,
+}
+
+impl<C: Clone + Send + Sync> TransitionOutcome<C> {
+    /// Outcome of a step that did nothing
+    pub fn new(state: &MachineStateImpl<C>) -> Self {
+        Self {
+            changed: false,
+            source: state.value.clone(),
+            target: state.value.clone(),
+            state: state.clone(),
+            exited: Vec::new(),
+            entered: Vec::new(),
+            actions: Vec::new(),
+            guards: Vec::new(),
+            raised: Vec::new(),
+            deferred: Vec::new(),
+            sent: Vec::new(),
+            spawned: Vec::new(),
+            errors: Vec::new(),
+            violated_invariant: None,
+            delivery: Delivery::default(),
+            microsteps: Vec::new(),
+        }
+    }
+
+    /// Outcome of a step rejected with `err`, which left `state` unchanged
+    pub(crate) fn rejected(state: &MachineStateImpl<C>, err: &MachineError) -> Self {
+        let mut outcome = Self::new(state);
+        if let MachineError::InvariantViolated(name) = err {
+            outcome.violated_invariant = Some(name.clone());
+        }
+        outcome
+    }
+
+    /// Record the state the step ended in
+    pub(crate) fn finish(mut self, state: MachineStateImpl<C>) -> Self {
+        self.target = state.value.clone();
+        self.state = state;
+        self
+    }
+
+    /// Guards that rejected a transition
+    pub fn rejected_guards(&self) -> impl Iterator<Item = &TransitionGuardEvaluation> {
+        self.guards.iter().filter(|evaluation| !evaluation.passed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::guards::FunctionGuard;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    struct TestContext {
+        count: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestEvent {
+        Start,
+        Stop,
+    }
+
+    #[test]
+    fn step_reports_guards_actions_and_states() {
+        let machine = MachineBuilder::<TestContext, TestEvent>::new()
+            .state("idle")
+            .on(TestEvent::Start, "running")
+            .guard(FunctionGuard::new(|ctx: &TestContext, _| ctx.count > 0))
+            .on(TestEvent::Start, "waiting")
+            .state("running")
+            .state("waiting")
+            .on_entry(FunctionAction::new(
+                |ctx: &mut TestContext, _: &TestEvent| ctx.count += 1,
+            ))
+            .initial("idle")
+            .build();
+
+        let outcome = machine.step(&machine.initial_state(), TestEvent::Start);
+        assert!(outcome.changed);
+        assert_eq!(outcome.source, StateValue::simple("idle"));
+        assert_eq!(outcome.target, StateValue::simple("waiting"));
+        assert_eq!(outcome.exited, vec!["idle"]);
+        assert_eq!(outcome.entered, vec!["waiting"]);
+        assert_eq!(outcome.actions.len(), 1);
+        assert_eq!(outcome.guards.len(), 1);
+        assert_eq!(outcome.rejected_guards().count(), 1);
+        assert_eq!(outcome.state.context().count, 1);
+
+        let unchanged = machine.step(&outcome.state, TestEvent::Stop);
+        assert!(!unchanged.changed);
+        assert_eq!(unchanged.target, unchanged.source);
+        assert!(unchanged.exited.is_empty());
+    }
+}
@@ -25,6 +25,8 @@ pub struct VisualizationConfig {
     pub show_timing: bool,
     /// Maximum depth for hierarchical display
     pub max_depth: Option<usize>,
+    /// Whether to include the metadata declared on states
+    pub include_metadata: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -52,6 +54,7 @@ impl Default for VisualizationConfig {
             font_size: 12.0,
             show_timing: false,
             max_depth: None,
+            include_metadata: true,
         }
     }
 }
@@ -121,6 +124,12 @@ impl VisualizationConfig {
         self.max_depth = depth;
         self
     }
+
+    /// Include the metadata declared on states
+    pub fn with_metadata(mut self, include: bool) -> Self {
+        self.include_metadata = include;
+        self
+    }
 }
 
 /// Export formats for state diagrams
@@ -214,7 +214,8 @@ impl<C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Se
         // Add transitions
         for state_name in machine.get_states() {
             if let Some(state_node) = machine.states_map().get(&state_name) {
-                for transition in &state_node.transitions {
+                // Targetless transitions stay in their state and are not drawn
+                for transition in state_node.transitions.iter().filter(|t| !t.is_targetless()) {
                     let target = &transition.target;
                     let label = if self.config.show_guards && !transition.guards.is_empty() {
                         format!("guards: {}", transition.guards.len())
@@ -255,7 +256,7 @@ impl<C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Se
         // Add transitions
         for state_name in machine.get_states() {
             if let Some(state_node) = machine.states_map().get(&state_name) {
-                for transition in &state_node.transitions {
+                for transition in state_node.transitions.iter().filter(|t| !t.is_targetless()) {
                     let target = &transition.target;
                     let label = if self.config.show_guards && !transition.guards.is_empty() {
                         format!(" : guards({})", transition.guards.len())
@@ -302,7 +303,7 @@ impl<C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Se
         // Add transitions
         for state_name in machine.get_states() {
             if let Some(state_node) = machine.states_map().get(&state_name) {
-                for transition in &state_node.transitions {
+                for transition in state_node.transitions.iter().filter(|t| !t.is_targetless()) {
                     let target = &transition.target;
                     let label = if self.config.show_guards && !transition.guards.is_empty() {
                         format!(" : [guards: {}]", transition.guards.len())
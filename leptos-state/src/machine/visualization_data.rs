@@ -84,7 +84,7 @@ impl<'a, C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone
         // Check that all transition states exist
         self.transitions.iter().all(|t| {
             self.states.iter().any(|s| s.name == t.from_state) &&
-            self.states.iter().any(|s| s.name == t.to_state)
+            (t.to_state.is_empty() || self.states.iter().any(|s| s.name == t.to_state))
         })
     }
 }
@@ -106,6 +106,8 @@ pub struct StateInfo<'a, C: Send + Sync, E> {
     pub is_initial: bool,
     /// State metadata
     pub metadata: std::collections::HashMap<String, String>,
+    /// Tags declared on the state
+    pub tags: Vec<String>,
 }
 
 impl<'a, C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static> StateInfo<'a, C, E> {
@@ -137,14 +139,43 @@ impl<'a, C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone
 
         let child_states = state_node.child_states.keys().map(|s| s.clone()).collect();
 
+        // Top-level metadata entries, with strings unquoted
+        #[cfg(feature = "serde_json")]
+        let metadata = match &state_node.meta {
+            Some(serde_json::Value::Object(entries)) if config.include_metadata => entries
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+            _ => std::collections::HashMap::new(),
+        };
+        #[cfg(not(feature = "serde_json"))]
+        let metadata = std::collections::HashMap::new();
+
+        #[cfg(feature = "serde_json")]
+        let description = state_node
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.get("description"))
+            .and_then(|description| description.as_str())
+            .map(str::to_string);
+        #[cfg(not(feature = "serde_json"))]
+        let description = None;
+
         Self {
             name: state_name.to_string(),
-            description: None, // Could be extracted from attributes
+            description,
             entry_actions,
             exit_actions,
             child_states,
             is_initial: false, // Set by caller
-            metadata: std::collections::HashMap::new(),
+            metadata,
+            tags: state_node.tags.clone(),
         }
     }
 
@@ -173,7 +204,7 @@ impl<'a, C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone
 pub struct TransitionInfo<'a, C: Send + Sync, E> {
     /// Source state
     pub from_state: String,
-    /// Target state
+    /// Target state, empty for targetless transitions
     pub to_state: String,
     /// Event that triggers the transition
     pub event: Option<String>,
@@ -196,7 +227,7 @@ impl<'a, C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone
             transition
                 .guards
                 .iter()
-                .map(|guard| guard.description())
+                .map(|guard| guard.name().to_string())
                 .collect()
         } else {
             Vec::new()
@@ -380,7 +411,7 @@ impl<'a, C: Send + Sync, E> serde::Serialize for StateInfo<'a, C, E> {
     {
         use serde::ser::SerializeStruct;
 
-        let mut state = serializer.serialize_struct("StateInfo", 7)?;
+        let mut state = serializer.serialize_struct("StateInfo", 8)?;
         state.serialize_field("name", &self.name)?;
         state.serialize_field("description", &self.description)?;
         state.serialize_field("entry_actions", &self.entry_actions)?;
@@ -388,6 +419,7 @@ impl<'a, C: Send + Sync, E> serde::Serialize for StateInfo<'a, C, E> {
         state.serialize_field("child_states", &self.child_states)?;
         state.serialize_field("is_initial", &self.is_initial)?;
         state.serialize_field("metadata", &self.metadata)?;
+        state.serialize_field("tags", &self.tags)?;
         state.end()
     }
 }
@@ -45,16 +45,14 @@ impl<C: Clone + Send + Sync + std::fmt::Debug + 'static, E: Clone + Send + Sync
 }
 
 /// A state machine with visualization capabilities
-#[derive(Debug)]
+///
+/// Runs the machine on a [`MachineService`] with a [`VisualizationPlugin`]
+/// attached, so the recorded transitions are the ones the service processed.
 pub struct VisualizedMachine<C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static> {
-    /// The underlying machine
-    pub machine: Machine<C, E, C>,
-    /// The current state
-    pub current_state: MachineStateImpl<C>,
-    /// The visualizer
-    pub visualizer: MachineVisualizer<C, E>,
-    /// The monitor
-    pub monitor: StateMonitor<C, E>,
+    /// The service running the machine
+    service: MachineService<C, E>,
+    /// The plugin recording the transitions
+    plugin: VisualizationPlugin<C, E>,
     /// Time travel debugger
     pub debugger: TimeTravelDebugger<C, E>,
 }
@@ -62,113 +60,89 @@ pub struct VisualizedMachine<C: Clone + Send + Sync + std::fmt::Debug + Default
 impl<C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static> VisualizedMachine<C, E> {
     /// Create a new visualized machine
     pub fn new(machine: Machine<C, E, C>) -> Self {
-        let current_state = machine.initial_state();
-        let visualizer = machine.visualizer();
-        let monitor = machine.monitor();
-        let debugger = TimeTravelDebugger::new();
-
-        Self {
-            machine,
-            current_state,
-            visualizer,
-            monitor,
-            debugger,
-        }
+        let plugin = VisualizationPlugin::new(&machine);
+        Self::with_plugin(machine, plugin)
     }
 
     /// Create with custom configuration
     pub fn with_config(machine: Machine<C, E, C>, config: VisualizationConfig) -> Self {
-        let current_state = machine.initial_state();
-        let visualizer = machine.visualizer_with_config(config);
-        let monitor = machine.monitor();
-        let debugger = TimeTravelDebugger::new();
+        let plugin = VisualizationPlugin::with_config(&machine, config);
+        Self::with_plugin(machine, plugin)
+    }
+
+    fn with_plugin(machine: Machine<C, E, C>, plugin: VisualizationPlugin<C, E>) -> Self {
+        let service = MachineService::new(machine).plugin(plugin.clone());
+        service.start();
 
         Self {
-            machine,
-            current_state,
-            visualizer,
-            monitor,
-            debugger,
+            service,
+            plugin,
+            debugger: TimeTravelDebugger::new(),
         }
     }
 
     /// Transition with full visualization tracking
     pub fn transition(&mut self, event: &E) -> Result<(), String> {
-        let start_time = std::time::Instant::now();
-        let from_state = self.current_state.clone();
-
-        // Create transition event
-        let transition_event = TransitionEvent::success(
-            from_state.clone(),
-            String::new(), // Will be filled after transition
-            Some(event.clone()),
-            None, // Context not easily accessible
-        );
-
-        // Perform the transition
-        let new_state = self.machine.transition(&self.current_state, event.clone());
-        self.current_state = new_state.clone();
-
-        // Complete the transition event
-        let mut completed_event = transition_event;
-        completed_event.to_state = new_state.value().to_string();
-        completed_event.success = true;
-
-        // Record the event
-        self.visualizer.record_transition(completed_event.clone())?;
-
-        // Notify monitor
-        let state_change = StateChangeEvent::new(
-            from_state.value().to_string(),
-            new_state.value().to_string(),
-            StateChangeType::Transition,
-        );
-        self.monitor.notify_state_change(&state_change);
-
-        // Record performance
-        let duration = start_time.elapsed();
-        let perf_event = PerformanceEvent::new(PerformanceEventType::Transition, duration);
-        self.visualizer.record_performance(perf_event)?;
-
+        self.service.send(event.clone());
         Ok(())
     }
 
+    /// Get the service running the machine
+    pub fn service(&self) -> &MachineService<C, E> {
+        &self.service
+    }
+
+    /// Get the plugin recording the transitions
+    pub fn plugin(&self) -> &VisualizationPlugin<C, E> {
+        &self.plugin
+    }
+
     /// Get current state
-    pub fn current_state(&self) -> &MachineStateImpl<C> {
-        &self.current_state
+    pub fn current_state(&self) -> MachineStateImpl<C> {
+        self.service.state()
     }
 
     /// Get current context
-    pub fn current_context(&self) -> &C {
-        self.current_state.context()
+    pub fn current_context(&self) -> C {
+        self.service.state().context().clone()
     }
 
     /// Export current diagram
     pub fn export_diagram(&self, format: ExportFormat) -> Result<String, String> {
-        self.visualizer.export_diagram(format)
+        self.plugin.visualizer().export_diagram(format)
     }
 
     /// Get visualization statistics
     pub fn get_stats(&self) -> VisualizationStats {
         let mut stats = VisualizationStats::default();
-        stats.update(&self.visualizer);
+        stats.update(&self.plugin.visualizer());
         stats
     }
 
     /// Enable or disable visualization
     pub fn set_visualization_enabled(&mut self, enabled: bool) {
-        self.visualizer.set_enabled(enabled);
-        self.monitor.set_enabled(enabled);
+        self.plugin.visualizer().set_enabled(enabled);
+        self.plugin.monitor().set_enabled(enabled);
     }
 
     /// Clear all recorded data
     pub fn clear_data(&mut self) {
-        self.visualizer.clear();
-        self.monitor.reset_stats();
+        self.plugin.visualizer().clear();
+        self.plugin.monitor().reset_stats();
         self.debugger.clear();
     }
 }
 
+impl<C: Clone + Send + Sync + std::fmt::Debug + Default + 'static, E: Clone + Send + Sync + std::fmt::Debug + PartialEq + Eq + std::hash::Hash + 'static> std::fmt::Debug for VisualizedMachine<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VisualizedMachine")
+            .field("state", &self.service.state())
+            .field("visualizer", &*self.plugin.visualizer())
+            .field("debugger", &self.debugger)
+            .finish_non_exhaustive()
+    }
+}
+
 /// Automatic visualization integration
 pub struct AutoVisualizer<C: Clone + Send + Sync + 'static, E: Clone + Send + Sync + 'static> {
     /// The visualized machine
@@ -239,7 +213,10 @@ impl<C: Clone + Send + Sync + std::fmt::Debug + 'static, E: Clone + Send + Sync
     /// Perform health checks
     pub fn perform_health_checks(&mut self) -> Option<Vec<HealthCheckResult>> {
         if let Some(ref mut checker) = self.health_checker {
-            Some(checker.perform_checks(&self.machine.machine, &self.machine.monitor))
+            Some(checker.perform_checks(
+                self.machine.service().machine(),
+                &self.machine.plugin().monitor(),
+            ))
         } else {
             None
         }
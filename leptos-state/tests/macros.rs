@@ -0,0 +1,107 @@
+//! Runtime behaviour of the code generated by `#[state_machine]` and
+//! `#[derive(MachineEvent)]`, used through the `leptos_state` re-exports
+
+#![cfg(feature = "macros")]
+
+use leptos_state::machine::{Event, MachineState, StateMachine};
+use leptos_state::{state_machine, MachineEvent};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, MachineEvent)]
+enum LightEvent {
+    Timer,
+    PowerOff,
+    Report { cycles: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct LightContext {
+    cycles: u32,
+    stops: u32,
+    powered: bool,
+}
+
+fn count_cycle(ctx: &mut LightContext, _: &LightEvent) {
+    ctx.cycles += 1;
+}
+
+fn stop_cars(ctx: &mut LightContext, _: &LightEvent) {
+    ctx.stops += 1;
+}
+
+fn is_powered(ctx: &LightContext, _: &LightEvent) -> bool {
+    ctx.powered
+}
+
+#[state_machine(context = LightContext, event = LightEvent)]
+enum Light {
+    #[on(LightEvent::Timer => Yellow)]
+    Green,
+    #[on(LightEvent::Timer => Red, action = count_cycle)]
+    Yellow,
+    #[initial]
+    #[entry(stop_cars)]
+    #[on(LightEvent::Timer => Green, guard = is_powered)]
+    #[on(LightEvent::PowerOff => Off)]
+    Red,
+    #[final_state]
+    Off,
+}
+
+#[test]
+fn derived_events_report_their_variant() {
+    assert_eq!(LightEvent::Timer.event_type(), "Timer");
+    assert_eq!(LightEvent::Report { cycles: 3 }.event_type(), "Report");
+}
+
+#[test]
+fn states_convert_to_and_from_ids() {
+    assert_eq!(
+        Light::ALL,
+        [Light::Green, Light::Yellow, Light::Red, Light::Off]
+    );
+    for light in Light::ALL {
+        assert_eq!(Light::from_id(light.id()), Some(light));
+        assert_eq!(light.to_string(), light.id());
+    }
+    assert_eq!(Light::from_id("Blue"), None);
+}
+
+#[test]
+fn generated_machine_runs_the_declared_transitions() {
+    let red = LightMachine::initial();
+    assert_eq!(Light::of(&red), Some(Light::Red));
+
+    // The guard keeps the light red until it is powered
+    let still_red = LightMachine::transition(&red, LightEvent::Timer);
+    assert_eq!(Light::of(&still_red), Some(Light::Red));
+
+    let powered = Light::machine().initial_with_context(LightContext {
+        powered: true,
+        ..LightContext::default()
+    });
+    let green = LightMachine::transition(&powered, LightEvent::Timer);
+    let yellow = LightMachine::transition(&green, LightEvent::Timer);
+    let red = LightMachine::transition(&yellow, LightEvent::Timer);
+    assert_eq!(Light::of(&red), Some(Light::Red));
+    assert_eq!(red.context().cycles, 1);
+    assert_eq!(red.context().stops, 1);
+
+    let off = LightMachine::transition(&red, LightEvent::PowerOff);
+    assert_eq!(Light::of(&off), Some(Light::Off));
+    assert!(LightMachine::is_done(&off));
+    assert!(!LightMachine::can(&off, &LightEvent::Timer));
+}
+
+#[state_machine(event = LightEvent, machine = Switch)]
+enum SwitchState {
+    On,
+    #[on(LightEvent::Timer => On)]
+    Off,
+}
+
+#[test]
+fn first_state_is_initial_by_default() {
+    let on = Switch::initial();
+    assert_eq!(SwitchState::of(&on), Some(SwitchState::On));
+    assert_eq!(SwitchState::machine().get_states().len(), 2);
+}
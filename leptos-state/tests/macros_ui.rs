@@ -0,0 +1,15 @@
+//! Definitions `#[state_machine]` accepts or rejects at compile time
+
+#![cfg(feature = "macros")]
+
+#[test]
+fn invalid_state_machines_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
+
+#[test]
+fn state_enums_can_derive_part_of_the_required_traits() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/pass/*.rs");
+}
@@ -0,0 +1,15 @@
+use leptos_state::state_machine;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LightEvent {
+    Timer,
+}
+
+#[state_machine(event = LightEvent)]
+enum Light<T> {
+    #[on(LightEvent::Timer => Red)]
+    Green,
+    Red,
+}
+
+fn main() {}
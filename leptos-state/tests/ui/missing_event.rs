@@ -0,0 +1,14 @@
+use leptos_state::state_machine;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct LightContext {
+    cycles: u32,
+}
+
+#[state_machine(context = LightContext)]
+enum Light {
+    Green,
+    Red,
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use leptos_state::state_machine;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LightEvent {
+    Timer,
+}
+
+#[state_machine(event = LightEvent)]
+#[derive(Debug, Clone)]
+enum Light {
+    #[on(LightEvent::Timer => Red)]
+    Green,
+    #[on(LightEvent::Timer => Green)]
+    Red,
+}
+
+fn main() {
+    let light = Light::Green.clone();
+    assert_eq!(light, Light::Green);
+    assert_eq!(Light::ALL, [Light::Green, Light::Red]);
+}
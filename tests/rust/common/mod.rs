@@ -63,6 +63,21 @@ impl StateMachine for TestMachine {
             _ => state.clone(),
         }
     }
+
+    fn can(state: &Self::State, event: &Self::Event) -> bool {
+        matches!(
+            (state, event),
+            (TestState::Idle | TestState::Counting, TestEvent::Increment | TestEvent::Decrement)
+                | (_, TestEvent::Reset)
+        )
+    }
+
+    fn enabled_events(state: &Self::State) -> Vec<Self::Event> {
+        [TestEvent::Increment, TestEvent::Decrement, TestEvent::Reset]
+            .into_iter()
+            .filter(|event| Self::can(state, event))
+            .collect()
+    }
 }
 
 /// Test states
@@ -124,14 +139,14 @@ pub mod utils {
 
     /// Create a test machine state
     pub fn create_test_state(state: TestState, context: TestContext) -> MachineStateImpl<TestContext> {
-        MachineStateImpl {
-            value: match state {
+        MachineStateImpl::new(
+            match state {
                 TestState::Idle => leptos_state::machine::states::StateValue::Simple("idle".to_string()),
                 TestState::Counting => leptos_state::machine::states::StateValue::Simple("counting".to_string()),
                 TestState::Error => leptos_state::machine::states::StateValue::Simple("error".to_string()),
             },
             context,
-        }
+        )
     }
 
     /// Assert state machine transitions
@@ -40,13 +40,13 @@ fn test_basic_machine_creation() {
 
 #[test]
 fn test_machine_state_impl() {
-    let state = MachineStateImpl {
-        value: StateValue::Simple("idle".to_string()),
-        context: TestContext {
+    let state = MachineStateImpl::new(
+        StateValue::Simple("idle".to_string()),
+        TestContext {
             count: 0,
             name: "test".to_string(),
         },
-    };
+    );
     
     assert_eq!(*state.value(), StateValue::Simple("idle".to_string()));
     assert_eq!(state.context().count, 0);
@@ -181,13 +181,13 @@ fn test_machine_state_impl_default() {
 
 #[test]
 fn test_machine_state_impl_clone() {
-    let state = MachineStateImpl {
-        value: StateValue::Simple("running".to_string()),
-        context: TestContext {
+    let state = MachineStateImpl::new(
+        StateValue::Simple("running".to_string()),
+        TestContext {
             count: 42,
             name: "test".to_string(),
         },
-    };
+    );
     
     let cloned = state.clone();
     assert_eq!(cloned.value(), state.value());
@@ -197,29 +197,29 @@ fn test_machine_state_impl_clone() {
 
 #[test]
 fn test_machine_state_impl_partial_eq() {
-    let state1 = MachineStateImpl {
-        value: StateValue::Simple("idle".to_string()),
-        context: TestContext {
+    let state1 = MachineStateImpl::new(
+        StateValue::Simple("idle".to_string()),
+        TestContext {
             count: 0,
             name: "test".to_string(),
         },
-    };
+    );
     
-    let state2 = MachineStateImpl {
-        value: StateValue::Simple("idle".to_string()),
-        context: TestContext {
+    let state2 = MachineStateImpl::new(
+        StateValue::Simple("idle".to_string()),
+        TestContext {
             count: 0,
             name: "test".to_string(),
         },
-    };
+    );
     
-    let state3 = MachineStateImpl {
-        value: StateValue::Simple("running".to_string()),
-        context: TestContext {
+    let state3 = MachineStateImpl::new(
+        StateValue::Simple("running".to_string()),
+        TestContext {
             count: 0,
             name: "test".to_string(),
         },
-    };
+    );
     
     assert_eq!(state1, state2);
     assert_ne!(state1, state3);